@@ -0,0 +1,323 @@
+/// REST API handlers
+///
+/// GET  /rooms                                  rooms list
+/// GET  /rooms/{room}/devices                   devices in the room
+/// GET  /rooms/{room}/devices/{device}          device state
+/// POST /rooms/{room}/devices/{device}/on       turn socket on
+/// POST /rooms/{room}/devices/{device}/off      turn socket off
+/// POST /rooms/{room}/devices/{device}/reset    clear overload trip of socket
+/// GET  /report                                 states of all devices
+/// GET  /report/text                            text report of the home
+/// GET  /ws                                     live events and commands over WebSocket
+/// GET  /metrics                                device and server metrics for Prometheus
+use std::{error::Error, fmt::Display, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use network::{
+    command::{CommandRequest, ResponseType, SocketRequestBuilder},
+    device::{socket_power, socket_state},
+    metrics::{render_servers, Exposition},
+};
+use serde::{Deserialize, Serialize};
+use smart_home::{devices::SocketState, query::DeviceKind, sources::DeviceSource, SmartHomeError};
+
+use crate::{backend::DeviceBinding, ws, HttpGateway};
+
+#[derive(Debug, PartialEq)]
+pub enum ApiError {
+    Home(SmartHomeError),                       // No such room or device in the home
+    NotServed { room: String, device: String }, // Device is not bound to backend
+    WrongKind { room: String, device: String }, // Command is not supported by the device
+    Device(String),                             // Device or transport error
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Home(e) => write!(f, "{}", e),
+            Self::NotServed { room, device } => {
+                write!(f, "Device {} in the room {} is not served", device, room)
+            }
+            Self::WrongKind { room, device } => {
+                write!(f, "Device {} in the room {} is not a socket", device, room)
+            }
+            Self::Device(e) => write!(f, "Device error: {}", e),
+        }
+    }
+}
+
+impl Error for ApiError {}
+
+impl From<SmartHomeError> for ApiError {
+    fn from(value: SmartHomeError) -> Self {
+        Self::Home(value)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Home(_) | Self::NotServed { .. } => StatusCode::NOT_FOUND,
+            Self::WrongKind { .. } => StatusCode::BAD_REQUEST,
+            Self::Device(_) => StatusCode::BAD_GATEWAY,
+        };
+        let body = Json(ErrorView {
+            error: self.to_string(),
+        });
+        (status, body).into_response()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ErrorView {
+    pub error: String,
+}
+
+/// Device state as returned by API
+/// Reading is power consumption for socket and temperature for thermometer
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DeviceView {
+    pub room: String,
+    pub device: String,
+    pub id: String,
+    pub kind: String,
+    pub state: Option<String>,
+    pub reading: Option<f32>,
+    pub error: Option<String>,
+}
+
+pub(crate) type ApiResult<T> = Result<T, ApiError>;
+type Gateway = State<Arc<HttpGateway>>;
+
+pub(crate) fn router(gateway: Arc<HttpGateway>) -> Router {
+    Router::new()
+        .route("/rooms", get(rooms))
+        .route("/rooms/{room}/devices", get(devices))
+        .route("/rooms/{room}/devices/{device}", get(device))
+        .route("/rooms/{room}/devices/{device}/on", post(turn_on))
+        .route("/rooms/{room}/devices/{device}/off", post(turn_off))
+        .route("/rooms/{room}/devices/{device}/reset", post(reset_socket))
+        .route("/report", get(report))
+        .route("/report/text", get(text_report))
+        .route("/ws", get(ws::upgrade))
+        .route("/metrics", get(metrics))
+        .with_state(gateway)
+}
+
+async fn rooms(State(gw): Gateway) -> Json<Vec<String>> {
+    let rooms = gw.home.get_rooms().into_iter().map(String::from).collect();
+    Json(rooms)
+}
+
+async fn devices(State(gw): Gateway, Path(room): Path<String>) -> ApiResult<Json<Vec<String>>> {
+    let devices = gw.home.devices(&room)?;
+    Ok(Json(devices.into_iter().map(String::from).collect()))
+}
+
+async fn device(
+    State(gw): Gateway,
+    Path((room, device)): Path<(String, String)>,
+) -> ApiResult<Json<DeviceView>> {
+    let binding = gw.binding(&room, &device)?;
+    Ok(Json(view(&room, &device, binding).await))
+}
+
+async fn turn_on(
+    State(gw): Gateway,
+    Path((room, device)): Path<(String, String)>,
+) -> ApiResult<Json<DeviceView>> {
+    switch(&gw, &room, &device, SocketState::On).await.map(Json)
+}
+
+async fn turn_off(
+    State(gw): Gateway,
+    Path((room, device)): Path<(String, String)>,
+) -> ApiResult<Json<DeviceView>> {
+    switch(&gw, &room, &device, SocketState::Off)
+        .await
+        .map(Json)
+}
+
+async fn reset_socket(
+    State(gw): Gateway,
+    Path((room, device)): Path<(String, String)>,
+) -> ApiResult<Json<DeviceView>> {
+    reset(&gw, &room, &device).await.map(Json)
+}
+
+async fn report(State(gw): Gateway) -> Json<Vec<DeviceView>> {
+    Json(views(&gw).await)
+}
+
+/// Text report in the same format as SmartHome::create_report
+async fn text_report(State(gw): Gateway) -> ApiResult<String> {
+    let mut source = DeviceSource::new();
+    for v in views(&gw).await.into_iter().filter(|v| v.error.is_none()) {
+        let info = match (v.state, v.reading) {
+            (Some(state), Some(power)) if v.kind == "socket" => {
+                format!("State: {state}, power consumption {power:.1}W")
+            }
+            (_, Some(t)) => format!("Temperature {t:.1}"),
+            _ => continue,
+        };
+        // Device names are unique in the room
+        source.add_device(&v.device, &v.room, info).unwrap();
+    }
+    Ok(gw.home.create_report(&source)?)
+}
+
+/// Value of device gauge, None if the device has no such value
+type Gauge = fn(&DeviceView) -> Option<f64>;
+
+/// Device gauges labelled by home, room and device, followed by server metrics
+async fn metrics(State(gw): Gateway) -> impl IntoResponse {
+    let views = views(&gw).await;
+    let home = gw.home.name();
+    let gauges: [(&str, &str, Gauge); 4] = [
+        (
+            "smart_home_device_temperature_celsius",
+            "Thermometer reading",
+            |v| v.reading.filter(|_| v.kind == "thermometer").map(f64::from),
+        ),
+        (
+            "smart_home_device_power_watts",
+            "Socket power consumption",
+            |v| v.reading.filter(|_| v.kind == "socket").map(f64::from),
+        ),
+        ("smart_home_device_on", "Socket is on", |v| {
+            v.state.as_ref().map(|s| f64::from(u8::from(s == "on")))
+        }),
+        ("smart_home_device_online", "Device responds", |v| {
+            Some(f64::from(u8::from(v.error.is_none())))
+        }),
+    ];
+    let mut exposition = Exposition::new();
+    for (name, help, value) in gauges {
+        exposition.family(name, "gauge", help);
+        for v in &views {
+            if let Some(value) = value(v) {
+                let labels = [("home", home), ("room", &v.room), ("device", &v.device)];
+                exposition.sample(name, &labels, value);
+            }
+        }
+    }
+    render_servers(&mut exposition, &gw.servers);
+    (
+        [("content-type", "text/plain; version=0.0.4")],
+        exposition.finish(),
+    )
+}
+
+/// Views of all devices in the home, sorted by room and device
+pub(crate) async fn views(gw: &HttpGateway) -> Vec<DeviceView> {
+    let mut views = vec![];
+    for room in gw.home.get_rooms() {
+        for device in gw.home.devices(room).unwrap_or_default() {
+            if let Ok(binding) = gw.binding(room, device) {
+                views.push(view(room, device, binding).await);
+            }
+        }
+    }
+    views
+}
+
+/// Switch socket and return its new state
+pub(crate) async fn switch(
+    gw: &HttpGateway,
+    room: &str,
+    device: &str,
+    state: SocketState,
+) -> ApiResult<DeviceView> {
+    socket_command(gw, room, device, |request| match state {
+        SocketState::On => request.turn_on(),
+        SocketState::Off => request.turn_off(),
+    })
+    .await
+}
+
+/// Clear overload trip of socket and return its state
+pub(crate) async fn reset(gw: &HttpGateway, room: &str, device: &str) -> ApiResult<DeviceView> {
+    socket_command(gw, room, device, |request| request.reset()).await
+}
+
+async fn socket_command<F: FnOnce(SocketRequestBuilder) -> CommandRequest>(
+    gw: &HttpGateway,
+    room: &str,
+    device: &str,
+    build: F,
+) -> ApiResult<DeviceView> {
+    let binding = gw.binding(room, device)?;
+    if binding.kind != DeviceKind::Socket {
+        return Err(ApiError::WrongKind {
+            room: room.to_string(),
+            device: device.to_string(),
+        });
+    }
+    let request = build(CommandRequest::builder().socket(&binding.id));
+    let response = binding
+        .backend
+        .execute(request)
+        .await
+        .map_err(|e| ApiError::Device(e.to_string()))?;
+    if let ResponseType::Err(e) = response.response() {
+        return Err(ApiError::Device(e.clone()));
+    }
+    Ok(view(room, device, binding).await)
+}
+
+/// Request device state, errors are reported in the view
+pub(crate) async fn view(room: &str, device: &str, binding: &DeviceBinding) -> DeviceView {
+    let mut view = DeviceView {
+        room: room.to_string(),
+        device: device.to_string(),
+        id: binding.id.clone(),
+        kind: kind_name(binding.kind).to_string(),
+        state: None,
+        reading: None,
+        error: None,
+    };
+    let request = match binding.kind {
+        DeviceKind::Socket => CommandRequest::builder().socket(&binding.id).get_state(),
+        DeviceKind::Thermometer => CommandRequest::builder().therm(&binding.id).get_temp(),
+    };
+    let response = match binding.backend.execute(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            view.error = Some(e.to_string());
+            return view;
+        }
+    };
+    match (binding.kind, response.response()) {
+        (_, ResponseType::Err(e)) => view.error = Some(e.clone()),
+        (DeviceKind::Socket, _) => match socket_state(&response) {
+            Ok(state) => {
+                view.state = Some(state_name(state).to_string());
+                view.reading = socket_power(&response);
+            }
+            Err(e) => view.error = Some(e),
+        },
+        (DeviceKind::Thermometer, ResponseType::Success(t)) => view.reading = t.parse().ok(),
+        (DeviceKind::Thermometer, _) => view.error = Some("Unexpected response".into()),
+    }
+    view
+}
+
+pub(crate) fn kind_name(kind: DeviceKind) -> &'static str {
+    match kind {
+        DeviceKind::Socket => "socket",
+        DeviceKind::Thermometer => "thermometer",
+    }
+}
+
+fn state_name(state: SocketState) -> &'static str {
+    match state {
+        SocketState::On => "on",
+        SocketState::Off => "off",
+    }
+}
@@ -0,0 +1,73 @@
+/// Backends which execute CommandRequest for the gateway:
+/// in-process devices or network devices through async clients
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use network::{
+    command::{CommandRequest, CommandResponse},
+    device::Device,
+    error::{NetworkError, NetworkResult},
+    r#async::{ClientAsync, SharedDevice, TCPClientAsync, UDPClientAsync},
+};
+use smart_home::query::DeviceKind;
+use tokio::{sync::RwLock, time::timeout};
+
+// Network device should answer within this time
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub enum Backend {
+    Local(SharedDevice),
+    Tcp(SocketAddr),
+    Udp(SocketAddr),
+}
+
+impl Backend {
+    /// In-process device
+    pub fn local<D: Device + Send + Sync + 'static>(device: D) -> Self {
+        Self::Local(Arc::new(RwLock::new(device)))
+    }
+
+    /// Send request and wait for response
+    /// New connection is used for each request of network device
+    pub async fn execute(&self, request: CommandRequest) -> NetworkResult<CommandResponse> {
+        match self {
+            Self::Local(device) => Ok(device.write().await.handle(request)),
+            Self::Tcp(addr) => {
+                let client = TCPClientAsync::new(addr).await?;
+                Self::send(client, request).await
+            }
+            Self::Udp(addr) => {
+                let client = UDPClientAsync::new(addr).await?;
+                Self::send(client, request).await
+            }
+        }
+    }
+
+    async fn send<C: ClientAsync>(
+        mut client: C,
+        request: CommandRequest,
+    ) -> NetworkResult<CommandResponse> {
+        timeout(TIMEOUT, async {
+            client.send(request).await?;
+            client.receive().await
+        })
+        .await
+        .map_err(|_| NetworkError::Timeout)?
+    }
+}
+
+/// Device of the home served by the gateway
+pub struct DeviceBinding {
+    pub id: String, // Device id used in CommandRequest
+    pub kind: DeviceKind,
+    pub backend: Backend,
+}
+
+impl DeviceBinding {
+    pub fn new(id: &str, kind: DeviceKind, backend: Backend) -> Self {
+        Self {
+            id: id.to_string(),
+            kind,
+            backend,
+        }
+    }
+}
@@ -0,0 +1,123 @@
+/// HTTP REST gateway for the smart home
+/// Exposes SmartHome topology, device states and commands over HTTP,
+/// requests are forwarded to in-process or network devices
+use std::{collections::HashMap, sync::Arc};
+
+use axum::Router;
+use network::{metrics::ServerMetrics, Result};
+use smart_home::{
+    events::{Event, EventBus},
+    SmartHome, SmartHomeError,
+};
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    sync::broadcast,
+};
+
+mod api;
+mod backend;
+mod ws;
+
+pub use api::{ApiError, DeviceView, ErrorView};
+pub use backend::{Backend, DeviceBinding};
+pub use ws::{EventFilter, EventView, WsMessage, WsRequest};
+
+//   Key = (Room,   Device)
+type Key = (String, String);
+
+// Events not yet sent to slow WebSocket client
+const EVENTS_CAPACITY: usize = 256;
+
+pub struct HttpGateway {
+    home: SmartHome,
+    devices: HashMap<Key, DeviceBinding>,
+    events: broadcast::Sender<Event>,
+    servers: Vec<ServerMetrics>,
+}
+
+impl HttpGateway {
+    pub fn new(home: SmartHome) -> Self {
+        Self {
+            home,
+            devices: HashMap::default(),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            servers: vec![],
+        }
+    }
+
+    /// Serve device of the home with the backend
+    /// Device should exist in the home
+    pub fn bind(
+        &mut self,
+        room: &str,
+        device: &str,
+        binding: DeviceBinding,
+    ) -> std::result::Result<(), SmartHomeError> {
+        if !self.home.devices(room)?.contains(&device) {
+            return Err(SmartHomeError::NoDevice {
+                room: room.to_string(),
+                device: device.to_string(),
+            });
+        }
+        self.devices
+            .insert((room.to_string(), device.to_string()), binding);
+        Ok(())
+    }
+
+    /// Stream events of the bus to WebSocket clients
+    /// Devices and home should publish to the same bus
+    pub fn set_event_bus(&mut self, bus: &EventBus) {
+        let events = self.events.clone();
+        bus.subscribe(move |e| {
+            // No receivers if there are no clients
+            let _ = events.send(e.clone());
+        });
+    }
+
+    /// Export metrics of the server at /metrics along with device gauges
+    pub fn add_server_metrics(&mut self, metrics: ServerMetrics) {
+        self.servers.push(metrics);
+    }
+
+    /// home getter
+    pub fn home(&self) -> &SmartHome {
+        &self.home
+    }
+
+    /// Router with API, could be merged into other axum application
+    pub fn router(self) -> Router {
+        api::router(Arc::new(self))
+    }
+
+    /// Serve API until error
+    pub async fn serve<A: ToSocketAddrs>(self, addr: A) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+
+    /// Room and device served by device with id
+    fn locate(&self, id: &str) -> Option<(String, String)> {
+        self.devices
+            .iter()
+            .find(|(_, b)| b.id == id)
+            .map(|(key, _)| key.clone())
+    }
+
+    fn binding(&self, room: &str, device: &str) -> std::result::Result<&DeviceBinding, ApiError> {
+        // Check home first to report missing room or device
+        if !self.home.devices(room)?.contains(&device) {
+            return Err(SmartHomeError::NoDevice {
+                room: room.to_string(),
+                device: device.to_string(),
+            }
+            .into());
+        }
+        self.devices
+            .get(&(room.to_string(), device.to_string()))
+            .ok_or(ApiError::NotServed {
+                room: room.to_string(),
+                device: device.to_string(),
+            })
+    }
+}
@@ -0,0 +1,103 @@
+/// HTTP gateway server
+/// Usage: http-gateway <config.json>
+///
+/// Config example:
+/// {
+///     "name": "Home",
+///     "listen": "127.0.0.1:8080",
+///     "devices": [
+///         {"room": "Kitchen", "device": "Socket", "id": "s1", "kind": "socket", "address": null},
+///         {"room": "Hall", "device": "Therm", "id": "t1", "kind": "thermometer", "address": "udp://127.0.0.1:8001"}
+///     ]
+/// }
+/// Devices without address are created in-process,
+/// their events are streamed to WebSocket clients
+///
+/// Logs are written to stderr, SMART_HOME_LOG sets the filter (e.g. "debug")
+/// and SMART_HOME_LOG_FORMAT=json switches to JSON lines, see network::logging
+use std::net::SocketAddr;
+
+use http_gateway::{Backend, DeviceBinding, HttpGateway};
+use network::{logging, Result};
+use serde::Deserialize;
+use smart_home::{
+    devices::{Socket, Thermometer},
+    events::EventBus,
+    query::DeviceKind,
+    SmartHome,
+};
+use tracing::info;
+
+#[derive(Deserialize)]
+struct Config {
+    name: String,
+    listen: String,
+    devices: Vec<DeviceConfig>,
+}
+
+#[derive(Deserialize)]
+struct DeviceConfig {
+    room: String,
+    device: String,
+    id: String,
+    kind: String,
+    address: Option<String>,
+}
+
+impl DeviceConfig {
+    fn binding(&self, bus: &EventBus) -> Result<DeviceBinding> {
+        let kind = match self.kind.as_str() {
+            "socket" => DeviceKind::Socket,
+            "thermometer" => DeviceKind::Thermometer,
+            k => return Err(format!("Unknown device kind {k}").into()),
+        };
+        let backend = match (self.address.as_deref(), kind) {
+            (None, DeviceKind::Socket) => {
+                let mut socket = Socket::new(&self.id);
+                socket.set_event_bus(bus.clone());
+                Backend::local(socket)
+            }
+            (None, DeviceKind::Thermometer) => {
+                let mut therm = Thermometer::new(&self.id);
+                therm.set_event_bus(bus.clone());
+                Backend::local(therm)
+            }
+            (Some(a), _) => match a.split_once("://") {
+                Some(("tcp", addr)) => Backend::Tcp(addr.parse::<SocketAddr>()?),
+                Some(("udp", addr)) => Backend::Udp(addr.parse::<SocketAddr>()?),
+                _ => return Err(format!("Wrong device address {a}").into()),
+            },
+        };
+        Ok(DeviceBinding::new(&self.id, kind, backend))
+    }
+}
+
+fn gateway(config: &Config) -> Result<HttpGateway> {
+    let bus = EventBus::new();
+    let mut home = SmartHome::new(&config.name);
+    for d in &config.devices {
+        if !home.get_rooms().contains(&d.room.as_str()) {
+            home.add_room(&d.room)?;
+        }
+        home.add_device(&d.room, &d.device)?;
+    }
+    let mut gateway = HttpGateway::new(home);
+    gateway.set_event_bus(&bus);
+    for d in &config.devices {
+        gateway.bind(&d.room, &d.device, d.binding(&bus)?)?;
+    }
+    Ok(gateway)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    logging::init();
+    let path = std::env::args()
+        .nth(1)
+        .ok_or("Usage: http-gateway <config.json>")?;
+    let config: Config = serde_json::from_slice(&std::fs::read(path)?)?;
+    let gateway = gateway(&config)?;
+    info!(listen = config.listen, "serving HTTP gateway");
+    gateway.serve(&config.listen).await?;
+    Ok(())
+}
@@ -0,0 +1,183 @@
+/// WebSocket stream of live events with commands over the same socket
+///
+/// Client messages:
+///   {"Subscribe": {"rooms": ["Kitchen"], "devices": [], "kinds": ["state", "reading", "home"]}}
+///   {"Command": {"room": "Kitchen", "device": "Socket", "command": "on" | "off" | "reset" | "get"}}
+/// Server messages:
+///   {"Subscribed": filter}, {"Event": event}, {"Device": device state}, {"Error": message}
+///
+/// Empty filter lists match everything, client receives all events until it subscribes
+use std::sync::Arc;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use smart_home::{devices::SocketState, events::Event, power::Budget};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    api::{self, ApiResult, DeviceView},
+    HttpGateway,
+};
+
+/// Events filter of the client
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct EventFilter {
+    pub rooms: Vec<String>,
+    pub devices: Vec<String>,
+    pub kinds: Vec<String>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &EventView) -> bool {
+        let check = |list: &[String], value: Option<&String>| {
+            list.is_empty() || value.is_some_and(|v| list.contains(v))
+        };
+        check(&self.rooms, event.room.as_ref())
+            && check(&self.devices, event.device.as_ref())
+            && check(&self.kinds, Some(&event.kind))
+    }
+}
+
+/// Event with room and device names of the home
+/// Kind is "state", "reading", "power" for trips and budgets or "home" for topology changes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EventView {
+    pub room: Option<String>,
+    pub device: Option<String>,
+    pub kind: String,
+    pub event: Event,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WsRequest {
+    Subscribe(EventFilter),
+    Command {
+        room: String,
+        device: String,
+        command: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum WsMessage {
+    Subscribed(EventFilter),
+    Event(EventView),
+    Device(DeviceView),
+    Error(String),
+}
+
+pub(crate) async fn upgrade(State(gw): State<Arc<HttpGateway>>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| session(socket, gw))
+}
+
+async fn session(mut socket: WebSocket, gw: Arc<HttpGateway>) {
+    let mut events = gw.events.subscribe();
+    let mut filter = EventFilter::default();
+    loop {
+        let reply = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => request(&gw, &mut filter, &text).await,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            event = events.recv() => match event {
+                Ok(event) => match view(&gw, event) {
+                    Some(view) if filter.matches(&view) => WsMessage::Event(view),
+                    _ => continue,
+                },
+                Err(RecvError::Lagged(n)) => WsMessage::Error(format!("{n} events are lost")),
+                Err(RecvError::Closed) => break,
+            },
+        };
+        // Serialization of own types doesn't fail
+        let text = serde_json::to_string(&reply).unwrap();
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn request(gw: &HttpGateway, filter: &mut EventFilter, text: &str) -> WsMessage {
+    match serde_json::from_str(text) {
+        Ok(WsRequest::Subscribe(f)) => {
+            *filter = f;
+            WsMessage::Subscribed(filter.clone())
+        }
+        Ok(WsRequest::Command {
+            room,
+            device,
+            command,
+        }) => match self::command(gw, &room, &device, &command).await {
+            Some(Ok(view)) => WsMessage::Device(view),
+            Some(Err(e)) => WsMessage::Error(e.to_string()),
+            None => WsMessage::Error(format!("Unknown command {command}")),
+        },
+        Err(e) => WsMessage::Error(format!("Wrong request: {e}")),
+    }
+}
+
+async fn command(
+    gw: &HttpGateway,
+    room: &str,
+    device: &str,
+    command: &str,
+) -> Option<ApiResult<DeviceView>> {
+    let result = match command {
+        "on" => api::switch(gw, room, device, SocketState::On).await,
+        "off" => api::switch(gw, room, device, SocketState::Off).await,
+        "reset" => api::reset(gw, room, device).await,
+        "get" => match gw.binding(room, device) {
+            Ok(binding) => Ok(api::view(room, device, binding).await),
+            Err(e) => Err(e),
+        },
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Find room and device names of the event
+/// Device events carry device id, it is resolved with gateway bindings
+fn view(gw: &HttpGateway, event: Event) -> Option<EventView> {
+    let located = |id: &str| gw.locate(id).map(|(r, d)| (Some(r), Some(d)));
+    let (room, device, kind) = match &event {
+        Event::SocketStateChanged { device, .. }
+        | Event::ThermometerStateChanged { device, .. } => {
+            let (room, device) = located(device)?;
+            (room, device, "state")
+        }
+        Event::TemperatureRead { device, .. } => {
+            let (room, device) = located(device)?;
+            (room, device, "reading")
+        }
+        Event::SocketTripped { device, .. } | Event::SocketReset { device } => {
+            let (room, device) = located(device)?;
+            (room, device, "power")
+        }
+        Event::PowerBudgetExceeded { budget, .. } => match budget {
+            Budget::Room(room) => (Some(room.clone()), None, "power"),
+            Budget::Home => (None, None, "power"),
+        },
+        Event::RoomAdded { room }
+        | Event::RoomRemoved { room }
+        | Event::RoomRenamed { to: room, .. } => (Some(room.clone()), None, "home"),
+        Event::DeviceAdded { room, device } | Event::DeviceRemoved { room, device } => {
+            (Some(room.clone()), Some(device.clone()), "home")
+        }
+        Event::DeviceMoved {
+            to_room, to_device, ..
+        } => (Some(to_room.clone()), Some(to_device.clone()), "home"),
+    };
+    Some(EventView {
+        room,
+        device,
+        kind: kind.to_string(),
+        event,
+    })
+}
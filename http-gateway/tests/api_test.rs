@@ -0,0 +1,239 @@
+use http_gateway::{Backend, DeviceBinding, DeviceView, ErrorView, HttpGateway};
+use network::{
+    metrics::ServerMetrics,
+    r#async::{BindAsync, NetworkDeviceAsync, TCPServerAsync},
+    Result,
+};
+use reqwest::{Client, StatusCode};
+use smart_home::{
+    devices::{Socket, Thermometer},
+    query::DeviceKind,
+    SmartHome,
+};
+use tokio::net::TcpListener;
+
+/// Home with local socket and thermometer, network socket and unbound socket
+async fn start() -> Result<String> {
+    let metrics = ServerMetrics::new("tcp");
+    let server = TCPServerAsync::new("127.0.0.1:0")
+        .await?
+        .with_metrics(metrics.clone());
+    let addr = server.local_addr()?;
+    let remote = NetworkDeviceAsync::with_server(Socket::new("s2"), server);
+    tokio::spawn(async move { remote.listen().await });
+
+    let mut home = SmartHome::new("Home");
+    home.add_room("Kitchen")?;
+    home.add_room("Hall")?;
+    home.add_device("Kitchen", "Socket")?;
+    home.add_device("Kitchen", "Therm")?;
+    home.add_device("Hall", "Socket")?;
+    home.add_device("Hall", "Lamp")?;
+
+    let mut gateway = HttpGateway::new(home);
+    gateway.add_server_metrics(metrics);
+    let socket = Backend::local(Socket::new("s1"));
+    gateway.bind(
+        "Kitchen",
+        "Socket",
+        DeviceBinding::new("s1", DeviceKind::Socket, socket),
+    )?;
+    let therm = Backend::local(Thermometer::new("t1"));
+    gateway.bind(
+        "Kitchen",
+        "Therm",
+        DeviceBinding::new("t1", DeviceKind::Thermometer, therm),
+    )?;
+    let remote = Backend::Tcp(addr);
+    gateway.bind(
+        "Hall",
+        "Socket",
+        DeviceBinding::new("s2", DeviceKind::Socket, remote),
+    )?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, gateway.router()).await });
+    Ok(url)
+}
+
+#[tokio::test]
+async fn test_api() -> Result<()> {
+    let url = start().await?;
+    let client = Client::new();
+
+    let rooms: Vec<String> = client
+        .get(format!("{url}/rooms"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(rooms, ["Hall", "Kitchen"]);
+    let devices: Vec<String> = client
+        .get(format!("{url}/rooms/Kitchen/devices"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(devices, ["Socket", "Therm"]);
+
+    // Local socket
+    let view: DeviceView = client
+        .post(format!("{url}/rooms/Kitchen/devices/Socket/on"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(view.state.as_deref(), Some("on"));
+    assert!(view.reading.is_some());
+    // Reset of socket which is not tripped keeps it on
+    let view: DeviceView = client
+        .post(format!("{url}/rooms/Kitchen/devices/Socket/reset"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(view.state.as_deref(), Some("on"));
+
+    // Network socket
+    let view: DeviceView = client
+        .get(format!("{url}/rooms/Hall/devices/Socket"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(
+        (view.id.as_str(), view.state.as_deref()),
+        ("s2", Some("off"))
+    );
+    let view: DeviceView = client
+        .post(format!("{url}/rooms/Hall/devices/Socket/on"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(view.state.as_deref(), Some("on"));
+
+    // Thermometer
+    let view: DeviceView = client
+        .get(format!("{url}/rooms/Kitchen/devices/Therm"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!((view.kind.as_str(), view.state), ("thermometer", None));
+    assert!(view.reading.is_some());
+
+    // Reports
+    let report: Vec<DeviceView> = client
+        .get(format!("{url}/report"))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(report.len(), 3);
+    assert!(report.iter().all(|v| v.error.is_none()));
+    let text = client
+        .get(format!("{url}/report/text"))
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(text.starts_with("Report for Home smart home"));
+    assert!(text.contains("State: on"));
+    assert!(text.contains("Temperature"));
+
+    // Metrics
+    let metrics = client
+        .get(format!("{url}/metrics"))
+        .send()
+        .await?
+        .text()
+        .await?;
+    for line in [
+        r#"smart_home_device_on{home="Home",room="Hall",device="Socket"} 1"#,
+        r#"smart_home_device_online{home="Home",room="Kitchen",device="Therm"} 1"#,
+        r#"smart_home_server_errors_total{server="tcp",type="SocketGetState"} 0"#,
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "{line} is missing");
+    }
+    assert!(metrics.contains(
+        r#"smart_home_device_temperature_celsius{home="Home",room="Kitchen",device="Therm"}"#
+    ));
+    assert!(
+        metrics.contains(r#"smart_home_server_requests_total{server="tcp",type="SocketTurnOn"} 1"#)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_api_errors() -> Result<()> {
+    let url = start_errors().await?;
+    let client = Client::new();
+
+    let status = |path: &str| {
+        let request = client.post(format!("{url}{path}"));
+        async move { request.send().await.map(|r| r.status()) }
+    };
+    assert_eq!(
+        status("/rooms/Garage/devices/Socket/on").await?,
+        StatusCode::NOT_FOUND
+    );
+    // Unbound device
+    assert_eq!(
+        status("/rooms/Hall/devices/Lamp/on").await?,
+        StatusCode::NOT_FOUND
+    );
+    // Thermometer can't be switched
+    assert_eq!(
+        status("/rooms/Kitchen/devices/Therm/off").await?,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        status("/rooms/Kitchen/devices/Therm/reset").await?,
+        StatusCode::BAD_REQUEST
+    );
+    // Network device is down
+    let response = client
+        .post(format!("{url}/rooms/Hall/devices/Socket/on"))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let error: ErrorView = response.json().await?;
+    assert!(error.error.starts_with("Device error"));
+    Ok(())
+}
+
+/// Home with thermometer, unbound device and unreachable network socket
+async fn start_errors() -> Result<String> {
+    let mut home = SmartHome::new("Home");
+    home.add_room("Kitchen")?;
+    home.add_room("Hall")?;
+    home.add_device("Kitchen", "Therm")?;
+    home.add_device("Hall", "Socket")?;
+    home.add_device("Hall", "Lamp")?;
+
+    let mut gateway = HttpGateway::new(home);
+    let therm = Backend::local(Thermometer::new("t1"));
+    gateway.bind(
+        "Kitchen",
+        "Therm",
+        DeviceBinding::new("t1", DeviceKind::Thermometer, therm),
+    )?;
+    // Nobody listens on the port which was free
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let remote = Backend::Tcp(addr);
+    gateway.bind(
+        "Hall",
+        "Socket",
+        DeviceBinding::new("s2", DeviceKind::Socket, remote),
+    )?;
+    // Device should exist in the home
+    let missing = DeviceBinding::new("s3", DeviceKind::Socket, Backend::local(Socket::new("s3")));
+    assert!(gateway.bind("Hall", "Fan", missing).is_err());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, gateway.router()).await });
+    Ok(url)
+}
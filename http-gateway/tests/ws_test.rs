@@ -0,0 +1,120 @@
+use futures_util::{SinkExt, StreamExt};
+use http_gateway::{Backend, DeviceBinding, EventFilter, HttpGateway, WsMessage, WsRequest};
+use network::Result;
+use smart_home::{
+    devices::{Socket, SocketState, Thermometer},
+    events::{Event, EventBus},
+    query::DeviceKind,
+    SmartHome,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn send(ws: &mut Ws, request: WsRequest) -> Result<()> {
+    let text = serde_json::to_string(&request)?;
+    ws.send(Message::text(text)).await?;
+    Ok(())
+}
+
+async fn receive(ws: &mut Ws) -> Result<WsMessage> {
+    loop {
+        match ws.next().await.ok_or("Closed")?? {
+            Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+            _ => continue,
+        }
+    }
+}
+
+/// Home with local socket and thermometer publishing to the bus
+async fn start() -> Result<String> {
+    let bus = EventBus::new();
+    let mut home = SmartHome::new("Home");
+    home.add_room("Kitchen")?;
+    home.add_device("Kitchen", "Socket")?;
+    home.add_device("Kitchen", "Therm")?;
+
+    let mut socket = Socket::new("s1");
+    socket.set_event_bus(bus.clone());
+    let mut therm = Thermometer::new("t1");
+    therm.set_event_bus(bus.clone());
+    let mut gateway = HttpGateway::new(home);
+    gateway.set_event_bus(&bus);
+    let socket = DeviceBinding::new("s1", DeviceKind::Socket, Backend::local(socket));
+    gateway.bind("Kitchen", "Socket", socket)?;
+    let therm = DeviceBinding::new("t1", DeviceKind::Thermometer, Backend::local(therm));
+    gateway.bind("Kitchen", "Therm", therm)?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, gateway.router()).await });
+    Ok(addr.to_string())
+}
+
+#[tokio::test]
+async fn test_ws_events() -> Result<()> {
+    let addr = start().await?;
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws")).await?;
+
+    // Only state changes of the socket
+    let filter = EventFilter {
+        devices: vec!["Socket".into()],
+        kinds: vec!["state".into()],
+        ..Default::default()
+    };
+    send(&mut ws, WsRequest::Subscribe(filter.clone())).await?;
+    assert_eq!(receive(&mut ws).await?, WsMessage::Subscribed(filter));
+
+    // Command over the socket, response is followed by event
+    let command = |device: &str, command: &str| WsRequest::Command {
+        room: "Kitchen".into(),
+        device: device.into(),
+        command: command.into(),
+    };
+    send(&mut ws, command("Socket", "on")).await?;
+    let WsMessage::Device(view) = receive(&mut ws).await? else {
+        panic!("Device state expected");
+    };
+    assert_eq!(view.state.as_deref(), Some("on"));
+    let WsMessage::Event(event) = receive(&mut ws).await? else {
+        panic!("Event expected");
+    };
+    assert_eq!(
+        (event.room.as_deref(), event.device.as_deref()),
+        (Some("Kitchen"), Some("Socket"))
+    );
+    assert_eq!(
+        event.event,
+        Event::SocketStateChanged {
+            device: "s1".into(),
+            state: SocketState::On
+        }
+    );
+
+    // Temperature reading is filtered out
+    send(&mut ws, command("Therm", "get")).await?;
+    let WsMessage::Device(view) = receive(&mut ws).await? else {
+        panic!("Device state expected");
+    };
+    assert!(view.reading.is_some());
+    // Thermometer can't be switched
+    send(&mut ws, command("Therm", "off")).await?;
+    assert!(matches!(receive(&mut ws).await?, WsMessage::Error(_)));
+
+    // Change from HTTP API is streamed too
+    let url = format!("http://{addr}/rooms/Kitchen/devices/Socket/off");
+    reqwest::Client::new().post(url).send().await?;
+    let WsMessage::Event(event) = receive(&mut ws).await? else {
+        panic!("Event expected");
+    };
+    assert_eq!(event.kind, "state");
+    assert_eq!(
+        event.event,
+        Event::SocketStateChanged {
+            device: "s1".into(),
+            state: SocketState::Off
+        }
+    );
+    Ok(())
+}
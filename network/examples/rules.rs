@@ -0,0 +1,49 @@
+use network::{
+    command::CommandRequest,
+    gateway::Gateway,
+    rules::{Condition, Rule, RulesEngine},
+    sync::{NetworkDevice, RemoteDevice, UDPClient, UDPServer},
+    Result,
+};
+use smart_home::devices::*;
+use std::{thread, time::Duration};
+use tracing::{error, info};
+
+fn run() -> Result<()> {
+    // Thermometer is available through the network
+    let therm1 = Thermometer::new("t1000");
+    let therm1_udp: NetworkDevice<UDPServer> = NetworkDevice::new(therm1, "127.0.0.1:8001")?;
+    thread::spawn(move || therm1_udp.listen());
+
+    // Local socket and remote thermometer behind one gateway
+    let mut gateway = Gateway::new();
+    gateway.add_device("s1000", Socket::new("s1000"));
+    gateway.add_device(
+        "t1000",
+        RemoteDevice::new(UDPClient::new("127.0.0.1:8001")?),
+    );
+
+    let mut engine = RulesEngine::new();
+    engine.add_rule(
+        Rule::new("heating", Condition::temperature_below("t1000", 22.5))
+            .then(CommandRequest::builder().socket("s1000").turn_on())
+            .release(CommandRequest::builder().socket("s1000").turn_off())
+            .hysteresis(0.5),
+    )?;
+
+    for _ in 0..5 {
+        for firing in engine.evaluate(&mut gateway) {
+            info!(rule = firing.rule, request = ?firing.request, "rule fired");
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+
+    Ok(())
+}
+
+fn main() {
+    network::logging::init();
+    if let Err(e) = run() {
+        error!("{e}");
+    }
+}
@@ -5,12 +5,13 @@ use network::{
 };
 use smart_home::devices::*;
 use std::{thread, time::Duration};
+use tracing::{error, info};
 
 /// Send command with client, get and print response
 fn send<C: Client>(client: &mut C, command: CommandRequest) -> Result<()> {
     client.send(command)?;
     let response = client.receive();
-    println!("Response {response:?}");
+    info!(?response, "received response");
     Ok(())
 }
 
@@ -35,7 +36,8 @@ fn run() -> Result<()> {
 }
 
 fn main() {
+    network::logging::init();
     if let Err(e) = run() {
-        println!("Error {e}");
+        error!("{e}");
     }
 }
@@ -6,12 +6,13 @@ use network::{
 use smart_home::devices::*;
 use std::time::Duration;
 use tokio::{runtime::Runtime, time::sleep};
+use tracing::{error, info};
 
 /// Send command with client, get and print response
 async fn send<C: ClientAsync>(client: &mut C, command: CommandRequest) -> Result<()> {
     client.send(command).await?;
     let response = client.receive().await;
-    println!("Response {response:?}");
+    info!(?response, "received response");
     Ok(())
 }
 
@@ -38,8 +39,9 @@ async fn run() -> Result<()> {
 }
 
 fn main() {
+    network::logging::init();
     let result = Runtime::new().unwrap().block_on(run());
     if let Err(e) = result {
-        println!("Error {e}");
+        error!("{e}");
     }
 }
@@ -0,0 +1,340 @@
+/// Access control lists for device commands
+/// Rules are checked in order, the first rule matching peer, device and request type
+/// allows or denies the request, default action is used if no rule matches
+/// Rejected commands are recorded in audit trail
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::Display,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    command::{CommandRequest, CommandResponse, RequestType, ResponseType},
+    device::{Device, Peer},
+    Result,
+};
+
+/// Number of rejections kept by audit trail
+pub const AUDIT_CAPACITY: usize = 1000;
+
+#[derive(Debug, PartialEq)]
+pub enum AclError {
+    Denied { device: String, request: String }, // Request is denied by rules
+}
+
+impl Display for AclError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Denied { device, request } => {
+                write!(f, "Forbidden: {} is not allowed for {}", request, device)
+            }
+        }
+    }
+}
+
+impl Error for AclError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+/// Rule fields which are not set match anything
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AclRule {
+    pub action: Action,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>, // Authenticated client, e.g. key id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<IpAddr>, // Source address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>, // Request id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<String>, // Request type name, e.g. "SocketTurnOff"
+}
+
+impl AclRule {
+    pub fn allow() -> Self {
+        Self::new(Action::Allow)
+    }
+
+    pub fn deny() -> Self {
+        Self::new(Action::Deny)
+    }
+
+    fn new(action: Action) -> Self {
+        Self {
+            action,
+            identity: None,
+            address: None,
+            device: None,
+            request: None,
+        }
+    }
+
+    pub fn identity(mut self, identity: &str) -> Self {
+        self.identity = Some(identity.to_string());
+        self
+    }
+
+    pub fn address(mut self, address: IpAddr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    pub fn device(mut self, device: &str) -> Self {
+        self.device = Some(device.to_string());
+        self
+    }
+
+    pub fn request(mut self, request: &str) -> Self {
+        self.request = Some(request.to_string());
+        self
+    }
+
+    fn matches(&self, peer: &Peer, request: &CommandRequest) -> bool {
+        let matched = |rule: &Option<String>, value: &str| rule.as_ref().is_none_or(|r| r == value);
+        self.identity
+            .as_ref()
+            .is_none_or(|i| peer.identity.as_ref() == Some(i))
+            && self.address.is_none_or(|a| peer.addr == Some(a))
+            && matched(&self.device, request.id())
+            && matched(&self.request, request.req_type().name())
+    }
+}
+
+/// Ordered rules with default action, could be loaded from JSON:
+/// {"default": "deny", "rules": [{"action": "allow", "identity": "guest", "request": "ThermGetTemp"}]}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AccessList {
+    default: Action,
+    #[serde(default)]
+    rules: Vec<AclRule>,
+}
+
+impl AccessList {
+    pub fn new(default: Action) -> Self {
+        Self {
+            default,
+            rules: vec![],
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Append rule, rules added earlier take precedence
+    pub fn rule(mut self, rule: AclRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Action for single command, batch commands should be checked one by one
+    pub fn check(&self, peer: &Peer, request: &CommandRequest) -> Action {
+        self.rules
+            .iter()
+            .find(|r| r.matches(peer, request))
+            .map_or(self.default, |r| r.action)
+    }
+}
+
+/// Rejected command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Rejection {
+    pub time: DateTime<Utc>,
+    pub peer: Peer,
+    pub device: String,
+    pub request: String,
+}
+
+/// Shared list of the latest rejections, cloned handle sees the same entries
+#[derive(Clone, Default)]
+pub struct AuditTrail {
+    entries: Arc<Mutex<VecDeque<Rejection>>>,
+}
+
+impl AuditTrail {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejections from the oldest to the latest
+    pub fn entries(&self) -> Vec<Rejection> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn record(&self, rejection: Rejection) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == AUDIT_CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(rejection);
+    }
+}
+
+/// Device (e.g. Gateway) which executes only commands allowed for the peer
+/// Batch is denied as a whole if any command is denied
+pub struct Acl<D: Device> {
+    inner: D,
+    access: AccessList,
+    audit: AuditTrail,
+}
+
+impl<D: Device> Acl<D> {
+    pub fn new(inner: D, access: AccessList) -> Self {
+        Self {
+            inner,
+            access,
+            audit: AuditTrail::new(),
+        }
+    }
+
+    /// Record rejections in the existing trail, e.g. shared by several devices
+    pub fn with_audit(mut self, audit: AuditTrail) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    pub fn audit(&self) -> AuditTrail {
+        self.audit.clone()
+    }
+
+    fn denied(&self, peer: &Peer, request: &CommandRequest) -> Vec<Rejection> {
+        let commands = match request.req_type() {
+            RequestType::Batch { commands, .. } => commands.iter().collect(),
+            _ => vec![request],
+        };
+        commands
+            .into_iter()
+            .filter(|c| self.access.check(peer, c) == Action::Deny)
+            .map(|c| Rejection {
+                time: Utc::now(),
+                peer: peer.clone(),
+                device: c.id().to_string(),
+                request: c.req_type().name().to_string(),
+            })
+            .collect()
+    }
+}
+
+impl<D: Device> Device for Acl<D> {
+    fn process(&mut self, request: CommandRequest) -> CommandResponse {
+        self.handle_from(&Peer::default(), request)
+    }
+
+    fn handle(&mut self, request: CommandRequest) -> CommandResponse {
+        self.handle_from(&Peer::default(), request)
+    }
+
+    fn handle_from(&mut self, peer: &Peer, request: CommandRequest) -> CommandResponse {
+        let denied = self.denied(peer, &request);
+        let Some(first) = denied.first() else {
+            return self.inner.handle_from(peer, request);
+        };
+        let error = AclError::Denied {
+            device: first.device.clone(),
+            request: first.request.clone(),
+        };
+        for rejection in denied {
+            self.audit.record(rejection);
+        }
+        CommandResponse::new(request.id(), ResponseType::Err(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smart_home::devices::{Socket, Thermometer};
+
+    use super::*;
+    use crate::gateway::Gateway;
+
+    fn gateway() -> Acl<Gateway> {
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        gateway.add_device("t1", Thermometer::new("t1"));
+        let access = AccessList::new(Action::Deny)
+            .rule(AclRule::allow().identity("admin"))
+            .rule(AclRule::allow().identity("guest").request("ThermGetTemp"))
+            .rule(AclRule::allow().identity("guest").request("SocketGetState"))
+            .rule(AclRule::allow().address([127, 0, 0, 1].into()).device("s1"));
+        Acl::new(gateway, access)
+    }
+
+    fn success(response: &CommandResponse) -> bool {
+        matches!(response.response(), ResponseType::Success(_))
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut acl = gateway();
+        let guest = Peer::default().with_identity("guest");
+        let admin = Peer::default().with_identity("admin");
+        let local = Peer::new([127, 0, 0, 1].into());
+
+        let get_temp = || CommandRequest::builder().therm("t1").get_temp();
+        let turn_off = || CommandRequest::builder().socket("s1").turn_off();
+        assert!(success(&acl.handle_from(&guest, get_temp())));
+        assert!(success(&acl.handle_from(&admin, turn_off())));
+        assert!(success(&acl.handle_from(&local, turn_off())));
+        assert!(!success(&acl.handle_from(&local, get_temp())));
+        assert_eq!(
+            acl.handle_from(&guest, turn_off()).response(),
+            &ResponseType::Err(
+                AclError::Denied {
+                    device: "s1".into(),
+                    request: "SocketTurnOff".into()
+                }
+                .to_string()
+            )
+        );
+        // Peer is unknown without server
+        assert!(!success(&acl.process(get_temp())));
+
+        let rejections = acl.audit().entries();
+        assert_eq!(rejections.len(), 3);
+        assert_eq!(rejections[1].peer, guest);
+        assert_eq!(rejections[1].request, "SocketTurnOff");
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut acl = gateway();
+        let guest = Peer::default().with_identity("guest");
+        let batch = CommandRequest::builder()
+            .batch("gw")
+            .command(CommandRequest::builder().therm("t1").get_temp())
+            .command(CommandRequest::builder().socket("s1").turn_on())
+            .build();
+        let response = acl.handle_from(&guest, batch.clone());
+        assert!(matches!(response.response(), ResponseType::Err(_)));
+        assert_eq!(acl.audit().entries()[0].device, "s1");
+
+        let admin = Peer::default().with_identity("admin");
+        let response = acl.handle_from(&admin, batch);
+        assert!(matches!(response.response(), ResponseType::Batch(_)));
+    }
+
+    #[test]
+    fn test_from_json() {
+        let json = r#"{"default": "allow", "rules": [
+            {"action": "deny", "address": "10.0.0.1"},
+            {"action": "deny", "device": "s1", "request": "SocketTurnOff"}
+        ]}"#;
+        let access = AccessList::from_json(json).unwrap();
+        let request = CommandRequest::builder().socket("s1").turn_off();
+        assert_eq!(access.check(&Peer::default(), &request), Action::Deny);
+        let request = CommandRequest::builder().socket("s1").turn_on();
+        assert_eq!(access.check(&Peer::default(), &request), Action::Allow);
+        let peer = Peer::new([10, 0, 0, 1].into());
+        assert_eq!(access.check(&peer, &request), Action::Deny);
+    }
+}
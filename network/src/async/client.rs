@@ -1,14 +1,14 @@
 /// Module provides Client trait and Clients for TCP and UDP protocols
 use std::net::{SocketAddr, ToSocketAddrs};
 
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpSocket, TcpStream, UdpSocket},
-};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+
+use tracing::debug;
 
 use crate::{
     command::{CommandRequest, CommandResponse},
-    Result, BUFLEN,
+    error::{NetworkError, NetworkResult},
+    frame, hooks, BUFLEN,
 };
 
 /// Client which unite TCP and UDP sockets
@@ -16,8 +16,10 @@ pub trait ClientAsync {
     fn send(
         &mut self,
         request: CommandRequest,
-    ) -> impl std::future::Future<Output = Result<()>> + Send;
-    fn receive(&mut self) -> impl std::future::Future<Output = Result<CommandResponse>> + Send;
+    ) -> impl std::future::Future<Output = NetworkResult<()>> + Send;
+    fn receive(
+        &mut self,
+    ) -> impl std::future::Future<Output = NetworkResult<CommandResponse>> + Send;
 }
 
 pub struct TCPClientAsync {
@@ -25,7 +27,7 @@ pub struct TCPClientAsync {
 }
 
 impl TCPClientAsync {
-    pub async fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+    pub async fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
         let socket = TcpSocket::new_v4()?;
         let addr = get_sock_addr(addr)?;
         let stream = socket.connect(addr).await?;
@@ -34,16 +36,14 @@ impl TCPClientAsync {
 }
 
 impl ClientAsync for TCPClientAsync {
-    async fn send(&mut self, request: CommandRequest) -> Result<()> {
-        let buf = serde_json::to_vec(&request)?;
-        self.stream.write_all(&buf).await?;
-        Ok(())
+    async fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
+        frame::write_async(&mut self.stream, &request).await
     }
 
-    async fn receive(&mut self) -> Result<CommandResponse> {
-        let mut buf = vec![0; BUFLEN];
-        let size = self.stream.read(&mut buf).await?;
-        let resp: CommandResponse = serde_json::from_slice(&buf[0..size])?;
+    async fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let resp: CommandResponse = frame::read_async(&mut self.stream).await?;
+        debug!(device = resp.id(), "received response");
         Ok(resp)
     }
 }
@@ -52,7 +52,7 @@ pub struct UDPClientAsync {
 }
 
 impl UDPClientAsync {
-    pub async fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+    pub async fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
         let socket = UdpSocket::bind("0.0.0.0:0").await?;
         let addr = get_sock_addr(addr)?;
         socket.connect(addr).await?;
@@ -61,22 +61,24 @@ impl UDPClientAsync {
 }
 
 impl ClientAsync for UDPClientAsync {
-    async fn send(&mut self, request: CommandRequest) -> Result<()> {
+    async fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
         let buf = serde_json::to_vec(&request)?;
         self.socket.send(&buf).await?;
         Ok(())
     }
 
-    async fn receive(&mut self) -> Result<CommandResponse> {
+    async fn receive(&mut self) -> NetworkResult<CommandResponse> {
         let mut buf = vec![0; BUFLEN];
         let size = self.socket.recv(&mut buf).await?;
-        let resp: CommandResponse = serde_json::from_slice(&buf[0..size])?;
+        let resp = CommandResponse::response_from(&buf[0..size])?;
+        debug!(device = resp.id(), "received response");
         Ok(resp)
     }
 }
 
-fn get_sock_addr<A: ToSocketAddrs>(addr: A) -> Result<SocketAddr> {
+fn get_sock_addr<A: ToSocketAddrs>(addr: A) -> NetworkResult<SocketAddr> {
     addr.to_socket_addrs()?
         .next()
-        .ok_or("Error converting to socket addr".into())
+        .ok_or_else(|| NetworkError::Address("Error converting to socket addr".into()))
 }
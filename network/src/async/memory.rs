@@ -0,0 +1,168 @@
+/// Async in-memory transport over channels, no ports are used
+/// Requests and responses are serialized as with network transports,
+/// so protocol and device logic could be tested hermetically
+use std::collections::HashMap;
+
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    Mutex,
+};
+
+use crate::{
+    command::{CommandRequest, CommandResponse},
+    device::Device,
+    error::{NetworkError, NetworkResult},
+    r#async::{ClientAsync, NetworkDeviceAsync, ServerAsync, SharedDevice},
+};
+
+// Request with sender for the response
+type Envelope = (Vec<u8>, UnboundedSender<Vec<u8>>);
+
+/// Listener stops when all connectors and clients are dropped
+pub struct MemoryServerAsync {
+    incoming: Mutex<UnboundedReceiver<Envelope>>,
+}
+
+impl MemoryServerAsync {
+    /// Server and connector which creates clients
+    pub fn new() -> (Self, MemoryConnectorAsync) {
+        let (tx, incoming) = unbounded_channel();
+        let server = Self {
+            incoming: Mutex::new(incoming),
+        };
+        (server, MemoryConnectorAsync { tx })
+    }
+}
+
+impl ServerAsync for MemoryServerAsync {
+    async fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        let mut incoming = self.incoming.lock().await;
+        while let Some((request, reply)) = incoming.recv().await {
+            let request = CommandRequest::request_from(&request)?;
+            let response = device.write().await.handle(request);
+            // Client could be dropped before response
+            let _ = reply.send(response.into());
+        }
+        Ok(())
+    }
+}
+
+/// Address of the server, clones connect to the same server
+#[derive(Clone)]
+pub struct MemoryConnectorAsync {
+    tx: UnboundedSender<Envelope>,
+}
+
+impl MemoryConnectorAsync {
+    pub fn connect(&self) -> MemoryClientAsync {
+        let (reply, responses) = unbounded_channel();
+        MemoryClientAsync {
+            server: self.tx.clone(),
+            reply,
+            responses,
+        }
+    }
+}
+
+pub struct MemoryClientAsync {
+    server: UnboundedSender<Envelope>,
+    reply: UnboundedSender<Vec<u8>>,
+    responses: UnboundedReceiver<Vec<u8>>,
+}
+
+impl ClientAsync for MemoryClientAsync {
+    async fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        let buf = serde_json::to_vec(&request)?;
+        self.server
+            .send((buf, self.reply.clone()))
+            .map_err(|_| NetworkError::Closed)?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let buf = self.responses.recv().await.ok_or(NetworkError::Closed)?;
+        let resp: CommandResponse = serde_json::from_slice(&buf)?;
+        Ok(resp)
+    }
+}
+
+/// Test harness: devices served in background tasks over in-memory transport
+/// Tasks stop when harness and its clients are dropped
+#[derive(Default)]
+pub struct HarnessAsync {
+    devices: HashMap<String, MemoryConnectorAsync>,
+}
+
+impl HarnessAsync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve device with the id, should be called within tokio runtime
+    pub fn spawn<D: Device + Send + Sync + 'static>(&mut self, id: &str, device: D) {
+        let (server, connector) = MemoryServerAsync::new();
+        let device = NetworkDeviceAsync::with_server(device, server);
+        tokio::spawn(async move { device.listen().await });
+        self.devices.insert(id.to_string(), connector);
+    }
+
+    pub fn client(&self, id: &str) -> NetworkResult<MemoryClientAsync> {
+        let connector = self
+            .devices
+            .get(id)
+            .ok_or_else(|| NetworkError::Address(format!("No such device {id}")))?;
+        Ok(connector.connect())
+    }
+
+    /// Send request to the device with request id and wait for response
+    pub async fn request(&self, request: CommandRequest) -> NetworkResult<CommandResponse> {
+        let mut client = self.client(request.id())?;
+        client.send(request).await?;
+        client.receive().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smart_home::devices::{Socket, Thermometer};
+
+    use super::*;
+    use crate::command::ResponseType;
+
+    #[tokio::test]
+    async fn test_memory_transport_async() {
+        let (server, connector) = MemoryServerAsync::new();
+        let device = NetworkDeviceAsync::with_server(Socket::new("s1"), server);
+        let t = tokio::spawn(async move { device.listen().await });
+
+        let mut client = connector.connect();
+        client
+            .send(CommandRequest::builder().socket("s1").turn_on())
+            .await
+            .unwrap();
+        let response = client.receive().await.unwrap();
+        assert_eq!(response.response(), &ResponseType::Success("".into()));
+
+        // Listener stops without connections
+        drop((client, connector));
+        assert!(t.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_harness_async() {
+        let mut harness = HarnessAsync::new();
+        harness.spawn("s1", Socket::new("s1"));
+        harness.spawn("t1", Thermometer::new("t1"));
+
+        let response = harness
+            .request(CommandRequest::builder().therm("t1").get_temp())
+            .await
+            .unwrap();
+        assert!(matches!(response.response(), ResponseType::Success(_)));
+        let response = harness
+            .request(CommandRequest::builder().socket("t1").get_state())
+            .await
+            .unwrap();
+        assert!(matches!(response.response(), ResponseType::Err(_)));
+    }
+}
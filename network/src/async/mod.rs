@@ -1,7 +1,17 @@
 pub mod client;
+pub mod memory;
 mod network_device;
 pub mod server;
+pub mod tls;
+#[cfg(unix)]
+pub mod unix;
 
 pub use client::{ClientAsync, TCPClientAsync, UDPClientAsync};
+pub use memory::{HarnessAsync, MemoryClientAsync, MemoryConnectorAsync, MemoryServerAsync};
 pub use network_device::NetworkDeviceAsync;
-pub use server::{ServerAsync, SharedDevice, TCPServerAsync, UDPServerAsync};
+pub use server::{BindAsync, ServerAsync, SharedDevice, TCPServerAsync, UDPServerAsync};
+pub use tls::{TLSClientAsync, TLSServerAsync};
+#[cfg(unix)]
+pub use unix::{
+    UnixClientAsync, UnixDatagramClientAsync, UnixDatagramServerAsync, UnixServerAsync,
+};
@@ -4,29 +4,36 @@
 use std::sync::Arc;
 use tokio::{net::ToSocketAddrs, sync::RwLock};
 
-use crate::{device::Device, r#async::SharedDevice, Result};
+use crate::{device::Device, error::NetworkResult, r#async::SharedDevice};
 
-use super::ServerAsync;
+use super::{BindAsync, ServerAsync};
 
 pub struct NetworkDeviceAsync<T: ServerAsync> {
     transport: T,
     device: SharedDevice,
 }
 
-impl<T: ServerAsync> NetworkDeviceAsync<T> {
+impl<T: ServerAsync + BindAsync> NetworkDeviceAsync<T> {
     pub async fn new<A: ToSocketAddrs + Send, D: Device + Send + Sync + 'static>(
         device: D,
         addr: A,
-    ) -> Result<Self> {
+    ) -> NetworkResult<Self> {
         let listener = T::new(addr).await?;
+        Ok(Self::with_server(device, listener))
+    }
+}
+
+impl<T: ServerAsync> NetworkDeviceAsync<T> {
+    /// Device with already created server, e.g. bound to Unix socket
+    pub fn with_server<D: Device + Send + Sync + 'static>(device: D, server: T) -> Self {
         let device = Arc::new(RwLock::new(device)) as SharedDevice;
-        Ok(Self {
-            transport: listener,
+        Self {
+            transport: server,
             device,
-        })
+        }
     }
 
-    pub async fn listen(&self) -> Result<()> {
+    pub async fn listen(&self) -> NetworkResult<()> {
         self.transport.listen(self.device.clone()).await
     }
 }
@@ -1,74 +1,136 @@
 /// Provides Transport trait and UDP and TCP types
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Instant};
 
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, ToSocketAddrs, UdpSocket},
     sync::RwLock,
 };
 
+use tracing::{debug, info_span, warn, Instrument};
+
 use crate::{
-    command::{CommandRequest, CommandResponse},
-    device::Device,
-    Result, BUFLEN,
+    command::{CommandRequest, CommandResponse, Rejected},
+    device::{Device, Peer},
+    error::{NetworkError, NetworkResult},
+    frame,
+    hooks::{self, Hooks},
+    limit::{RateLimiter, Verdict},
+    metrics::ServerMetrics,
+    BUFLEN,
 };
 
 pub type SharedDevice = Arc<RwLock<dyn Device + Send + Sync>>;
 
-/// Clue for UDP and TCP sockets
+/// Clue for UDP, TCP and Unix sockets
 /// Each socket could receive CommandRequest, redirect it to NetworkDevice
 /// and send CommandResponse back
-pub trait ServerAsync: Sized {
+pub trait ServerAsync {
+    fn listen(
+        &self,
+        device: SharedDevice,
+    ) -> impl std::future::Future<Output = NetworkResult<()>> + Send;
+}
+
+/// Server bound to IP address
+pub trait BindAsync: Sized {
     fn new<A: ToSocketAddrs + Send>(
         addr: A,
-    ) -> impl std::future::Future<Output = Result<Self>> + Send;
-    fn listen(&self, device: SharedDevice) -> impl std::future::Future<Output = Result<()>> + Send;
+    ) -> impl std::future::Future<Output = NetworkResult<Self>> + Send;
 }
 
 pub struct TCPServerAsync {
     listener: TcpListener,
+    hooks: Hooks,
 }
 
 impl TCPServerAsync {
+    /// Bound address, e.g. when bound to port 0
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Limit requests of clients
+    pub fn with_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.hooks.limiter = Some(limiter);
+        self
+    }
+
+    /// Count requests, errors and latency
+    pub fn with_metrics(mut self, metrics: ServerMetrics) -> Self {
+        self.hooks.metrics = Some(metrics);
+        self
+    }
+
     /// Handle "one time" connection
-    async fn handle(mut con: TcpStream, device: SharedDevice) -> Result<()> {
-        // receive CommandRequest
-        while let Ok(request) = Self::receive(&mut con).await {
-            // obtain NetworkDevice
-            let mut device = device.write().await;
-            // process CommandRequest
-            let resp = device.process(request);
-            // send CommandResponse back
-            Self::send(&mut con, resp).await?;
+    /// Any stream could be handled, e.g. Unix socket
+    /// Connection is closed if request over the limit should be dropped
+    pub(super) async fn handle<S: AsyncRead + AsyncWrite + Unpin>(
+        mut con: S,
+        peer: Peer,
+        device: SharedDevice,
+        hooks: &Hooks,
+    ) -> NetworkResult<()> {
+        let _connection = hooks.connect();
+        let span = info_span!("connection", %peer);
+        async move {
+            debug!("accepted connection");
+            // receive CommandRequest
+            while let Ok(request) = Self::receive(&mut con).await {
+                let started = Instant::now();
+                let kind = request.req_type().name();
+                debug!(device = request.id(), kind, "received request");
+                let resp = match hooks.check(&peer) {
+                    Verdict::Allow => {
+                        // obtain NetworkDevice
+                        let mut device = device.write().await;
+                        // process CommandRequest
+                        hooks::span(&request).in_scope(|| device.handle_from(&peer, request))
+                    }
+                    Verdict::Drop => break,
+                    Verdict::Reject(e) => {
+                        CommandResponse::rejected(request.id(), Rejected::Limit(e))
+                    }
+                };
+                hooks.observe(kind, started, &resp);
+                // send CommandResponse back
+                Self::send(&mut con, resp).await?;
+            }
+            debug!("connection closed");
+            Ok(())
         }
-        Ok(())
+        .instrument(span)
+        .await
     }
 
-    /// Receive CommandRequest from tcp stream
-    /// receiver buffer is limited with BUFLEN
-    async fn receive(con: &mut TcpStream) -> Result<CommandRequest> {
-        let mut buf = vec![0u8; BUFLEN];
-        let size = con.read(&mut buf).await?;
-        let req = CommandRequest::request_from(&buf[0..size])?;
-        Ok(req)
+    /// Receive framed CommandRequest from the stream
+    async fn receive<S: AsyncRead + Unpin>(con: &mut S) -> NetworkResult<CommandRequest> {
+        frame::read_async(con).await
     }
 
-    /// Send CommandResponse back
-    async fn send(con: &mut TcpStream, resp: CommandResponse) -> Result<()> {
-        let buf: Vec<u8> = resp.into();
-        con.write_all(&buf).await?;
-        Ok(())
+    /// Send framed CommandResponse back
+    async fn send<S: AsyncWrite + Unpin>(con: &mut S, resp: CommandResponse) -> NetworkResult<()> {
+        frame::write_async(con, &resp).await
     }
 }
 
-impl ServerAsync for TCPServerAsync {
-    async fn new<A: ToSocketAddrs + Send>(addr: A) -> Result<Self> {
+impl BindAsync for TCPServerAsync {
+    async fn new<A: ToSocketAddrs + Send>(addr: A) -> NetworkResult<Self> {
         let listener = TcpListener::bind(addr).await?;
-        Ok(Self { listener })
+        Ok(Self {
+            listener,
+            hooks: Hooks::default(),
+        })
     }
-    async fn listen(&self, device: SharedDevice) -> Result<()> {
-        while let Ok((stream, _)) = self.listener.accept().await {
-            Self::handle(stream, device.clone()).await?;
+}
+
+impl ServerAsync for TCPServerAsync {
+    async fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        while let Ok((stream, addr)) = self.listener.accept().await {
+            let peer = Peer::new(addr.ip());
+            if let Err(e) = Self::handle(stream, peer, device.clone(), &self.hooks).await {
+                warn!(%addr, error = %e, "connection failed");
+            }
         }
         Ok(())
     }
@@ -76,39 +138,93 @@ impl ServerAsync for TCPServerAsync {
 
 pub struct UDPServerAsync {
     socket: UdpSocket,
+    hooks: Hooks,
 }
 
 impl UDPServerAsync {
+    /// Bound address, e.g. when bound to port 0
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Limit requests of clients
+    pub fn with_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.hooks.limiter = Some(limiter);
+        self
+    }
+
+    /// Count requests, errors and latency
+    pub fn with_metrics(mut self, metrics: ServerMetrics) -> Self {
+        self.hooks.metrics = Some(metrics);
+        self
+    }
+
     // Receive CommandRequest from socket
-    async fn receive(&self) -> Result<(SocketAddr, CommandRequest)> {
+    // Malformed datagram is skipped, as well as error of previous send
+    // to unreachable client, which is reported on some platforms
+    async fn receive(&self) -> NetworkResult<Option<(SocketAddr, CommandRequest)>> {
         let mut buf = vec![0u8; BUFLEN];
-        let (size, addr) = self.socket.recv_from(&mut buf).await?;
-        let req = CommandRequest::request_from(&buf[0..size])?;
-        Ok((addr, req))
+        let (size, addr) = match self.socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => match NetworkError::from(e) {
+                NetworkError::ConnectionRefused | NetworkError::Closed => return Ok(None),
+                e => return Err(e),
+            },
+        };
+        match CommandRequest::request_from(&buf[0..size]) {
+            Ok(req) => Ok(Some((addr, req))),
+            Err(e) => {
+                debug!(%addr, error = %e, "malformed request is skipped");
+                Ok(None)
+            }
+        }
     }
 
     /// Send CommandResponse to addr
-    async fn send<A: ToSocketAddrs>(&self, resp: CommandResponse, addr: A) -> Result<()> {
+    async fn send<A: ToSocketAddrs>(&self, resp: CommandResponse, addr: A) -> NetworkResult<()> {
         let buf: Vec<u8> = resp.into();
         self.socket.send_to(&buf, addr).await?;
         Ok(())
     }
 
-    async fn handle(&self, device: SharedDevice) -> Result<()> {
-        let (addr, request) = self.receive().await?;
-        let mut device = device.write().await;
-        let response = device.process(request);
-        self.send(response, addr).await?;
+    /// Requests over the limit are checked before device is locked
+    /// Only failure of the socket is returned, failed datagrams are skipped
+    async fn handle(&self, device: SharedDevice) -> NetworkResult<()> {
+        let Some((addr, request)) = self.receive().await? else {
+            return Ok(());
+        };
+        let peer = Peer::new(addr.ip());
+        let started = Instant::now();
+        let kind = request.req_type().name();
+        debug!(%peer, device = request.id(), kind, "received request");
+        let response = match self.hooks.check(&peer) {
+            Verdict::Allow => {
+                let mut device = device.write().await;
+                hooks::span(&request).in_scope(|| device.handle_from(&peer, request))
+            }
+            Verdict::Drop => return Ok(()),
+            Verdict::Reject(e) => CommandResponse::rejected(request.id(), Rejected::Limit(e)),
+        };
+        self.hooks.observe(kind, started, &response);
+        if let Err(e) = self.send(response, addr).await {
+            warn!(%addr, error = %e, "response is not sent");
+        }
         Ok(())
     }
 }
 
-impl ServerAsync for UDPServerAsync {
-    async fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+impl BindAsync for UDPServerAsync {
+    async fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
         let socket = UdpSocket::bind(addr).await?;
-        Ok(Self { socket })
+        Ok(Self {
+            socket,
+            hooks: Hooks::default(),
+        })
     }
-    async fn listen(&self, device: SharedDevice) -> Result<()> {
+}
+
+impl ServerAsync for UDPServerAsync {
+    async fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
         loop {
             self.handle(device.clone()).await?;
         }
@@ -117,39 +233,69 @@ impl ServerAsync for UDPServerAsync {
 
 #[cfg(test)]
 mod tests {
-    use crate::r#async::{ClientAsync, TCPClientAsync, UDPClientAsync};
+    use crate::{
+        command::ResponseType,
+        r#async::{ClientAsync, TCPClientAsync, UDPClientAsync},
+    };
     use smart_home::devices::Thermometer;
+    use std::time::Duration;
 
     use super::*;
     #[tokio::test]
     async fn test_tcp_listener() {
-        let listener = TCPServerAsync::new("127.0.0.1:8008").await.unwrap();
+        let listener = TCPServerAsync::new("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
         let device = Arc::new(RwLock::new(Thermometer::new("123")));
         let t = tokio::spawn(async move { listener.listen(device).await });
 
-        let mut s = TCPClientAsync::new("127.0.0.1:8008").await.unwrap();
+        let mut s = TCPClientAsync::new(addr).await.unwrap();
         s.send(CommandRequest::builder().therm("123").get_temp())
             .await
             .unwrap();
 
         let resp = s.receive().await.unwrap();
-        println!("{resp:?}");
+        assert!(matches!(resp.response(), ResponseType::Success(_)));
         t.abort();
     }
 
     #[tokio::test]
     async fn test_udp_listener() {
-        let listener = UDPServerAsync::new("127.0.0.1:8009").await.unwrap();
+        let listener = UDPServerAsync::new("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
         let device = Arc::new(RwLock::new(Thermometer::new("123")));
         let t = tokio::spawn(async move { listener.listen(device).await });
 
-        let mut s = UDPClientAsync::new("127.0.0.1:8009").await.unwrap();
+        let mut s = UDPClientAsync::new(addr).await.unwrap();
         s.send(CommandRequest::builder().therm("123").get_temp())
             .await
             .unwrap();
 
         let resp = s.receive().await.unwrap();
-        println!("{resp:?}");
+        assert!(matches!(resp.response(), ResponseType::Success(_)));
+        t.abort();
+    }
+
+    #[tokio::test]
+    async fn test_udp_malformed() {
+        let listener = UDPServerAsync::new("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let device = Arc::new(RwLock::new(Thermometer::new("123")));
+        let t = tokio::spawn(async move { listener.listen(device).await });
+
+        // Junk and datagram over the buffer don't stop the server
+        let junk = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        junk.send_to(b"garbage", addr).await.unwrap();
+        junk.send_to(&[b'{'; BUFLEN + 1], addr).await.unwrap();
+        let mut s = UDPClientAsync::new(addr).await.unwrap();
+        s.send(CommandRequest::builder().therm("123").get_temp())
+            .await
+            .unwrap();
+        let resp = tokio::time::timeout(Duration::from_secs(1), s.receive())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(resp.response(), ResponseType::Success(_)));
+        assert!(!t.is_finished());
         t.abort();
     }
 }
@@ -0,0 +1,110 @@
+/// TLS variant of async TCP server and client
+/// Configs are created with crate::tls from certificate and key files
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio_rustls::{client::TlsStream, TlsAcceptor, TlsConnector};
+
+use tracing::{debug, warn};
+
+use crate::{
+    command::{CommandRequest, CommandResponse},
+    device::Peer,
+    error::NetworkResult,
+    frame,
+    hooks::{self, Hooks},
+    limit::RateLimiter,
+    metrics::ServerMetrics,
+    r#async::{ClientAsync, ServerAsync, SharedDevice, TCPServerAsync},
+    tls::{server_name, TlsClientConfig, TlsServerConfig},
+};
+
+/// Single task listener, same as TCPServerAsync
+/// Connection is closed if handshake fails, e.g. client certificate is not valid
+pub struct TLSServerAsync {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    hooks: Hooks,
+}
+
+impl TLSServerAsync {
+    pub async fn new<A: ToSocketAddrs>(
+        addr: A,
+        config: Arc<TlsServerConfig>,
+    ) -> NetworkResult<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let acceptor = TlsAcceptor::from(config);
+        Ok(Self {
+            listener,
+            acceptor,
+            hooks: Hooks::default(),
+        })
+    }
+
+    /// Bound address, e.g. when bound to port 0
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Limit requests of clients
+    pub fn with_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.hooks.limiter = Some(limiter);
+        self
+    }
+
+    /// Count requests, errors and latency
+    pub fn with_metrics(mut self, metrics: ServerMetrics) -> Self {
+        self.hooks.metrics = Some(metrics);
+        self
+    }
+}
+
+impl ServerAsync for TLSServerAsync {
+    async fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        while let Ok((stream, addr)) = self.listener.accept().await {
+            match self.acceptor.accept(stream).await {
+                Ok(stream) => {
+                    let peer = Peer::new(addr.ip());
+                    let handled = TCPServerAsync::handle(stream, peer, device.clone(), &self.hooks);
+                    if let Err(e) = handled.await {
+                        warn!(%addr, error = %e, "connection failed");
+                    }
+                }
+                Err(e) => warn!(%addr, "TLS handshake failed: {e}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct TLSClientAsync {
+    stream: TlsStream<TcpStream>,
+}
+
+impl TLSClientAsync {
+    /// Server certificate should be valid for the name
+    pub async fn new<A: ToSocketAddrs>(
+        addr: A,
+        name: &str,
+        config: Arc<TlsClientConfig>,
+    ) -> NetworkResult<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        let stream = TlsConnector::from(config)
+            .connect(server_name(name)?, stream)
+            .await?;
+        Ok(Self { stream })
+    }
+}
+
+impl ClientAsync for TLSClientAsync {
+    async fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
+        frame::write_async(&mut self.stream, &request).await
+    }
+
+    async fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let resp: CommandResponse = frame::read_async(&mut self.stream).await?;
+        debug!(device = resp.id(), "received response");
+        Ok(resp)
+    }
+}
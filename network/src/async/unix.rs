@@ -0,0 +1,280 @@
+/// Async servers and clients over Unix domain sockets: stream and datagram
+/// Access is controlled with permissions of the socket file,
+/// the file is removed when server is dropped
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use tokio::net::{UnixDatagram, UnixListener, UnixStream};
+
+use tracing::{debug, warn};
+
+use crate::{
+    command::{CommandRequest, CommandResponse},
+    device::Peer,
+    error::NetworkResult,
+    frame,
+    hooks::{self, Hooks},
+    r#async::{ClientAsync, ServerAsync, SharedDevice, TCPServerAsync},
+    socket_file::{self, DEFAULT_MODE},
+    BUFLEN,
+};
+
+/// Single task listener, same as TCPServerAsync
+/// Server should be created within tokio runtime
+pub struct UnixServerAsync {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixServerAsync {
+    pub fn new<P: AsRef<Path>>(path: P) -> NetworkResult<Self> {
+        Self::with_mode(path, DEFAULT_MODE)
+    }
+
+    /// Bind with permissions of the socket file, e.g. 0o600 for owner only
+    pub fn with_mode<P: AsRef<Path>>(path: P, mode: u32) -> NetworkResult<Self> {
+        let path = path.as_ref();
+        socket_file::remove_stale(path, false)?;
+        let listener = socket_file::bind(path, mode, |p| UnixListener::bind(p))?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl ServerAsync for UnixServerAsync {
+    async fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        while let Ok((stream, _)) = self.listener.accept().await {
+            let hooks = Hooks::default();
+            if let Err(e) =
+                TCPServerAsync::handle(stream, Peer::default(), device.clone(), &hooks).await
+            {
+                warn!(error = %e, "connection failed");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UnixServerAsync {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Server should be created within tokio runtime
+pub struct UnixDatagramServerAsync {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl UnixDatagramServerAsync {
+    pub fn new<P: AsRef<Path>>(path: P) -> NetworkResult<Self> {
+        Self::with_mode(path, DEFAULT_MODE)
+    }
+
+    /// Bind with permissions of the socket file, e.g. 0o600 for owner only
+    pub fn with_mode<P: AsRef<Path>>(path: P, mode: u32) -> NetworkResult<Self> {
+        let path = path.as_ref();
+        socket_file::remove_stale(path, true)?;
+        let socket = socket_file::bind(path, mode, |p| UnixDatagram::bind(p))?;
+        Ok(Self {
+            socket,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Only failure of the socket is returned, failed datagrams are skipped
+    async fn handle(&self, device: SharedDevice) -> NetworkResult<()> {
+        let mut buf = vec![0u8; BUFLEN];
+        let (size, addr) = self.socket.recv_from(&mut buf).await?;
+        let request = match CommandRequest::request_from(&buf[0..size]) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!(error = %e, "malformed request is skipped");
+                return Ok(());
+            }
+        };
+        debug!(
+            device = request.id(),
+            kind = request.req_type().name(),
+            "received request"
+        );
+        let mut device = device.write().await;
+        let response = hooks::span(&request).in_scope(|| device.handle(request));
+        // Response could be sent only to bound client, which is still there
+        let Some(addr) = addr.as_pathname() else {
+            warn!("response is not sent: client socket is not bound");
+            return Ok(());
+        };
+        let buf: Vec<u8> = response.into();
+        if let Err(e) = self.socket.send_to(&buf, addr).await {
+            warn!(addr = %addr.display(), error = %e, "response is not sent");
+        }
+        Ok(())
+    }
+}
+
+impl ServerAsync for UnixDatagramServerAsync {
+    async fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        loop {
+            self.handle(device.clone()).await?;
+        }
+    }
+}
+
+impl Drop for UnixDatagramServerAsync {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct UnixClientAsync {
+    stream: UnixStream,
+}
+
+impl UnixClientAsync {
+    pub async fn new<P: AsRef<Path>>(path: P) -> NetworkResult<Self> {
+        let stream = UnixStream::connect(path).await?;
+        Ok(Self { stream })
+    }
+}
+
+impl ClientAsync for UnixClientAsync {
+    async fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
+        frame::write_async(&mut self.stream, &request).await
+    }
+
+    async fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let resp: CommandResponse = frame::read_async(&mut self.stream).await?;
+        debug!(device = resp.id(), "received response");
+        Ok(resp)
+    }
+}
+
+/// Client is bound to temporary socket file to receive responses
+pub struct UnixDatagramClientAsync {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl UnixDatagramClientAsync {
+    pub async fn new<P: AsRef<Path>>(path: P) -> NetworkResult<Self> {
+        let client_path = socket_file::client_path();
+        let socket = UnixDatagram::bind(&client_path)?;
+        let client = Self {
+            socket,
+            path: client_path,
+        };
+        client.socket.connect(path)?;
+        Ok(client)
+    }
+}
+
+impl ClientAsync for UnixDatagramClientAsync {
+    async fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
+        let buf = serde_json::to_vec(&request)?;
+        self.socket.send(&buf).await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let mut buf = vec![0; BUFLEN];
+        let size = self.socket.recv(&mut buf).await?;
+        let resp = CommandResponse::response_from(&buf[0..size])?;
+        debug!(device = resp.id(), "received response");
+        Ok(resp)
+    }
+}
+
+impl Drop for UnixDatagramClientAsync {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smart_home::devices::{Socket, Thermometer};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{command::ResponseType, r#async::NetworkDeviceAsync};
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{name}_{}.sock", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_unix_stream_async() {
+        let path = socket_path("unix_stream_async");
+        let server = UnixServerAsync::new(&path).unwrap();
+        let device = NetworkDeviceAsync::with_server(Socket::new("s1"), server);
+        let t = tokio::spawn(async move { device.listen().await });
+
+        let mut client = UnixClientAsync::new(&path).await.unwrap();
+        client
+            .send(CommandRequest::builder().socket("s1").turn_on())
+            .await
+            .unwrap();
+        let response = client.receive().await.unwrap();
+        assert_eq!(response.response(), &ResponseType::Success("".into()));
+        t.abort();
+    }
+
+    #[tokio::test]
+    async fn test_unix_datagram_async() {
+        let path = socket_path("unix_datagram_async");
+        let server = UnixDatagramServerAsync::new(&path).unwrap();
+        let device = NetworkDeviceAsync::with_server(Thermometer::new("t1"), server);
+        let t = tokio::spawn(async move { device.listen().await });
+
+        let mut client = UnixDatagramClientAsync::new(&path).await.unwrap();
+        client
+            .send(CommandRequest::builder().therm("t1").get_temp())
+            .await
+            .unwrap();
+        let response = client.receive().await.unwrap();
+        assert!(matches!(response.response(), ResponseType::Success(_)));
+        t.abort();
+    }
+
+    #[tokio::test]
+    async fn test_unix_datagram_failures_async() {
+        let path = socket_path("unix_datagram_failures_async");
+        let server = UnixDatagramServerAsync::new(&path).unwrap();
+        let device = NetworkDeviceAsync::with_server(Thermometer::new("t1"), server);
+        let t = tokio::spawn(async move { device.listen().await });
+
+        let request =
+            serde_json::to_vec(&CommandRequest::builder().therm("t1").get_temp()).unwrap();
+        // Junk and request from unbound client
+        let unbound = UnixDatagram::unbound().unwrap();
+        unbound.send_to(b"garbage", &path).await.unwrap();
+        unbound.send_to(&request, &path).await.unwrap();
+        // Client which is gone before response
+        let gone_path = socket_file::client_path();
+        let gone = UnixDatagram::bind(&gone_path).unwrap();
+        gone.send_to(&request, &path).await.unwrap();
+        drop(gone);
+        fs::remove_file(&gone_path).unwrap();
+
+        let mut client = UnixDatagramClientAsync::new(&path).await.unwrap();
+        client
+            .send(CommandRequest::builder().therm("t1").get_temp())
+            .await
+            .unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(1), client.receive())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(response.response(), ResponseType::Success(_)));
+        assert!(!t.is_finished());
+        t.abort();
+    }
+}
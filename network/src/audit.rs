@@ -0,0 +1,330 @@
+/// Append-only audit log of handled commands
+/// Entries are stored as JSON lines, file is rotated when it exceeds max size:
+/// audit.jsonl -> audit.jsonl.1 -> ... -> audit.jsonl.N, the oldest file is removed
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    command::{CommandRequest, CommandResponse, RequestType, ResponseType},
+    device::{Device, Peer},
+    Result,
+};
+
+/// Default size of the file before rotation
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// Default number of rotated files
+pub const DEFAULT_KEEP: usize = 5;
+
+/// Result of the command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    pub peer: Peer,
+    pub device: String,  // Request id
+    pub request: String, // Request type name, e.g. "SocketTurnOff"
+    pub outcome: Outcome,
+}
+
+impl AuditEntry {
+    /// Entries for request and its response, one per command of batch
+    pub fn from_exchange(
+        peer: &Peer,
+        request: &CommandRequest,
+        response: &CommandResponse,
+    ) -> Vec<Self> {
+        let time = Utc::now();
+        let entry = |request: &CommandRequest, response: &ResponseType| Self {
+            time,
+            peer: peer.clone(),
+            device: request.id().to_string(),
+            request: request.req_type().name().to_string(),
+            outcome: match response {
+                ResponseType::Success(_) | ResponseType::Batch(_) => Outcome::Success,
+                ResponseType::Err(e) => Outcome::Error(e.clone()),
+            },
+        };
+        match (request.req_type(), response.response()) {
+            (RequestType::Batch { commands, .. }, ResponseType::Batch(responses)) => commands
+                .iter()
+                .zip(responses)
+                .map(|(c, r)| entry(c, r.response()))
+                .collect(),
+            // Batch is rejected as a whole
+            (RequestType::Batch { commands, .. }, r) => {
+                commands.iter().map(|c| entry(c, r)).collect()
+            }
+            (_, r) => vec![entry(request, r)],
+        }
+    }
+}
+
+/// Filter for stored entries, not set fields match anything
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    pub device: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>, // Exclusive
+}
+
+impl AuditQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn device(mut self, device: &str) -> Self {
+        self.device = Some(device.to_string());
+        self
+    }
+
+    pub fn since(mut self, time: DateTime<Utc>) -> Self {
+        self.since = Some(time);
+        self
+    }
+
+    pub fn until(mut self, time: DateTime<Utc>) -> Self {
+        self.until = Some(time);
+        self
+    }
+
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.device.as_ref().is_none_or(|d| *d == entry.device)
+            && self.since.is_none_or(|t| entry.time >= t)
+            && self.until.is_none_or(|t| entry.time < t)
+    }
+}
+
+/// Entries found by the query
+/// Lines which are not valid entries, e.g. truncated by crash, are skipped
+#[derive(Debug, Default, PartialEq)]
+pub struct QueryResult {
+    pub entries: Vec<AuditEntry>,
+    pub skipped: usize,
+}
+
+/// Writer of the log file
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl AuditLog {
+    /// Open log for appending, file is created if it doesn't exist
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size: DEFAULT_MAX_SIZE,
+            keep: DEFAULT_KEEP,
+        })
+    }
+
+    /// Rotate when file exceeds max_size bytes, keep number of rotated files
+    pub fn with_rotation(mut self, max_size: u64, keep: usize) -> Self {
+        self.max_size = max_size;
+        self.keep = keep;
+        self
+    }
+
+    pub fn append(&mut self, entry: &AuditEntry) -> Result<()> {
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Entries of all log files matching the query, from the oldest
+    pub fn query<P: AsRef<Path>>(path: P, query: &AuditQuery) -> Result<QueryResult> {
+        let path = path.as_ref();
+        let mut result = QueryResult::default();
+        let mut files = (1..)
+            .map(|i| rotated(path, i))
+            .take_while(|p| p.exists())
+            .collect::<Vec<_>>();
+        files.reverse();
+        files.push(path.to_path_buf());
+        for file in files.iter().filter(|p| p.exists()) {
+            for line in BufReader::new(File::open(file)?).split(b'\n') {
+                match serde_json::from_slice::<AuditEntry>(&line?) {
+                    Ok(entry) if query.matches(&entry) => result.entries.push(entry),
+                    Ok(_) => (),
+                    Err(e) => {
+                        debug!(file = %file.display(), error = %e, "audit entry is skipped");
+                        result.skipped += 1;
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+        } else {
+            for i in (1..self.keep).rev() {
+                let from = rotated(&self.path, i);
+                if from.exists() {
+                    fs::rename(from, rotated(&self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+/// Device (e.g. Gateway) which records every handled command
+/// Should be wrapped with Authenticated to log client identity
+/// Failure to write the log doesn't affect the response
+pub struct Audited<D: Device> {
+    inner: D,
+    log: AuditLog,
+}
+
+impl<D: Device> Audited<D> {
+    pub fn new(inner: D, log: AuditLog) -> Self {
+        Self { inner, log }
+    }
+}
+
+impl<D: Device> Device for Audited<D> {
+    fn process(&mut self, request: CommandRequest) -> CommandResponse {
+        self.handle_from(&Peer::default(), request)
+    }
+
+    fn handle(&mut self, request: CommandRequest) -> CommandResponse {
+        self.handle_from(&Peer::default(), request)
+    }
+
+    fn handle_from(&mut self, peer: &Peer, request: CommandRequest) -> CommandResponse {
+        let response = self.inner.handle_from(peer, request.clone());
+        for entry in AuditEntry::from_exchange(peer, &request, &response) {
+            if let Err(e) = self.log.append(&entry) {
+                warn!(error = %e, "audit entry is not written");
+            }
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use smart_home::devices::Socket;
+
+    use super::*;
+    use crate::gateway::Gateway;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{name}_{}.jsonl", std::process::id()));
+        for i in 0..4 {
+            let _ = fs::remove_file(rotated(&path, i));
+        }
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_audited_gateway() -> Result<()> {
+        let path = temp_path("audit_gateway");
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        let mut device = Audited::new(gateway, AuditLog::open(&path)?);
+        let peer = Peer::new([10, 0, 0, 1].into()).with_identity("admin");
+        device.handle_from(&peer, CommandRequest::builder().socket("s1").turn_on());
+        let batch = CommandRequest::builder()
+            .batch("gw")
+            .command(CommandRequest::builder().socket("s1").turn_off())
+            .command(CommandRequest::builder().socket("s2").turn_off())
+            .build();
+        device.handle_from(&peer, batch);
+
+        let entries = AuditLog::query(&path, &AuditQuery::new())?.entries;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].peer, peer);
+        assert_eq!(entries[1].request, "SocketTurnOff");
+        assert_eq!(entries[1].outcome, Outcome::Success);
+        assert_eq!(entries[2].outcome, Outcome::Error("No such device".into()));
+
+        let query = AuditQuery::new().device("s2");
+        assert_eq!(AuditLog::query(&path, &query)?.entries.len(), 1);
+        let query = AuditQuery::new().since(Utc::now() + TimeDelta::minutes(1));
+        assert!(AuditLog::query(&path, &query)?.entries.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_lines() -> Result<()> {
+        let path = temp_path("audit_malformed");
+        let mut log = AuditLog::open(&path)?;
+        let request = CommandRequest::builder().socket("s1").turn_on();
+        let response = CommandResponse::new("s1", ResponseType::Success("".into()));
+        let entry = AuditEntry::from_exchange(&Peer::default(), &request, &response).remove(0);
+        log.append(&entry)?;
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(b"not an entry\n\xff\xfe\n")?;
+        log.append(&entry)?;
+        // Line truncated by crash
+        file.write_all(br#"{"time":"2024-"#)?;
+
+        let result = AuditLog::query(&path, &AuditQuery::new())?;
+        assert_eq!(result.entries, vec![entry.clone(), entry]);
+        assert_eq!(result.skipped, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation() -> Result<()> {
+        let path = temp_path("audit_rotation");
+        let mut log = AuditLog::open(&path)?.with_rotation(1, 2);
+        let request = CommandRequest::builder().socket("s1").turn_on();
+        let response = CommandResponse::new("s1", ResponseType::Success("".into()));
+        for _ in 0..4 {
+            for entry in AuditEntry::from_exchange(&Peer::default(), &request, &response) {
+                log.append(&entry)?;
+            }
+        }
+        // Each entry exceeds max size, the oldest one is removed
+        assert!(rotated(&path, 2).exists());
+        assert!(!rotated(&path, 3).exists());
+        assert_eq!(AuditLog::query(&path, &AuditQuery::new())?.entries.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,437 @@
+/// Message authentication with shared keys
+/// Requests are signed by clients with HMAC-SHA256 over key id, timestamp, nonce
+/// and request, devices wrapped with Authenticated check signature, time window
+/// and nonce reuse, responses are signed with the same key
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    command::{CommandRequest, CommandResponse, Rejected, ResponseType},
+    device::{Device, Peer},
+    error::NetworkResult,
+    r#async::ClientAsync,
+    sync::Client,
+};
+
+/// Requests older or newer than this are rejected
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(30);
+
+static NONCE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AuthError {
+    Missing,            // Request is not signed
+    UnknownKey(String), // Key id is not known by the device
+    BadSignature,       // Signature doesn't match
+    Expired,            // Timestamp is out of time window
+    Replayed,           // Nonce is already used
+    NonceMismatch,      // Response is not for the sent request
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "Unauthorized: request is not signed"),
+            Self::UnknownKey(k) => write!(f, "Unauthorized: unknown key {}", k),
+            Self::BadSignature => write!(f, "Unauthorized: bad signature"),
+            Self::Expired => write!(f, "Unauthorized: timestamp is expired"),
+            Self::Replayed => write!(f, "Unauthorized: nonce is already used"),
+            Self::NonceMismatch => write!(f, "Unauthorized: response nonce mismatch"),
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+/// Signature attached to request or response
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Auth {
+    pub key_id: String,
+    pub timestamp: i64, // Unix time, seconds
+    pub nonce: u64,
+    pub signature: String, // Hex encoded HMAC-SHA256
+}
+
+/// Shared key known by client and device
+#[derive(Clone)]
+pub struct Key {
+    id: String,
+    secret: Vec<u8>,
+}
+
+impl Key {
+    pub fn new(id: &str, secret: &[u8]) -> Self {
+        Self {
+            id: id.to_string(),
+            secret: secret.to_vec(),
+        }
+    }
+
+    /// id getter
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Sign request with current time and unique nonce, returns nonce
+    pub fn sign_request(&self, request: &mut CommandRequest) -> u64 {
+        let nonce = Self::nonce();
+        self.sign_request_at(request, Utc::now().timestamp(), nonce);
+        nonce
+    }
+
+    pub fn sign_request_at(&self, request: &mut CommandRequest, timestamp: i64, nonce: u64) {
+        request.set_auth(None);
+        let payload = serde_json::to_vec(request).unwrap();
+        request.set_auth(Some(self.auth(timestamp, nonce, &payload)));
+    }
+
+    /// Response signature should match and response should be for the request with nonce
+    /// Unsigned response is accepted only for rejections which can't be signed:
+    /// device doesn't know the key or request is over the rate limit of the server,
+    /// other unsigned responses are reported as Missing
+    pub fn verify_response(
+        &self,
+        response: &mut CommandResponse,
+        nonce: u64,
+    ) -> std::result::Result<(), AuthError> {
+        let Some(auth) = response.auth().cloned() else {
+            let unsigned = match response.rejection() {
+                Some(Rejected::Auth(AuthError::Missing) | Rejected::Limit(_)) => true,
+                Some(Rejected::Auth(AuthError::UnknownKey(id))) => *id == self.id,
+                _ => false,
+            };
+            let error = response
+                .rejection()
+                .map(|r| ResponseType::Err(r.to_string()));
+            return if unsigned && error.as_ref() == Some(response.response()) {
+                Ok(())
+            } else {
+                Err(AuthError::Missing)
+            };
+        };
+        if auth.nonce != nonce {
+            return Err(AuthError::NonceMismatch);
+        }
+        response.set_auth(None);
+        let payload = serde_json::to_vec(response).unwrap();
+        let result = self.verify(&auth, &payload);
+        response.set_auth(Some(auth));
+        result
+    }
+
+    fn sign_response(&self, response: &mut CommandResponse, nonce: u64) {
+        let payload = serde_json::to_vec(response).unwrap();
+        response.set_auth(Some(self.auth(Utc::now().timestamp(), nonce, &payload)));
+    }
+
+    fn auth(&self, timestamp: i64, nonce: u64, payload: &[u8]) -> Auth {
+        let signature = self.mac(timestamp, nonce, payload).finalize().into_bytes();
+        Auth {
+            key_id: self.id.clone(),
+            timestamp,
+            nonce,
+            signature: signature.iter().map(|b| format!("{b:02x}")).collect(),
+        }
+    }
+
+    fn verify(&self, auth: &Auth, payload: &[u8]) -> std::result::Result<(), AuthError> {
+        let signature = (0..auth.signature.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(auth.signature.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()
+            .ok_or(AuthError::BadSignature)?;
+        self.mac(auth.timestamp, auth.nonce, payload)
+            .verify_slice(&signature)
+            .map_err(|_| AuthError::BadSignature)
+    }
+
+    fn mac(&self, timestamp: i64, nonce: u64, payload: &[u8]) -> Hmac<Sha256> {
+        // Any key length is accepted by HMAC
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        mac.update(self.id.as_bytes());
+        mac.update(&timestamp.to_be_bytes());
+        mac.update(&nonce.to_be_bytes());
+        mac.update(payload);
+        mac
+    }
+
+    fn nonce() -> u64 {
+        let time = Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+        time.wrapping_add(NONCE_COUNTER.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Device which accepts only requests signed with known keys
+/// Failures are returned as ResponseType::Err signed with the key of the request,
+/// they are unsigned if the key is missing or not known
+pub struct Authenticated<D: Device> {
+    inner: D,
+    keys: HashMap<String, Key>,
+    window: u64,                       // Seconds
+    seen: HashMap<(String, u64), i64>, // Nonces used within time window
+}
+
+impl<D: Device> Authenticated<D> {
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            keys: HashMap::default(),
+            window: DEFAULT_WINDOW.as_secs(),
+            seen: HashMap::default(),
+        }
+    }
+
+    pub fn with_key(mut self, key: Key) -> Self {
+        self.keys.insert(key.id.clone(), key);
+        self
+    }
+
+    /// Allowed difference between request timestamp and device time
+    /// Timestamps are in seconds, so the window is rounded up to whole seconds
+    pub fn with_window(mut self, window: Duration) -> Self {
+        let partial = window.subsec_nanos() > 0;
+        self.window = window.as_secs().saturating_add(u64::from(partial));
+        self
+    }
+
+    /// Check request and remove signature, returns key for the response
+    pub fn authenticate(
+        &mut self,
+        request: &mut CommandRequest,
+    ) -> std::result::Result<Key, AuthError> {
+        let auth = request.auth().cloned().ok_or(AuthError::Missing)?;
+        let key = self
+            .keys
+            .get(&auth.key_id)
+            .ok_or_else(|| AuthError::UnknownKey(auth.key_id.clone()))?;
+        request.set_auth(None);
+        let payload = serde_json::to_vec(request).unwrap();
+        key.verify(&auth, &payload)?;
+
+        let now = Utc::now().timestamp();
+        if now.abs_diff(auth.timestamp) > self.window {
+            return Err(AuthError::Expired);
+        }
+        let window = self.window;
+        self.seen.retain(|_, t| now.abs_diff(*t) <= window);
+        if self
+            .seen
+            .insert((auth.key_id, auth.nonce), auth.timestamp)
+            .is_some()
+        {
+            return Err(AuthError::Replayed);
+        }
+        Ok(key.clone())
+    }
+}
+
+impl<D: Device> Device for Authenticated<D> {
+    fn process(&mut self, request: CommandRequest) -> CommandResponse {
+        self.handle_from(&Peer::default(), request)
+    }
+
+    /// Batch is signed as a whole
+    fn handle(&mut self, request: CommandRequest) -> CommandResponse {
+        self.handle_from(&Peer::default(), request)
+    }
+
+    /// Inner device gets the peer identified by key id
+    fn handle_from(&mut self, peer: &Peer, mut request: CommandRequest) -> CommandResponse {
+        let nonce = request.auth().map(|a| a.nonce).unwrap_or_default();
+        let key_id = request.auth().map(|a| a.key_id.clone());
+        match self.authenticate(&mut request) {
+            Ok(key) => {
+                let peer = peer.with_identity(key.id());
+                let mut response = self.inner.handle_from(&peer, request);
+                key.sign_response(&mut response, nonce);
+                response
+            }
+            Err(e) => {
+                let mut response = CommandResponse::rejected(request.id(), Rejected::Auth(e));
+                if let Some(key) = key_id.and_then(|id| self.keys.get(&id)) {
+                    key.sign_response(&mut response, nonce);
+                }
+                response
+            }
+        }
+    }
+}
+
+/// Client which signs requests and verifies responses
+pub struct AuthClient<C> {
+    inner: C,
+    key: Key,
+    nonce: u64, // Nonce of the last request
+}
+
+impl<C> AuthClient<C> {
+    pub fn new(inner: C, key: Key) -> Self {
+        Self {
+            inner,
+            key,
+            nonce: 0,
+        }
+    }
+}
+
+impl<C: Client> Client for AuthClient<C> {
+    fn send(&mut self, mut request: CommandRequest) -> NetworkResult<()> {
+        self.nonce = self.key.sign_request(&mut request);
+        self.inner.send(request)
+    }
+
+    fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let mut response = self.inner.receive()?;
+        self.key.verify_response(&mut response, self.nonce)?;
+        Ok(response)
+    }
+}
+
+impl<C: ClientAsync + Send> ClientAsync for AuthClient<C> {
+    async fn send(&mut self, mut request: CommandRequest) -> NetworkResult<()> {
+        self.nonce = self.key.sign_request(&mut request);
+        self.inner.send(request).await
+    }
+
+    async fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let mut response = self.inner.receive().await?;
+        self.key.verify_response(&mut response, self.nonce)?;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smart_home::devices::Socket;
+
+    use super::*;
+
+    fn device() -> Authenticated<Socket> {
+        Authenticated::new(Socket::new("s1")).with_key(Key::new("admin", b"secret"))
+    }
+
+    #[test]
+    fn test_signed_request() {
+        let mut device = device();
+        let key = Key::new("admin", b"secret");
+        let mut request = CommandRequest::builder().socket("s1").turn_on();
+        let nonce = key.sign_request(&mut request);
+        let mut response = device.handle(request);
+        assert_eq!(response.response(), &ResponseType::Success("".into()));
+        assert!(key.verify_response(&mut response, nonce).is_ok());
+        assert_eq!(
+            key.verify_response(&mut response, nonce + 1),
+            Err(AuthError::NonceMismatch)
+        );
+    }
+
+    #[test]
+    fn test_rejected_requests() {
+        let mut device = device();
+        let error =
+            |device: &mut Authenticated<Socket>, request| device.handle(request).response().clone();
+        let request = || CommandRequest::builder().socket("s1").turn_on();
+        assert_eq!(
+            error(&mut device, request()),
+            ResponseType::Err(AuthError::Missing.to_string())
+        );
+
+        // Wrong secret
+        let mut r = request();
+        Key::new("admin", b"guess").sign_request(&mut r);
+        assert_eq!(
+            error(&mut device, r),
+            ResponseType::Err(AuthError::BadSignature.to_string())
+        );
+
+        let mut r = request();
+        Key::new("guest", b"secret").sign_request(&mut r);
+        assert_eq!(
+            error(&mut device, r),
+            ResponseType::Err(AuthError::UnknownKey("guest".into()).to_string())
+        );
+
+        // Request is modified after signing
+        let key = Key::new("admin", b"secret");
+        let mut r = request();
+        key.sign_request(&mut r);
+        r.set_id("s2");
+        assert_eq!(
+            error(&mut device, r),
+            ResponseType::Err(AuthError::BadSignature.to_string())
+        );
+
+        let mut r = request();
+        key.sign_request_at(&mut r, Utc::now().timestamp() - 60, 1);
+        assert_eq!(
+            error(&mut device, r),
+            ResponseType::Err(AuthError::Expired.to_string())
+        );
+
+        // Replay of the same request
+        let mut r = request();
+        key.sign_request(&mut r);
+        assert!(matches!(
+            error(&mut device, r.clone()),
+            ResponseType::Success(_)
+        ));
+        assert_eq!(
+            error(&mut device, r),
+            ResponseType::Err(AuthError::Replayed.to_string())
+        );
+    }
+
+    #[test]
+    fn test_signed_errors() {
+        let mut device = device();
+        let key = Key::new("admin", b"secret");
+        let mut r = CommandRequest::builder().socket("s1").turn_on();
+        key.sign_request_at(&mut r, Utc::now().timestamp() - 60, 1);
+        let mut response = device.handle(r);
+        assert_eq!(
+            response.response(),
+            &ResponseType::Err(AuthError::Expired.to_string())
+        );
+        assert!(key.verify_response(&mut response, 1).is_ok());
+
+        // Forged error without signature
+        let forged = ResponseType::Err("Socket is overloaded: 1.0W".into());
+        let mut response = CommandResponse::new("s1", forged);
+        assert_eq!(
+            key.verify_response(&mut response, 1),
+            Err(AuthError::Missing)
+        );
+
+        // Device doesn't know the key, so it can't sign the failure
+        let guest = Key::new("guest", b"secret");
+        let mut r = CommandRequest::builder().socket("s1").turn_on();
+        let nonce = guest.sign_request(&mut r);
+        let mut response = device.handle(r);
+        assert!(response.auth().is_none());
+        assert!(guest.verify_response(&mut response, nonce).is_ok());
+    }
+
+    #[test]
+    fn test_window() {
+        let device = device().with_window(Duration::from_millis(500));
+        assert_eq!(device.window, 1);
+        let mut device = device.with_window(Duration::MAX);
+        let key = Key::new("admin", b"secret");
+        let mut r = CommandRequest::builder().socket("s1").turn_on();
+        key.sign_request_at(&mut r, i64::MIN, 1);
+        assert!(matches!(
+            device.handle(r).response(),
+            ResponseType::Success(_)
+        ));
+    }
+}
@@ -0,0 +1,49 @@
+/// Print audit log entries as JSON lines
+/// Usage: audit <audit.jsonl> [--device ID] [--since RFC3339] [--until RFC3339]
+/// Entries go to stdout, logs to stderr (SMART_HOME_LOG, SMART_HOME_LOG_FORMAT=json)
+use chrono::{DateTime, Utc};
+use network::{
+    audit::{AuditLog, AuditQuery},
+    logging, Result,
+};
+use tracing::{error, warn};
+
+const USAGE: &str = "Usage: audit <audit.jsonl> [--device ID] [--since RFC3339] [--until RFC3339]";
+
+fn time(value: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc))
+}
+
+fn run() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or(USAGE)?;
+    let mut query = AuditQuery::new();
+    while let Some(option) = args.next() {
+        let value = args.next().ok_or(USAGE)?;
+        query = match option.as_str() {
+            "--device" => query.device(&value),
+            "--since" => query.since(time(&value)?),
+            "--until" => query.until(time(&value)?),
+            _ => return Err(USAGE.into()),
+        };
+    }
+    let result = AuditLog::query(path, &query)?;
+    for entry in result.entries {
+        println!("{}", serde_json::to_string(&entry)?);
+    }
+    if result.skipped > 0 {
+        warn!(
+            skipped = result.skipped,
+            "malformed audit entries are skipped"
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    logging::init();
+    if let Err(e) = run() {
+        error!(error = %e, "audit query failed");
+        std::process::exit(1);
+    }
+}
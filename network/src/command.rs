@@ -1,41 +1,106 @@
 /// Module provides CommandRequest and CommandResponse structures
 /// serialized and deserialized with serde_json
-use crate::Result;
-use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::{
+    auth::{Auth, AuthError},
+    error::{NetworkError, NetworkResult},
+    limit::LimitError,
+    BUFLEN,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct CommandRequest {
     id: String,
     request: RequestType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth: Option<Auth>, // Signature of authenticated request
 }
 
 impl CommandRequest {
-    pub fn request_from(buf: &[u8]) -> Result<CommandRequest> {
-        let req: CommandRequest = serde_json::from_slice(buf)?;
-        Ok(req)
+    pub fn request_from(buf: &[u8]) -> NetworkResult<CommandRequest> {
+        decode(buf)
     }
 
     /// id getter
     pub fn id(&self) -> &str {
         &self.id
     }
+    /// id setter
+    pub fn set_id(&mut self, id: &str) {
+        self.id = id.to_string();
+    }
     pub fn req_type(&self) -> &RequestType {
         &self.request
     }
+    pub fn auth(&self) -> Option<&Auth> {
+        self.auth.as_ref()
+    }
+    pub fn set_auth(&mut self, auth: Option<Auth>) {
+        self.auth = auth;
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum RequestType {
     SocketTurnOn,
     SocketTurnOff,
     SocketGetState,
+    SocketReset, // Clear overload trip
+    // Trip by external protection, e.g. power budget of the home
+    SocketTrip {
+        load: f32,
+        limit: f32,
+    },
     ThermGetTemp, // Get thermometer udp socket address
+    // Commands executed in order, with atomic all changes are undone
+    // if one command fails (supported for socket state changes)
+    Batch {
+        commands: Vec<CommandRequest>,
+        atomic: bool,
+    },
+}
+
+impl RequestType {
+    /// Name of the request type, e.g. "SocketTurnOff"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SocketTurnOn => "SocketTurnOn",
+            Self::SocketTurnOff => "SocketTurnOff",
+            Self::SocketGetState => "SocketGetState",
+            Self::SocketReset => "SocketReset",
+            Self::SocketTrip { .. } => "SocketTrip",
+            Self::ThermGetTemp => "ThermGetTemp",
+            Self::Batch { .. } => "Batch",
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct CommandResponse {
     id: String,
     response: ResponseType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth: Option<Auth>, // Signature of response to authenticated request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rejected: Option<Rejected>, // Reason of error which is not reported by the device
+}
+
+/// Request is rejected before it reaches the device
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Rejected {
+    Auth(AuthError),
+    Limit(LimitError),
+}
+
+impl Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auth(e) => write!(f, "{e}"),
+            Self::Limit(e) => write!(f, "{e}"),
+        }
+    }
 }
 
 impl CommandResponse {
@@ -43,8 +108,66 @@ impl CommandResponse {
         Self {
             id: id.to_string(),
             response,
+            auth: None,
+            rejected: None,
+        }
+    }
+
+    /// Error response to rejected request, the reason is kept for the client
+    pub fn rejected(id: &str, reason: Rejected) -> Self {
+        Self {
+            id: id.to_string(),
+            response: ResponseType::Err(reason.to_string()),
+            auth: None,
+            rejected: Some(reason),
+        }
+    }
+
+    /// Decode response received into buffer of BUFLEN
+    pub fn response_from(buf: &[u8]) -> NetworkResult<CommandResponse> {
+        decode(buf)
+    }
+
+    /// id getter
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Response with error is converted to NetworkError:
+    /// Auth or Limit for rejected request, Device for error reported by the device
+    pub fn into_result(self) -> NetworkResult<Self> {
+        match (&self.response, &self.rejected) {
+            (ResponseType::Err(_), Some(Rejected::Auth(e))) => Err(NetworkError::Auth(e.clone())),
+            (ResponseType::Err(_), Some(Rejected::Limit(e))) => Err(NetworkError::Limit(e.clone())),
+            (ResponseType::Err(e), None) => Err(NetworkError::device(&self.id, e)),
+            _ => Ok(self),
         }
     }
+
+    pub fn response(&self) -> &ResponseType {
+        &self.response
+    }
+    pub fn auth(&self) -> Option<&Auth> {
+        self.auth.as_ref()
+    }
+    pub fn set_auth(&mut self, auth: Option<Auth>) {
+        self.auth = auth;
+    }
+    pub fn rejection(&self) -> Option<&Rejected> {
+        self.rejected.as_ref()
+    }
+}
+
+/// Message is read with single call into buffer of BUFLEN,
+/// empty read means closed connection and full buffer could be truncated message
+fn decode<T: DeserializeOwned>(buf: &[u8]) -> NetworkResult<T> {
+    match buf.len() {
+        0 => Err(NetworkError::Closed),
+        BUFLEN.. => Err(NetworkError::Framing(format!(
+            "Message is longer than {BUFLEN} bytes"
+        ))),
+        _ => Ok(serde_json::from_slice(buf)?),
+    }
 }
 
 impl From<CommandResponse> for Vec<u8> {
@@ -53,24 +176,39 @@ impl From<CommandResponse> for Vec<u8> {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ResponseType {
     Success(String),
     Err(String),
+    Batch(Vec<CommandResponse>), // Responses of batch commands in order
 }
 
 pub struct CommandRequestBuilder;
 pub struct SocketRequestBuilder<'a>(&'a str); // id
 pub struct ThermRequestBuilder<'a>(&'a str); // id
+pub struct BatchRequestBuilder {
+    id: String,
+    commands: Vec<CommandRequest>,
+    atomic: bool,
+}
 
 impl CommandRequestBuilder {
-    pub fn socket(self, id: &str) -> SocketRequestBuilder {
+    pub fn socket(self, id: &str) -> SocketRequestBuilder<'_> {
         SocketRequestBuilder(id)
     }
 
-    pub fn therm(self, id: &str) -> ThermRequestBuilder {
+    pub fn therm(self, id: &str) -> ThermRequestBuilder<'_> {
         ThermRequestBuilder(id)
     }
+
+    /// Batch of commands sent in one request, id is returned in batch response
+    pub fn batch(self, id: &str) -> BatchRequestBuilder {
+        BatchRequestBuilder {
+            id: id.to_string(),
+            commands: Vec::default(),
+            atomic: false,
+        }
+    }
 }
 
 impl SocketRequestBuilder<'_> {
@@ -78,18 +216,35 @@ impl SocketRequestBuilder<'_> {
         CommandRequest {
             id: self.0.to_string(),
             request: RequestType::SocketTurnOn,
+            auth: None,
         }
     }
     pub fn turn_off(self) -> CommandRequest {
         CommandRequest {
             id: self.0.to_string(),
             request: RequestType::SocketTurnOff,
+            auth: None,
         }
     }
     pub fn get_state(self) -> CommandRequest {
         CommandRequest {
             id: self.0.to_string(),
             request: RequestType::SocketGetState,
+            auth: None,
+        }
+    }
+    pub fn reset(self) -> CommandRequest {
+        CommandRequest {
+            id: self.0.to_string(),
+            request: RequestType::SocketReset,
+            auth: None,
+        }
+    }
+    pub fn trip(self, load: f32, limit: f32) -> CommandRequest {
+        CommandRequest {
+            id: self.0.to_string(),
+            request: RequestType::SocketTrip { load, limit },
+            auth: None,
         }
     }
 }
@@ -99,6 +254,29 @@ impl ThermRequestBuilder<'_> {
         CommandRequest {
             id: self.0.to_string(),
             request: RequestType::ThermGetTemp,
+            auth: None,
+        }
+    }
+}
+
+impl BatchRequestBuilder {
+    pub fn command(mut self, request: CommandRequest) -> Self {
+        self.commands.push(request);
+        self
+    }
+    /// All-or-nothing execution
+    pub fn atomic(mut self) -> Self {
+        self.atomic = true;
+        self
+    }
+    pub fn build(self) -> CommandRequest {
+        CommandRequest {
+            id: self.id,
+            request: RequestType::Batch {
+                commands: self.commands,
+                atomic: self.atomic,
+            },
+            auth: None,
         }
     }
 }
@@ -111,11 +289,50 @@ impl CommandRequest {
 
 #[cfg(test)]
 mod tests {
-    use super::CommandRequest;
+    use super::{CommandRequest, RequestType};
+    use crate::{error::NetworkError, BUFLEN};
 
     #[test]
     fn command_request() {
         let request = CommandRequest::builder().socket("socket_123").get_state();
-        println!("{request:?}")
+        assert_eq!(request.id(), "socket_123");
+    }
+
+    #[test]
+    fn batch_request() {
+        let request = CommandRequest::builder()
+            .batch("gw")
+            .command(CommandRequest::builder().socket("s1").turn_on())
+            .command(CommandRequest::builder().therm("t1").get_temp())
+            .atomic()
+            .build();
+        let buf = serde_json::to_vec(&request).unwrap();
+        assert_eq!(CommandRequest::request_from(&buf).unwrap(), request);
+        assert_eq!(
+            request.req_type(),
+            &RequestType::Batch {
+                commands: vec![
+                    CommandRequest::builder().socket("s1").turn_on(),
+                    CommandRequest::builder().therm("t1").get_temp()
+                ],
+                atomic: true
+            }
+        );
+    }
+
+    #[test]
+    fn framing() {
+        assert!(matches!(
+            CommandRequest::request_from(&[]),
+            Err(NetworkError::Closed)
+        ));
+        assert!(matches!(
+            CommandRequest::request_from(&[b' '; BUFLEN]),
+            Err(NetworkError::Framing(_))
+        ));
+        assert!(matches!(
+            CommandRequest::request_from(b"{}"),
+            Err(NetworkError::Serialization(_))
+        ));
     }
 }
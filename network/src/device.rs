@@ -1,12 +1,132 @@
 /// Provides Device trait, which makes devices capable to handle
 /// CommandRequest
 /// Device is implemented for devices from smart home
+use std::{fmt::Display, net::IpAddr};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace};
+
 use crate::command::{CommandRequest, CommandResponse, RequestType, ResponseType};
 
-use smart_home::devices::{Socket, Thermometer};
+use smart_home::{
+    devices::{Socket, SocketState, Thermometer, ThermometerState},
+    query::{DeviceState, DeviceStatus},
+    DeviceStatusProvider, DeviceSwitch,
+};
 
 pub trait Device {
     fn process(&mut self, request: CommandRequest) -> CommandResponse;
+
+    /// Process request received by server
+    /// Batch is executed command by command with process
+    fn handle(&mut self, request: CommandRequest) -> CommandResponse {
+        match request.req_type() {
+            RequestType::Batch { commands, atomic } => {
+                let commands = commands.clone();
+                process_batch(self, request.id(), commands, *atomic)
+            }
+            _ => self.process(request),
+        }
+    }
+
+    /// Handle request received from the peer, e.g. for access control
+    /// Peer is ignored by default
+    fn handle_from(&mut self, _peer: &Peer, request: CommandRequest) -> CommandResponse {
+        self.handle(request)
+    }
+}
+
+/// Client which sent the request to the server
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Peer {
+    pub addr: Option<IpAddr>, // None for Unix sockets and in-memory transport
+    pub identity: Option<String>, // Set by authentication, e.g. key id
+}
+
+impl Peer {
+    pub fn new(addr: IpAddr) -> Self {
+        Self {
+            addr: Some(addr),
+            identity: None,
+        }
+    }
+
+    /// Same peer with identity confirmed by authentication
+    pub fn with_identity(&self, identity: &str) -> Self {
+        Self {
+            addr: self.addr,
+            identity: Some(identity.to_string()),
+        }
+    }
+}
+
+impl Display for Peer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.identity, &self.addr) {
+            (Some(id), Some(addr)) => write!(f, "{id}@{addr}"),
+            (Some(id), None) => write!(f, "{id}"),
+            (None, Some(addr)) => write!(f, "{addr}"),
+            (None, None) => write!(f, "anonymous"),
+        }
+    }
+}
+
+/// Execute batch commands in order
+/// Atomic batch stops on the first failure and restores previous state
+/// of switched sockets
+fn process_batch<D: Device + ?Sized>(
+    device: &mut D,
+    id: &str,
+    commands: Vec<CommandRequest>,
+    atomic: bool,
+) -> CommandResponse {
+    let mut responses = vec![];
+    // Index of response with request restoring previous state
+    let mut undo = vec![];
+    let mut commands = commands.into_iter();
+    for command in commands.by_ref() {
+        let previous = match command.req_type() {
+            RequestType::SocketTurnOn | RequestType::SocketTurnOff if atomic => {
+                let request = CommandRequest::builder().socket(command.id()).get_state();
+                socket_state(&device.process(request)).ok()
+            }
+            _ => None,
+        };
+        let response = match command.req_type() {
+            RequestType::Batch { .. } => {
+                CommandResponse::new(command.id(), ResponseType::Err("Nested batch".into()))
+            }
+            _ => device.process(command.clone()),
+        };
+        let failed = !matches!(response.response(), ResponseType::Success(_));
+        if let (Some(previous), false) = (previous, failed) {
+            let request = CommandRequest::builder().socket(command.id());
+            let request = match previous {
+                SocketState::On => request.turn_on(),
+                SocketState::Off => request.turn_off(),
+            };
+            undo.push((responses.len(), request));
+        }
+        responses.push(response);
+        if failed && atomic {
+            debug!(batch = id, "atomic batch failed, rolling back");
+            for (i, request) in undo.into_iter().rev() {
+                device.process(request);
+                responses[i] = CommandResponse::new(
+                    responses[i].id(),
+                    ResponseType::Err("Rolled back".into()),
+                );
+            }
+            break;
+        }
+    }
+    for command in commands {
+        responses.push(CommandResponse::new(
+            command.id(),
+            ResponseType::Err("Not executed".into()),
+        ));
+    }
+    CommandResponse::new(id, ResponseType::Batch(responses))
 }
 
 impl Device for Socket {
@@ -15,7 +135,7 @@ impl Device for Socket {
             ResponseType::Err("Id is not matched".into())
         } else {
             match request.req_type() {
-                RequestType::SocketGetState => ResponseType::Success(self.to_string()),
+                RequestType::SocketGetState => ResponseType::Success(self.status()),
                 RequestType::SocketTurnOff => match self.turn_off() {
                     Ok(_) => ResponseType::Success("".into()),
                     Err(e) => ResponseType::Err(e.to_string()),
@@ -24,9 +144,23 @@ impl Device for Socket {
                     Ok(_) => ResponseType::Success("".into()),
                     Err(e) => ResponseType::Err(e.to_string()),
                 },
+                RequestType::SocketReset => {
+                    self.reset();
+                    ResponseType::Success("".into())
+                }
+                RequestType::SocketTrip { load, limit } => match self.trip(*load, *limit) {
+                    Ok(_) => ResponseType::Success("".into()),
+                    Err(e) => ResponseType::Err(e.to_string()),
+                },
                 _ => ResponseType::Err("Wrong request".into()),
             }
         };
+        trace!(
+            device = self.id(),
+            kind = request.req_type().name(),
+            response = ?response_type,
+            "processed request"
+        );
         CommandResponse::new(self.id(), response_type)
     }
 }
@@ -44,6 +178,252 @@ impl Device for Thermometer {
                 _ => ResponseType::Err("Wrong request".into()),
             }
         };
+        trace!(
+            device = self.id(),
+            kind = request.req_type().name(),
+            response = ?response_type,
+            "processed request"
+        );
         CommandResponse::new(self.id(), response_type)
     }
 }
+
+/// Parse socket state from SocketGetState response
+/// Socket state is reported as "State: on, ..." or "State: off, ..."
+pub fn socket_state(response: &CommandResponse) -> Result<SocketState, String> {
+    match response.response() {
+        ResponseType::Success(s) if s.starts_with("State: on") => Ok(SocketState::On),
+        ResponseType::Success(s) if s.starts_with("State: off") => Ok(SocketState::Off),
+        ResponseType::Success(s) => Err(format!("Unknown socket state {s}")),
+        ResponseType::Err(e) => Err(e.clone()),
+        ResponseType::Batch(_) => Err("Unexpected batch response".into()),
+    }
+}
+
+/// Parse power consumption from SocketGetState response
+/// "State: on, power consumption 123.4W"
+pub fn socket_power(response: &CommandResponse) -> Option<f32> {
+    match response.response() {
+        ResponseType::Success(s) => s
+            .split_once("power consumption ")
+            .and_then(|(_, p)| p.trim_end_matches('W').parse().ok()),
+        _ => None,
+    }
+}
+
+/// Makes Device (e.g. Gateway or RemoteDevice) usable as DeviceSwitch
+/// for applying scenes of smart home
+/// Device name in the home is used as request id, room is ignored
+pub struct CommandSwitch<'a>(pub &'a mut dyn Device);
+
+impl DeviceSwitch for CommandSwitch<'_> {
+    fn get_state(&mut self, _room: &str, device: &str) -> Result<SocketState, String> {
+        let response = self
+            .0
+            .process(CommandRequest::builder().socket(device).get_state());
+        socket_state(&response)
+    }
+
+    fn set_state(&mut self, _room: &str, device: &str, state: SocketState) -> Result<(), String> {
+        let request = match state {
+            SocketState::On => CommandRequest::builder().socket(device).turn_on(),
+            SocketState::Off => CommandRequest::builder().socket(device).turn_off(),
+        };
+        self.command(request)
+    }
+
+    fn trip(&mut self, _room: &str, device: &str, load: f32, limit: f32) -> Result<(), String> {
+        self.command(CommandRequest::builder().socket(device).trip(load, limit))
+    }
+}
+
+impl CommandSwitch<'_> {
+    fn command(&mut self, request: CommandRequest) -> Result<(), String> {
+        match self.0.process(request).response() {
+            ResponseType::Success(_) => Ok(()),
+            ResponseType::Err(e) => Err(e.clone()),
+            ResponseType::Batch(_) => Err("Unexpected batch response".into()),
+        }
+    }
+}
+
+/// Device kind is detected by request: socket answers SocketGetState,
+/// thermometer answers ThermGetTemp
+/// Socket is probed first, ThermGetTemp is sent only if the device isn't a socket,
+/// so sockets never get requests of other kind
+impl DeviceStatusProvider for CommandSwitch<'_> {
+    fn get_status(&mut self, _room: &str, device: &str) -> Option<DeviceStatus> {
+        let socket = self
+            .0
+            .process(CommandRequest::builder().socket(device).get_state());
+        if let Ok(state) = socket_state(&socket) {
+            return Some(DeviceStatus {
+                state: DeviceState::Socket(state),
+                reading: socket_power(&socket),
+            });
+        }
+        let therm = self
+            .0
+            .process(CommandRequest::builder().therm(device).get_temp());
+        // Thermometer state is not reported, answering thermometer is on
+        match therm.response() {
+            ResponseType::Success(t) => Some(DeviceStatus {
+                state: DeviceState::Thermometer(ThermometerState::On),
+                reading: t.parse().ok(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandSwitch, Device};
+    use crate::{
+        command::{CommandRequest, CommandResponse, ResponseType},
+        gateway::Gateway,
+    };
+    use smart_home::{
+        devices::{Socket, SocketState, Thermometer, ThermometerState},
+        query::{DeviceHandle, DeviceKind, DeviceState, Query},
+        scene::Scene,
+        DeviceStatusProvider, DeviceSwitch, SmartHome,
+    };
+
+    #[test]
+    fn test_apply_scene() {
+        let mut home = SmartHome::new("My home");
+        home.add_device("bedroom", "s1").unwrap();
+        home.add_device("kitchen", "s2").unwrap();
+        home.add_scene(
+            Scene::new("Day")
+                .with("bedroom", "s1", SocketState::On)
+                .with("kitchen", "s2", SocketState::On),
+        )
+        .unwrap();
+
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        let mut switch = CommandSwitch(&mut gateway);
+        let report = home.apply_scene("Day", &mut switch, true).unwrap();
+        assert_eq!(report.results[1].result, Err("No such device".to_string()));
+        assert_eq!(report.rolled_back[0].device, "s1");
+        assert_eq!(switch.get_state("bedroom", "s1"), Ok(SocketState::Off));
+    }
+
+    /// Device behind transport, records kinds of requests sent to it
+    struct Recorded<D>(D, Vec<&'static str>);
+
+    impl<D: Device> Device for Recorded<D> {
+        fn process(&mut self, request: CommandRequest) -> CommandResponse {
+            self.1.push(request.req_type().name());
+            self.0.process(request)
+        }
+        fn handle(&mut self, request: CommandRequest) -> CommandResponse {
+            self.1.push(request.req_type().name());
+            self.0.handle(request)
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let mut therm = Recorded(Thermometer::new("t1"), vec![]);
+        let status = CommandSwitch(&mut therm)
+            .get_status("bedroom", "t1")
+            .unwrap();
+        assert_eq!(status.state, DeviceState::Thermometer(ThermometerState::On));
+        assert!(status.reading.is_some());
+        assert_eq!(therm.1, ["SocketGetState", "ThermGetTemp"]);
+
+        // Socket doesn't get thermometer request
+        let mut socket = Recorded(Socket::new("s1"), vec![]);
+        let status = CommandSwitch(&mut socket)
+            .get_status("bedroom", "s1")
+            .unwrap();
+        assert_eq!(status.state, DeviceState::Socket(SocketState::Off));
+        assert_eq!(status.reading, Some(0.));
+        assert_eq!(socket.1, ["SocketGetState"]);
+        assert!(CommandSwitch(&mut socket)
+            .get_status("bedroom", "t1")
+            .is_none());
+    }
+
+    #[test]
+    fn test_query() {
+        let mut home = SmartHome::new("My home");
+        home.add_device("bedroom", "s1").unwrap();
+        home.add_device("bedroom", "t1").unwrap();
+        home.add_device("kitchen", "s2").unwrap();
+
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        gateway.add_device("s2", Socket::new("s2"));
+        gateway.add_device("t1", Thermometer::new("t1"));
+        let mut switch = CommandSwitch(&mut gateway);
+        switch.set_state("kitchen", "s2", SocketState::On).unwrap();
+
+        let query = Query::new().kind(DeviceKind::Socket).reading_above(1.);
+        assert_eq!(
+            home.query_with(&query, &mut switch),
+            vec![DeviceHandle::new("kitchen", "s2")]
+        );
+        let query = Query::new().kind(DeviceKind::Thermometer).reading_above(1.);
+        assert_eq!(
+            home.query_with(&query, &mut switch),
+            vec![DeviceHandle::new("bedroom", "t1")]
+        );
+    }
+
+    fn batch_results(response: CommandResponse) -> Vec<ResponseType> {
+        match response.response() {
+            ResponseType::Batch(r) => r.iter().map(|r| r.response().clone()).collect(),
+            r => panic!("Unexpected response {r:?}"),
+        }
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        gateway.add_device("s2", Socket::new("s2"));
+        let batch = CommandRequest::builder()
+            .batch("gw")
+            .command(CommandRequest::builder().socket("s1").turn_on())
+            .command(CommandRequest::builder().socket("s3").turn_on())
+            .command(CommandRequest::builder().socket("s2").turn_on());
+
+        let response = gateway.handle(batch.build());
+        assert_eq!(response.id(), "gw");
+        assert_eq!(
+            batch_results(response),
+            vec![
+                ResponseType::Success("".into()),
+                ResponseType::Err("No such device".into()),
+                ResponseType::Success("".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_atomic_batch() {
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        gateway.add_device("s2", Socket::new("s2"));
+        let batch = CommandRequest::builder()
+            .batch("gw")
+            .command(CommandRequest::builder().socket("s1").turn_on())
+            .command(CommandRequest::builder().socket("s2").get_state())
+            .command(CommandRequest::builder().therm("s2").get_temp())
+            .command(CommandRequest::builder().socket("s2").turn_on())
+            .atomic();
+
+        let results = batch_results(gateway.handle(batch.build()));
+        assert_eq!(results[0], ResponseType::Err("Rolled back".into()));
+        assert!(matches!(results[1], ResponseType::Success(_)));
+        assert_eq!(results[2], ResponseType::Err("Wrong request".into()));
+        assert_eq!(results[3], ResponseType::Err("Not executed".into()));
+        let mut switch = CommandSwitch(&mut gateway);
+        assert_eq!(switch.get_state("", "s1"), Ok(SocketState::Off));
+        assert_eq!(switch.get_state("", "s2"), Ok(SocketState::Off));
+    }
+}
@@ -0,0 +1,195 @@
+/// Errors of network transports
+/// Clients, servers and NetworkDevice return NetworkError, so callers could
+/// tell timeouts, refused connections, malformed messages and device errors apart
+use std::{error::Error, fmt::Display, io};
+
+use smart_home::devices::{SocketError, ThermometerError};
+
+use crate::{auth::AuthError, limit::LimitError};
+
+pub type NetworkResult<T> = std::result::Result<T, NetworkError>;
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(io::Error),                              // Other transport failure
+    Timeout,                                    // No data within timeout
+    ConnectionRefused,                          // Nobody listens at the address
+    Closed,                                     // Connection or server is closed
+    Address(String),                            // Address is not resolved, not found or in use
+    Framing(String),                            // Message doesn't fit the buffer
+    Serialization(serde_json::Error),           // Message is not valid JSON command
+    Tls(rustls::Error),                         // Handshake or certificate failure
+    Auth(AuthError),                            // Request or response is not authenticated
+    Limit(LimitError),                          // Request is rejected by rate limit of the server
+    Device { device: String, message: String }, // Error reported by the device
+}
+
+impl NetworkError {
+    /// Error of the device, e.g. SocketError or ThermometerError
+    pub fn device<E: Display>(device: &str, error: E) -> Self {
+        Self::Device {
+            device: device.to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Timeout => write!(f, "Timed out"),
+            Self::ConnectionRefused => write!(f, "Connection refused"),
+            Self::Closed => write!(f, "Connection closed"),
+            Self::Address(e) => write!(f, "Address error: {e}"),
+            Self::Framing(e) => write!(f, "Framing error: {e}"),
+            Self::Serialization(e) => write!(f, "Serialization error: {e}"),
+            Self::Tls(e) => write!(f, "TLS error: {e}"),
+            Self::Auth(e) => write!(f, "{e}"),
+            Self::Limit(e) => write!(f, "{e}"),
+            Self::Device { device, message } => write!(f, "Device {device} error: {message}"),
+        }
+    }
+}
+
+impl Error for NetworkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Serialization(e) => Some(e),
+            Self::Tls(e) => Some(e),
+            Self::Auth(e) => Some(e),
+            Self::Limit(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NetworkError {
+    /// Socket with read timeout reports WouldBlock on Unix and TimedOut on Windows
+    fn from(value: io::Error) -> Self {
+        match value.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Self::Timeout,
+            io::ErrorKind::ConnectionRefused => Self::ConnectionRefused,
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::BrokenPipe => Self::Closed,
+            _ => Self::Io(value),
+        }
+    }
+}
+
+impl From<serde_json::Error> for NetworkError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Serialization(value)
+    }
+}
+
+impl From<rustls::Error> for NetworkError {
+    fn from(value: rustls::Error) -> Self {
+        Self::Tls(value)
+    }
+}
+
+impl From<AuthError> for NetworkError {
+    fn from(value: AuthError) -> Self {
+        Self::Auth(value)
+    }
+}
+
+impl From<LimitError> for NetworkError {
+    fn from(value: LimitError) -> Self {
+        Self::Limit(value)
+    }
+}
+
+/// Errors of local devices, the device id is unknown here, so kind is used instead
+impl From<SocketError> for NetworkError {
+    fn from(value: SocketError) -> Self {
+        match value {
+            SocketError::Timeout => Self::Timeout,
+            e => Self::device("socket", e),
+        }
+    }
+}
+
+impl From<ThermometerError> for NetworkError {
+    fn from(value: ThermometerError) -> Self {
+        match value {
+            ThermometerError::Timeout => Self::Timeout,
+            e => Self::device("thermometer", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandResponse, Rejected, ResponseType};
+
+    #[test]
+    fn test_io_kinds() {
+        let error = |kind: io::ErrorKind| NetworkError::from(io::Error::from(kind));
+        assert!(matches!(
+            error(io::ErrorKind::WouldBlock),
+            NetworkError::Timeout
+        ));
+        assert!(matches!(
+            error(io::ErrorKind::ConnectionRefused),
+            NetworkError::ConnectionRefused
+        ));
+        assert!(matches!(
+            error(io::ErrorKind::ConnectionReset),
+            NetworkError::Closed
+        ));
+        assert!(matches!(
+            error(io::ErrorKind::PermissionDenied),
+            NetworkError::Io(_)
+        ));
+    }
+
+    #[test]
+    fn test_device_error() {
+        let response = CommandResponse::new("s1", ResponseType::Err("Overload".into()));
+        let error = response.into_result().unwrap_err();
+        assert_eq!(error.to_string(), "Device s1 error: Overload");
+        let response = CommandResponse::new("s1", ResponseType::Success("".into()));
+        assert!(response.into_result().is_ok());
+    }
+
+    #[test]
+    fn test_rejected() {
+        let response = CommandResponse::rejected("s1", Rejected::Auth(AuthError::Expired));
+        assert_eq!(
+            response.response(),
+            &ResponseType::Err(AuthError::Expired.to_string())
+        );
+        assert!(matches!(
+            response.into_result(),
+            Err(NetworkError::Auth(AuthError::Expired))
+        ));
+        // Reason is kept on the wire
+        let buf: Vec<u8> =
+            CommandResponse::rejected("s1", Rejected::Limit(LimitError::TooManyRequests)).into();
+        let response = CommandResponse::response_from(&buf).unwrap();
+        assert!(matches!(
+            response.into_result(),
+            Err(NetworkError::Limit(LimitError::TooManyRequests))
+        ));
+    }
+
+    #[test]
+    fn test_local_device_error() {
+        assert!(matches!(
+            NetworkError::from(SocketError::Timeout),
+            NetworkError::Timeout
+        ));
+        let error = NetworkError::from(ThermometerError::Disconnected);
+        assert_eq!(
+            error.to_string(),
+            "Device thermometer error: Thermometer sensor is disconnected"
+        );
+        let error = NetworkError::from(SocketError::Tripped);
+        assert!(matches!(error, NetworkError::Device { device, .. } if device == "socket"));
+    }
+}
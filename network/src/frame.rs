@@ -0,0 +1,112 @@
+/// Framing of messages on stream transports: TCP, TLS and Unix streams
+/// Each JSON message is preceded by its length as 4-byte big-endian integer,
+/// so a message split into several segments is read completely
+use std::io::{Read, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::error::{NetworkError, NetworkResult};
+
+/// Longest message, bogus length doesn't allocate more
+pub const MAX_FRAME: usize = 1 << 20;
+
+pub(crate) fn write<W: Write, T: Serialize>(stream: &mut W, message: &T) -> NetworkResult<()> {
+    stream.write_all(&encode(message)?)?;
+    stream.flush()?;
+    Ok(())
+}
+
+pub(crate) fn read<R: Read, T: DeserializeOwned>(stream: &mut R) -> NetworkResult<T> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let mut buf = vec![0u8; length(len)?];
+    stream.read_exact(&mut buf)?;
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+pub(crate) async fn write_async<W: AsyncWrite + Unpin, T: Serialize>(
+    stream: &mut W,
+    message: &T,
+) -> NetworkResult<()> {
+    stream.write_all(&encode(message)?).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+pub(crate) async fn read_async<R: AsyncRead + Unpin, T: DeserializeOwned>(
+    stream: &mut R,
+) -> NetworkResult<T> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).await?;
+    let mut buf = vec![0u8; length(len)?];
+    stream.read_exact(&mut buf).await?;
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+fn encode<T: Serialize>(message: &T) -> NetworkResult<Vec<u8>> {
+    let body = serde_json::to_vec(message)?;
+    let len = u32::try_from(body.len())
+        .ok()
+        .filter(|len| *len as usize <= MAX_FRAME)
+        .ok_or_else(|| too_long(body.len()))?;
+    let mut buf = Vec::with_capacity(body.len() + 4);
+    buf.extend(len.to_be_bytes());
+    buf.extend(body);
+    Ok(buf)
+}
+
+fn length(len: [u8; 4]) -> NetworkResult<usize> {
+    match u32::from_be_bytes(len) as usize {
+        len if len > MAX_FRAME => Err(too_long(len)),
+        len => Ok(len),
+    }
+}
+
+fn too_long(len: usize) -> NetworkError {
+    NetworkError::Framing(format!("Message of {len} bytes is longer than {MAX_FRAME}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::command::CommandRequest;
+
+    /// Reader which returns one byte per read, as a stream split into segments
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let end = buf.len().min(1);
+            Read::read(&mut self.0, &mut buf[..end])
+        }
+    }
+
+    #[test]
+    fn test_split_message() {
+        let request = CommandRequest::builder().socket("s1").turn_on();
+        let mut buf = vec![];
+        write(&mut buf, &request).unwrap();
+        write(&mut buf, &request).unwrap();
+        let mut stream = Trickle(Cursor::new(buf));
+        for _ in 0..2 {
+            let received: CommandRequest = read(&mut stream).unwrap();
+            assert_eq!(received, request);
+        }
+        assert!(matches!(
+            read::<_, CommandRequest>(&mut stream),
+            Err(NetworkError::Closed)
+        ));
+    }
+
+    #[test]
+    fn test_too_long() {
+        let mut stream = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        assert!(matches!(
+            read::<_, CommandRequest>(&mut stream),
+            Err(NetworkError::Framing(_))
+        ));
+    }
+}
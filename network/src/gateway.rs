@@ -0,0 +1,75 @@
+/// Provides Gateway structure, which unites several devices
+/// behind one Device implementation
+/// CommandRequest is routed to the device by request id
+use std::collections::HashMap;
+
+use crate::{
+    command::{CommandRequest, CommandResponse, ResponseType},
+    device::Device,
+};
+
+type Devices = HashMap<String, Box<dyn Device + Send + Sync>>;
+
+#[derive(Default)]
+pub struct Gateway {
+    devices: Devices,
+}
+
+impl Gateway {
+    /// Creates Gateway without devices
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register device with id
+    /// Previously registered device with the same id is replaced
+    pub fn add_device<D: Device + Send + Sync + 'static>(&mut self, id: &str, device: D) {
+        self.devices.insert(id.to_string(), Box::new(device));
+    }
+
+    /// Remove device from the gateway, returns true if device existed
+    pub fn remove_device(&mut self, id: &str) -> bool {
+        self.devices.remove(id).is_some()
+    }
+
+    /// Get sorted list of devices ids
+    pub fn devices(&self) -> Vec<&str> {
+        let mut devices = self.devices.keys().map(|d| d.as_str()).collect::<Vec<_>>();
+        devices.sort_unstable();
+        devices
+    }
+}
+
+impl Device for Gateway {
+    fn process(&mut self, request: CommandRequest) -> CommandResponse {
+        match self.devices.get_mut(request.id()) {
+            Some(device) => device.process(request),
+            None => CommandResponse::new(request.id(), ResponseType::Err("No such device".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Gateway;
+    use crate::{
+        command::{CommandRequest, ResponseType},
+        device::Device,
+    };
+    use smart_home::devices::{Socket, Thermometer};
+
+    #[test]
+    fn test_routing() {
+        let mut g = Gateway::new();
+        g.add_device("s1", Socket::new("s1"));
+        g.add_device("t1", Thermometer::new("t1"));
+        assert_eq!(g.devices(), vec!["s1", "t1"]);
+
+        let resp = g.process(CommandRequest::builder().socket("s1").turn_on());
+        assert_eq!(resp.response(), &ResponseType::Success("".into()));
+        let resp = g.process(CommandRequest::builder().therm("t1").get_temp());
+        assert!(matches!(resp.response(), ResponseType::Success(_)));
+        let resp = g.process(CommandRequest::builder().socket("s2").turn_on());
+        assert_eq!(resp.response(), &ResponseType::Err("No such device".into()));
+    }
+}
@@ -0,0 +1,124 @@
+/// Request handling shared by servers: optional rate limits and metrics, tracing
+use std::time::Instant;
+
+use tracing::{debug, debug_span, warn, Span};
+
+use crate::{
+    command::{CommandRequest, CommandResponse, ResponseType},
+    device::Peer,
+    limit::{RateLimiter, Verdict},
+    metrics::{Connection, ServerMetrics},
+};
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub(crate) limiter: Option<RateLimiter>,
+    pub(crate) metrics: Option<ServerMetrics>,
+}
+
+impl Hooks {
+    /// Check request before device is locked
+    pub(crate) fn check(&self, peer: &Peer) -> Verdict {
+        let verdict = self
+            .limiter
+            .as_ref()
+            .map_or(Verdict::Allow, |l| l.check(peer));
+        match &verdict {
+            Verdict::Allow => {}
+            Verdict::Drop => warn!(%peer, "request over the limit is dropped"),
+            Verdict::Reject(e) => warn!(%peer, "request over the limit is rejected: {e}"),
+        }
+        verdict
+    }
+
+    /// Guard of stream connection
+    pub(crate) fn connect(&self) -> Option<Connection> {
+        self.metrics.as_ref().map(|m| m.connect())
+    }
+
+    /// Record and log request of the type received at started
+    pub(crate) fn observe(&self, request: &str, started: Instant, response: &CommandResponse) {
+        let elapsed = started.elapsed();
+        let error = match response.response() {
+            ResponseType::Err(e) => Some(e.as_str()),
+            _ => None,
+        };
+        debug!(
+            device = response.id(),
+            kind = request,
+            elapsed_us = elapsed.as_micros() as u64,
+            error,
+            "handled request"
+        );
+        if let Some(metrics) = &self.metrics {
+            metrics.observe(request, elapsed, response);
+        }
+    }
+}
+
+/// Log request sent by client
+pub(crate) fn sending(request: &CommandRequest) {
+    debug!(
+        device = request.id(),
+        kind = request.req_type().name(),
+        "sending request"
+    );
+}
+
+/// Span of the device processing request, closed span reports its timing
+pub(crate) fn span(request: &CommandRequest) -> Span {
+    debug_span!(
+        "process",
+        device = request.id(),
+        kind = request.req_type().name()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    /// Captured log output
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_observe_log() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let response = CommandResponse::new("s1", ResponseType::Err("Wrong request".into()));
+            Hooks::default().observe("ThermGetTemp", Instant::now(), &response);
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        let fields = &line["fields"];
+        assert_eq!(fields["message"], "handled request");
+        assert_eq!(fields["device"], "s1");
+        assert_eq!(fields["kind"], "ThermGetTemp");
+        assert_eq!(fields["error"], "Wrong request");
+        assert!(fields["elapsed_us"].is_u64());
+    }
+}
@@ -3,8 +3,33 @@ use std::error::Error;
 pub mod r#async;
 pub mod sync;
 
+pub mod acl;
+pub mod audit;
+pub mod auth;
 pub mod command;
-mod device;
+pub mod device;
+pub mod error;
+mod frame;
+pub mod gateway;
+mod hooks;
+pub mod limit;
+#[cfg(feature = "logging")]
+pub mod logging;
+pub mod metrics;
+pub mod mqtt;
+pub mod recorder;
+pub mod rules;
+pub mod scheduler;
+#[cfg(unix)]
+mod socket_file;
+pub mod state;
+pub mod timeseries;
+pub mod tls;
+
+#[cfg(unix)]
+pub use socket_file::DEFAULT_MODE;
 
 pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
-pub const BUFLEN: usize = 1024;
+// Buffer of datagram transports, should fit batch requests and responses
+// Stream transports are framed, see frame::MAX_FRAME
+pub const BUFLEN: usize = 8192;
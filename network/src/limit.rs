@@ -0,0 +1,307 @@
+/// Rate limiting of requests received by servers
+/// Token bucket per source address and optional global bucket,
+/// requests over the limit are dropped, answered with error or the source is banned
+/// Only sources over own limit are banned, requests over global limit are dropped
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::device::Peer;
+
+// Buckets of idle sources are removed when there are more of them,
+// the least recently used bucket is replaced if all sources are active
+const MAX_BUCKETS: usize = 1024;
+// Expired bans are removed when there are more of them,
+// the ban which ends first is replaced if all are active
+const MAX_BANS: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum LimitError {
+    TooManyRequests, // Rate limit is exceeded
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyRequests => write!(f, "Too many requests"),
+        }
+    }
+}
+
+impl Error for LimitError {}
+
+/// What server does with request over the limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Excess {
+    Drop,          // Request is ignored, TCP connection is closed
+    Error,         // Error response is sent without processing
+    Ban(Duration), // Requests from the source are dropped for the duration
+}
+
+/// Result of the check for received request
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Drop,
+    Reject(LimitError),
+}
+
+/// rate requests per second on average, up to burst at once
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+impl Rate {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self { rate, burst }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(rate: &Rate, now: Instant) -> Self {
+        Self {
+            tokens: rate.burst as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, rate: &Rate, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.rate).min(rate.burst as f64);
+        self.updated = now;
+    }
+
+    fn take(&mut self, rate: &Rate, now: Instant) -> bool {
+        self.refill(rate, now);
+        if self.tokens < 1. {
+            return false;
+        }
+        self.tokens -= 1.;
+        true
+    }
+}
+
+#[derive(Default)]
+struct State {
+    sources: HashMap<Option<IpAddr>, Bucket>, // Unix and in-memory peers share one bucket
+    global: Option<Bucket>,
+    bans: HashMap<Option<IpAddr>, Instant>, // Ban end
+}
+
+/// Limiter shared by server connections
+pub struct RateLimiter {
+    per_source: Option<Rate>,
+    global: Option<Rate>,
+    excess: Excess,
+    state: Mutex<State>,
+}
+
+impl RateLimiter {
+    /// Limiter without limits, see per_source and global
+    pub fn new(excess: Excess) -> Self {
+        Self {
+            per_source: None,
+            global: None,
+            excess,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Limit requests from each source address
+    pub fn per_source(mut self, rate: Rate) -> Self {
+        self.per_source = Some(rate);
+        self
+    }
+
+    /// Limit requests from all sources together
+    pub fn global(mut self, rate: Rate) -> Self {
+        self.global = Some(rate);
+        self
+    }
+
+    /// Check request from the peer, should be called before device is locked
+    pub fn check(&self, peer: &Peer) -> Verdict {
+        self.check_at(peer, Instant::now())
+    }
+
+    fn check_at(&self, peer: &Peer, now: Instant) -> Verdict {
+        let mut state = self.state.lock().unwrap();
+        let source = peer.addr;
+        match state.bans.get(&source) {
+            Some(end) if *end > now => return Verdict::Drop,
+            Some(_) => {
+                state.bans.remove(&source);
+            }
+            None => {}
+        }
+
+        if !self.take_source(&mut state, source, now) {
+            if let Excess::Ban(duration) = self.excess {
+                Self::ban(&mut state, source, now, now + duration);
+            }
+            return self.excess();
+        }
+        if !self.take_global(&mut state, now) {
+            // Request is not processed, so it is not counted for the source
+            if let (Some(rate), Some(bucket)) = (&self.per_source, state.sources.get_mut(&source)) {
+                bucket.tokens = (bucket.tokens + 1.).min(rate.burst as f64);
+            }
+            return self.excess();
+        }
+        Verdict::Allow
+    }
+
+    /// Verdict for request over the limit, ban is already applied
+    fn excess(&self) -> Verdict {
+        match self.excess {
+            Excess::Error => Verdict::Reject(LimitError::TooManyRequests),
+            Excess::Drop | Excess::Ban(_) => Verdict::Drop,
+        }
+    }
+
+    fn ban(state: &mut State, source: Option<IpAddr>, now: Instant, end: Instant) {
+        if state.bans.len() >= MAX_BANS && !state.bans.contains_key(&source) {
+            state.bans.retain(|_, end| *end > now);
+            if state.bans.len() >= MAX_BANS {
+                let first = state.bans.iter().min_by_key(|(_, end)| **end);
+                if let Some(first) = first.map(|(s, _)| *s) {
+                    state.bans.remove(&first);
+                }
+            }
+        }
+        state.bans.insert(source, end);
+    }
+
+    fn take_source(&self, state: &mut State, source: Option<IpAddr>, now: Instant) -> bool {
+        let Some(rate) = &self.per_source else {
+            return true;
+        };
+        if state.sources.len() >= MAX_BUCKETS && !state.sources.contains_key(&source) {
+            state.sources.retain(|_, b| {
+                b.refill(rate, now);
+                b.tokens < rate.burst as f64
+            });
+            if state.sources.len() >= MAX_BUCKETS {
+                let oldest = state.sources.iter().min_by_key(|(_, b)| b.updated);
+                if let Some(oldest) = oldest.map(|(s, _)| *s) {
+                    state.sources.remove(&oldest);
+                }
+            }
+        }
+        state
+            .sources
+            .entry(source)
+            .or_insert_with(|| Bucket::new(rate, now))
+            .take(rate, now)
+    }
+
+    fn take_global(&self, state: &mut State, now: Instant) -> bool {
+        let Some(rate) = &self.global else {
+            return true;
+        };
+        state
+            .global
+            .get_or_insert_with(|| Bucket::new(rate, now))
+            .take(rate, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(last: u8) -> Peer {
+        Peer::new([10, 0, 0, last].into())
+    }
+
+    #[test]
+    fn test_per_source() {
+        let limiter = RateLimiter::new(Excess::Error).per_source(Rate::new(1., 2));
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(&peer(1), now), Verdict::Allow);
+        assert_eq!(limiter.check_at(&peer(1), now), Verdict::Allow);
+        assert_eq!(
+            limiter.check_at(&peer(1), now),
+            Verdict::Reject(LimitError::TooManyRequests)
+        );
+        // Other source has own bucket
+        assert_eq!(limiter.check_at(&peer(2), now), Verdict::Allow);
+        // Token is added in a second
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.check_at(&peer(1), later), Verdict::Allow);
+        assert_eq!(
+            limiter.check_at(&peer(1), later),
+            Verdict::Reject(LimitError::TooManyRequests)
+        );
+    }
+
+    #[test]
+    fn test_global() {
+        let limiter = RateLimiter::new(Excess::Drop)
+            .per_source(Rate::new(10., 10))
+            .global(Rate::new(1., 3));
+        let now = Instant::now();
+        for i in 0..3 {
+            assert_eq!(limiter.check_at(&peer(i), now), Verdict::Allow);
+        }
+        assert_eq!(limiter.check_at(&peer(4), now), Verdict::Drop);
+    }
+
+    #[test]
+    fn test_ban() {
+        let ban = Duration::from_secs(10);
+        let limiter = RateLimiter::new(Excess::Ban(ban)).per_source(Rate::new(100., 1));
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(&peer(1), now), Verdict::Allow);
+        assert_eq!(limiter.check_at(&peer(1), now), Verdict::Drop);
+        // Bucket is refilled, but source is still banned
+        let later = now + Duration::from_secs(5);
+        assert_eq!(limiter.check_at(&peer(1), later), Verdict::Drop);
+        assert_eq!(limiter.check_at(&peer(2), later), Verdict::Allow);
+        assert_eq!(limiter.check_at(&peer(1), now + ban), Verdict::Allow);
+    }
+
+    #[test]
+    fn test_global_ban() {
+        let limiter = RateLimiter::new(Excess::Ban(Duration::from_secs(10)))
+            .per_source(Rate::new(0.001, 1))
+            .global(Rate::new(1., 1));
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(&peer(1), now), Verdict::Allow);
+        // Global limit is exceeded, source is not banned and keeps its token
+        assert_eq!(limiter.check_at(&peer(2), now), Verdict::Drop);
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.check_at(&peer(2), later), Verdict::Allow);
+        assert!(limiter.state.lock().unwrap().bans.is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let limiter =
+            RateLimiter::new(Excess::Ban(Duration::from_secs(10))).per_source(Rate::new(0.001, 1));
+        let now = Instant::now();
+        for i in 0..(MAX_BUCKETS + 10) as u32 {
+            let peer = Peer::new(std::net::Ipv4Addr::from(i).into());
+            assert_eq!(limiter.check_at(&peer, now), Verdict::Allow);
+            assert_eq!(limiter.check_at(&peer, now), Verdict::Drop);
+        }
+        let state = limiter.state.lock().unwrap();
+        assert_eq!(state.sources.len(), MAX_BUCKETS);
+        assert_eq!(state.bans.len(), MAX_BANS);
+    }
+}
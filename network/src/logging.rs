@@ -0,0 +1,27 @@
+/// Log output of binaries and examples, available with default "logging" feature
+/// Servers, clients and devices emit tracing spans and events, nothing is printed
+/// until subscriber is installed with init
+///
+/// Configured with environment variables:
+/// SMART_HOME_LOG         filter, e.g. "debug" or "network=trace,smart_home=info" (default "info")
+/// SMART_HOME_LOG_FORMAT  "json" for JSON lines, human readable text otherwise
+///
+/// Example: SMART_HOME_LOG=debug SMART_HOME_LOG_FORMAT=json http-gateway config.json
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+pub const LOG_ENV: &str = "SMART_HOME_LOG";
+pub const FORMAT_ENV: &str = "SMART_HOME_LOG_FORMAT";
+
+/// Install global subscriber writing to stderr, so stdout stays for command output
+/// Does nothing if subscriber is already installed, e.g. by tests
+pub fn init() {
+    let filter = EnvFilter::try_from_env(LOG_ENV).unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE) // Closed span reports its timing
+        .with_writer(std::io::stderr);
+    let _ = match std::env::var(FORMAT_ENV).as_deref() {
+        Ok("json") => builder.json().try_init(),
+        _ => builder.try_init(),
+    };
+}
@@ -0,0 +1,232 @@
+/// Server metrics in Prometheus text format
+/// Servers count requests and errors by request type, measure latency
+/// and track active connections, Exposition renders metric families
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::command::{CommandResponse, ResponseType};
+
+/// Upper bounds of latency histogram buckets, seconds
+pub const LATENCY_BUCKETS: [f64; 10] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.,
+];
+
+/// Builder of Prometheus text exposition
+/// Samples of the family should follow its header
+#[derive(Default)]
+pub struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Header of metric family, kind is "counter", "gauge" or "histogram"
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) -> &mut Self {
+        let _ = writeln!(self.out, "# HELP {name} {help}");
+        let _ = writeln!(self.out, "# TYPE {name} {kind}");
+        self
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) -> &mut Self {
+        let labels = labels
+            .iter()
+            .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+            .collect::<Vec<_>>();
+        let _ = match labels.is_empty() {
+            true => writeln!(self.out, "{name} {value}"),
+            false => writeln!(self.out, "{name}{{{}}} {value}", labels.join(",")),
+        };
+        self
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[derive(Default, Clone)]
+struct RequestStats {
+    requests: u64,
+    errors: u64,
+    buckets: [u64; LATENCY_BUCKETS.len()], // Not cumulative
+    sum: f64,
+}
+
+#[derive(Default)]
+struct State {
+    requests: BTreeMap<String, RequestStats>, // By request type
+    connections: u64,
+}
+
+/// Metrics of one server, cloned handle updates the same metrics
+#[derive(Clone)]
+pub struct ServerMetrics {
+    server: String, // Label value
+    state: Arc<Mutex<State>>,
+}
+
+impl ServerMetrics {
+    pub fn new(server: &str) -> Self {
+        Self {
+            server: server.to_string(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Count connection as active until guard is dropped
+    pub fn connect(&self) -> Connection {
+        self.state.lock().unwrap().connections += 1;
+        Connection(self.clone())
+    }
+
+    /// Record handled request, response with error is counted as error
+    pub fn observe(&self, request: &str, latency: Duration, response: &CommandResponse) {
+        let mut state = self.state.lock().unwrap();
+        let stats = state.requests.entry(request.to_string()).or_default();
+        stats.requests += 1;
+        if let ResponseType::Err(_) = response.response() {
+            stats.errors += 1;
+        }
+        let seconds = latency.as_secs_f64();
+        stats.sum += seconds;
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|b| seconds <= *b) {
+            stats.buckets[i] += 1;
+        }
+    }
+
+    pub fn requests(&self, request: &str) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.requests.get(request).map_or(0, |s| s.requests)
+    }
+
+    pub fn errors(&self, request: &str) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.requests.get(request).map_or(0, |s| s.errors)
+    }
+
+    pub fn connections(&self) -> u64 {
+        self.state.lock().unwrap().connections
+    }
+
+    /// Text exposition of the server metrics
+    pub fn render(&self) -> String {
+        let mut exposition = Exposition::new();
+        render_servers(&mut exposition, std::slice::from_ref(self));
+        exposition.finish()
+    }
+}
+
+/// Guard of active connection
+pub struct Connection(ServerMetrics);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().connections -= 1;
+    }
+}
+
+/// Write metric families of several servers, samples are labelled by server
+pub fn render_servers(exposition: &mut Exposition, servers: &[ServerMetrics]) {
+    let snapshots = servers
+        .iter()
+        .map(|s| {
+            let state = s.state.lock().unwrap();
+            (s.server.as_str(), state.requests.clone(), state.connections)
+        })
+        .collect::<Vec<_>>();
+
+    let counters = [
+        ("smart_home_server_requests_total", "Handled requests"),
+        (
+            "smart_home_server_errors_total",
+            "Requests with error response",
+        ),
+    ];
+    for (i, (name, help)) in counters.into_iter().enumerate() {
+        exposition.family(name, "counter", help);
+        for (server, requests, _) in &snapshots {
+            for (request, stats) in requests {
+                let value = if i == 0 { stats.requests } else { stats.errors };
+                let labels = [("server", *server), ("type", request.as_str())];
+                exposition.sample(name, &labels, value as f64);
+            }
+        }
+    }
+
+    let name = "smart_home_server_request_duration_seconds";
+    exposition.family(name, "histogram", "Request handling latency");
+    for (server, requests, _) in &snapshots {
+        for (request, stats) in requests {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                cumulative += count;
+                let le = bound.to_string();
+                let labels = [("server", *server), ("type", request), ("le", &le)];
+                exposition.sample(&format!("{name}_bucket"), &labels, cumulative as f64);
+            }
+            let labels = [("server", *server), ("type", request), ("le", "+Inf")];
+            exposition.sample(&format!("{name}_bucket"), &labels, stats.requests as f64);
+            let labels = [("server", *server), ("type", request.as_str())];
+            exposition.sample(&format!("{name}_sum"), &labels, stats.sum);
+            exposition.sample(&format!("{name}_count"), &labels, stats.requests as f64);
+        }
+    }
+
+    let name = "smart_home_server_active_connections";
+    exposition.family(name, "gauge", "Open stream connections");
+    for (server, _, connections) in &snapshots {
+        exposition.sample(name, &[("server", server)], *connections as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = ServerMetrics::new("tcp");
+        let ok = CommandResponse::new("s1", ResponseType::Success("".into()));
+        let err = CommandResponse::new("s1", ResponseType::Err("Wrong request".into()));
+        metrics.observe("SocketTurnOn", Duration::from_millis(2), &ok);
+        metrics.observe("SocketTurnOn", Duration::from_secs(2), &err);
+        let connection = metrics.connect();
+        assert_eq!(metrics.connections(), 1);
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE smart_home_server_requests_total counter",
+            r#"smart_home_server_requests_total{server="tcp",type="SocketTurnOn"} 2"#,
+            r#"smart_home_server_errors_total{server="tcp",type="SocketTurnOn"} 1"#,
+            r#"smart_home_server_request_duration_seconds_bucket{server="tcp",type="SocketTurnOn",le="0.001"} 0"#,
+            r#"smart_home_server_request_duration_seconds_bucket{server="tcp",type="SocketTurnOn",le="0.0025"} 1"#,
+            r#"smart_home_server_request_duration_seconds_bucket{server="tcp",type="SocketTurnOn",le="+Inf"} 2"#,
+            r#"smart_home_server_active_connections{server="tcp"} 1"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} is missing");
+        }
+        drop(connection);
+        assert_eq!(metrics.connections(), 0);
+    }
+
+    #[test]
+    fn test_escape() {
+        let mut exposition = Exposition::new();
+        exposition.sample("m", &[("room", "a \"b\"")], 1.);
+        assert_eq!(exposition.finish(), "m{room=\"a \\\"b\\\"\"} 1\n");
+    }
+}
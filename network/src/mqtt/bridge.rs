@@ -0,0 +1,185 @@
+/// Bridge between MQTT topics and devices
+///
+/// Published (retained):
+///   <prefix>/bridge/status                 "online", "offline" as last will
+///   <prefix>/<room>/<device>/availability  "online" or "offline" if device doesn't respond
+///   <prefix>/<room>/<device>/state         socket state "on" or "off"
+///   <prefix>/<room>/<device>/reading       socket power consumption or temperature
+///   <prefix>/<room>/<device>/error         command error (not retained)
+/// Subscribed:
+///   <prefix>/<room>/<device>/set           socket command "on" or "off"
+///
+/// Room and device names are escaped with topic_level, e.g. "A/C" is "A%2FC"
+use std::{collections::HashMap, time::Duration};
+
+use smart_home::{devices::SocketState, query::DeviceKind};
+
+use super::{topic_level, Message, MqttClient, Will};
+use crate::{
+    command::{CommandRequest, ResponseType},
+    device::{socket_power, socket_state, Device},
+    Result,
+};
+
+struct BridgedDevice {
+    topic: String, // <prefix>/<room>/<device>
+    id: String,
+    kind: DeviceKind,
+    inner: Box<dyn Device + Send>,
+}
+
+pub struct MqttBridge<C: MqttClient> {
+    client: C,
+    prefix: String,
+    devices: Vec<BridgedDevice>,
+    published: HashMap<String, String>, // Last retained payloads
+}
+
+impl<C: MqttClient> MqttBridge<C> {
+    /// Last will which should be set on the client connection
+    pub fn will(prefix: &str) -> Will {
+        Will {
+            topic: format!("{prefix}/bridge/status"),
+            payload: "offline".into(),
+        }
+    }
+
+    /// Announce bridge and subscribe to command topics
+    pub fn new(mut client: C, prefix: &str) -> Result<Self> {
+        client.publish(&format!("{prefix}/bridge/status"), "online", true)?;
+        client.subscribe(&format!("{prefix}/+/+/set"))?;
+        Ok(Self {
+            client,
+            prefix: prefix.to_string(),
+            devices: vec![],
+            published: HashMap::default(),
+        })
+    }
+
+    /// Bridge local device or RemoteDevice,
+    /// id is used in CommandRequest, room and device name in topics
+    pub fn add_device<D: Device + Send + 'static>(
+        &mut self,
+        room: &str,
+        device: &str,
+        id: &str,
+        kind: DeviceKind,
+        inner: D,
+    ) {
+        self.devices.push(BridgedDevice {
+            topic: format!(
+                "{}/{}/{}",
+                self.prefix,
+                topic_level(room),
+                topic_level(device)
+            ),
+            id: id.to_string(),
+            kind,
+            inner: Box::new(inner),
+        });
+    }
+
+    /// Handle commands received within timeout, then publish states
+    pub fn run_once(&mut self, timeout: Duration) -> Result<()> {
+        if let Some(message) = self.client.poll(timeout)? {
+            self.command(message)?;
+            // Drain commands which are already received
+            while let Some(message) = self.client.poll(Duration::ZERO)? {
+                self.command(message)?;
+            }
+        }
+        self.publish_states()
+    }
+
+    /// Run until client error, states are published at least once per interval
+    pub fn run(&mut self, interval: Duration) -> Result<()> {
+        loop {
+            self.run_once(interval)?;
+        }
+    }
+
+    /// Request all devices and publish changed values
+    pub fn publish_states(&mut self) -> Result<()> {
+        for i in 0..self.devices.len() {
+            self.publish_state(i)?;
+        }
+        Ok(())
+    }
+
+    fn publish_state(&mut self, index: usize) -> Result<()> {
+        let d = &mut self.devices[index];
+        let topic = d.topic.clone();
+        let request = match d.kind {
+            DeviceKind::Socket => CommandRequest::builder().socket(&d.id).get_state(),
+            DeviceKind::Thermometer => CommandRequest::builder().therm(&d.id).get_temp(),
+        };
+        let response = d.inner.handle(request);
+        let mut values = vec![];
+        match (d.kind, response.response()) {
+            (_, ResponseType::Err(_)) => (),
+            (DeviceKind::Socket, _) => {
+                if let Ok(state) = socket_state(&response) {
+                    let state = match state {
+                        SocketState::On => "on",
+                        SocketState::Off => "off",
+                    };
+                    values.push(("state", state.to_string()));
+                }
+                if let Some(power) = socket_power(&response) {
+                    values.push(("reading", format!("{power:.1}")));
+                }
+            }
+            (DeviceKind::Thermometer, ResponseType::Success(t)) => {
+                if let Ok(t) = t.parse::<f32>() {
+                    values.push(("reading", format!("{t:.1}")));
+                }
+            }
+            (DeviceKind::Thermometer, _) => (),
+        }
+        let availability = if values.is_empty() {
+            "offline"
+        } else {
+            "online"
+        };
+        values.push(("availability", availability.into()));
+        for (name, value) in values {
+            self.publish_retained(&format!("{topic}/{name}"), value)?;
+        }
+        Ok(())
+    }
+
+    fn publish_retained(&mut self, topic: &str, payload: String) -> Result<()> {
+        if self.published.get(topic) != Some(&payload) {
+            self.client.publish(topic, &payload, true)?;
+            self.published.insert(topic.to_string(), payload);
+        }
+        Ok(())
+    }
+
+    /// Translate command message into CommandRequest, errors are published to error topic
+    fn command(&mut self, message: Message) -> Result<()> {
+        let topic = message.topic.strip_suffix("/set").unwrap_or_default();
+        let Some(index) = self.devices.iter().position(|d| d.topic == topic) else {
+            return Ok(());
+        };
+        let d = &mut self.devices[index];
+        let request = CommandRequest::builder().socket(&d.id);
+        let request = match (d.kind, message.payload.to_lowercase().as_str()) {
+            (DeviceKind::Socket, "on") => Some(request.turn_on()),
+            (DeviceKind::Socket, "off") => Some(request.turn_off()),
+            _ => None,
+        };
+        let error = match request.map(|r| d.inner.handle(r)) {
+            None => Some(format!("Wrong command {}", message.payload)),
+            Some(response) => match response.response() {
+                ResponseType::Err(e) => Some(e.clone()),
+                _ => None,
+            },
+        };
+        if let Some(error) = error {
+            self.client
+                .publish(&format!("{topic}/error"), &error, false)?;
+        }
+        self.publish_state(index)
+    }
+}
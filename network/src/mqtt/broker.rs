@@ -0,0 +1,218 @@
+/// In-process broker stand-in with retained messages and last will,
+/// used for tests and for bridging inside one application
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use super::{topic_matches, Message, MqttClient, Will};
+use crate::Result;
+
+struct Session {
+    filters: Vec<String>,
+    tx: Sender<Message>,
+    will: Option<Will>,
+}
+
+#[derive(Default)]
+struct BrokerState {
+    next_id: usize,
+    sessions: HashMap<usize, Session>,
+    retained: HashMap<String, String>,
+}
+
+impl BrokerState {
+    fn publish(&mut self, message: Message) {
+        if message.retain {
+            // Empty retained message clears the topic
+            if message.payload.is_empty() {
+                self.retained.remove(&message.topic);
+            } else {
+                self.retained
+                    .insert(message.topic.clone(), message.payload.clone());
+            }
+        }
+        let message = Message {
+            retain: false,
+            ..message
+        };
+        for session in self.sessions.values() {
+            if session
+                .filters
+                .iter()
+                .any(|f| topic_matches(f, &message.topic))
+            {
+                // Receiver is dropped only with the session
+                let _ = session.tx.send(message.clone());
+            }
+        }
+    }
+}
+
+/// Handle to the broker, clones share the same broker
+#[derive(Default, Clone)]
+pub struct LocalBroker {
+    inner: Arc<Mutex<BrokerState>>,
+}
+
+impl LocalBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// New client connection, will is published when client is dropped without disconnect
+    pub fn connect(&self, will: Option<Will>) -> LocalClient {
+        let (tx, rx) = channel();
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        let session = Session {
+            filters: vec![],
+            tx,
+            will,
+        };
+        inner.sessions.insert(id, session);
+        LocalClient {
+            broker: self.clone(),
+            id,
+            rx,
+        }
+    }
+
+    /// Retained message of the topic
+    pub fn retained(&self, topic: &str) -> Option<String> {
+        self.inner.lock().unwrap().retained.get(topic).cloned()
+    }
+}
+
+pub struct LocalClient {
+    broker: LocalBroker,
+    id: usize,
+    rx: Receiver<Message>,
+}
+
+impl LocalClient {
+    /// Graceful disconnect, last will is not published
+    pub fn disconnect(self) {
+        let mut inner = self.broker.inner.lock().unwrap();
+        if let Some(session) = inner.sessions.get_mut(&self.id) {
+            session.will = None;
+        }
+    }
+}
+
+/// Connection is lost
+impl Drop for LocalClient {
+    fn drop(&mut self) {
+        let mut inner = self.broker.inner.lock().unwrap();
+        let will = inner.sessions.remove(&self.id).and_then(|s| s.will);
+        if let Some(will) = will {
+            inner.publish(Message {
+                topic: will.topic,
+                payload: will.payload,
+                retain: true,
+            });
+        }
+    }
+}
+
+impl MqttClient for LocalClient {
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<()> {
+        let message = Message {
+            topic: topic.to_string(),
+            payload: payload.to_string(),
+            retain,
+        };
+        self.broker.inner.lock().unwrap().publish(message);
+        Ok(())
+    }
+
+    /// Retained messages of matching topics are delivered at once
+    fn subscribe(&mut self, filter: &str) -> Result<()> {
+        let mut inner = self.broker.inner.lock().unwrap();
+        let retained = inner
+            .retained
+            .iter()
+            .filter(|(topic, _)| topic_matches(filter, topic))
+            .map(|(topic, payload)| Message {
+                topic: topic.clone(),
+                payload: payload.clone(),
+                retain: true,
+            })
+            .collect::<Vec<_>>();
+        let session = inner.sessions.get_mut(&self.id).ok_or("Not connected")?;
+        session.filters.push(filter.to_string());
+        for message in retained {
+            session.tx.send(message)?;
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(message) => Ok(Some(message)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::LocalBroker;
+    use crate::mqtt::{Message, MqttClient, Will};
+
+    const TIMEOUT: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn test_broker() {
+        let broker = LocalBroker::new();
+        let mut publisher = broker.connect(None);
+        let mut subscriber = broker.connect(None);
+        publisher.publish("home/a/state", "on", true).unwrap();
+        publisher.publish("home/b/state", "off", false).unwrap();
+
+        // Retained message is delivered on subscribe
+        subscriber.subscribe("home/+/state").unwrap();
+        let retained = subscriber.poll(TIMEOUT).unwrap().unwrap();
+        assert_eq!((retained.payload.as_str(), retained.retain), ("on", true));
+        assert_eq!(subscriber.poll(TIMEOUT).unwrap(), None);
+
+        publisher.publish("home/b/state", "on", false).unwrap();
+        publisher.publish("home/b/power", "10", false).unwrap();
+        assert_eq!(
+            subscriber.poll(TIMEOUT).unwrap(),
+            Some(Message {
+                topic: "home/b/state".into(),
+                payload: "on".into(),
+                retain: false
+            })
+        );
+        assert_eq!(subscriber.poll(TIMEOUT).unwrap(), None);
+
+        // Empty retained message clears the topic
+        publisher.publish("home/a/state", "", true).unwrap();
+        assert_eq!(broker.retained("home/a/state"), None);
+    }
+
+    #[test]
+    fn test_will() {
+        let broker = LocalBroker::new();
+        let will = || {
+            Some(Will {
+                topic: "status".into(),
+                payload: "offline".into(),
+            })
+        };
+        broker.connect(will()).disconnect();
+        assert_eq!(broker.retained("status"), None);
+        drop(broker.connect(will()));
+        assert_eq!(broker.retained("status"), Some("offline".into()));
+    }
+}
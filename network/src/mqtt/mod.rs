@@ -0,0 +1,91 @@
+/// MQTT bridge for devices
+/// Device state is published to <prefix>/<room>/<device>/... topics,
+/// commands from <prefix>/<room>/<device>/set are translated into CommandRequest
+use std::time::Duration;
+
+use crate::Result;
+
+mod bridge;
+mod broker;
+mod rumqtt;
+
+pub use bridge::MqttBridge;
+pub use broker::{LocalBroker, LocalClient};
+pub use rumqtt::RumqttClient;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+/// Last will, published retained by broker when client connection is lost
+#[derive(Debug, Clone, PartialEq)]
+pub struct Will {
+    pub topic: String,
+    pub payload: String,
+}
+
+/// Connection to MQTT broker
+pub trait MqttClient {
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<()>;
+    /// Subscribe to topic filter, + and # wildcards are supported
+    fn subscribe(&mut self, filter: &str) -> Result<()>;
+    /// Wait for incoming message, None if there is no message within timeout
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Message>>;
+}
+
+/// Room or device name as one topic level
+/// Level separator, wildcards and % are percent-encoded, e.g. "A/C" is "A%2FC"
+pub fn topic_level(name: &str) -> String {
+    let mut level = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '%' => level.push_str("%25"),
+            '/' => level.push_str("%2F"),
+            '+' => level.push_str("%2B"),
+            '#' => level.push_str("%23"),
+            c => level.push(c),
+        }
+    }
+    level
+}
+
+/// Check topic against filter with + (one level) and # (all remaining levels) wildcards
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic = topic.split('/');
+    for f in filter.split('/') {
+        match (f, topic.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => (),
+            (f, Some(t)) if f == t => (),
+            _ => return false,
+        }
+    }
+    topic.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{topic_level, topic_matches};
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("home/+/+/set", "home/Kitchen/Socket/set"));
+        assert!(!topic_matches("home/+/+/set", "home/Kitchen/Socket/state"));
+        assert!(!topic_matches("home/+/set", "home/Kitchen/Socket/set"));
+        assert!(topic_matches("home/#", "home/Kitchen/Socket/state"));
+        assert!(topic_matches("home/#", "home"));
+        assert!(topic_matches("home/bridge/status", "home/bridge/status"));
+        assert!(!topic_matches("home/bridge", "home/bridge/status"));
+    }
+
+    #[test]
+    fn test_topic_level() {
+        assert_eq!(topic_level("Kitchen"), "Kitchen");
+        assert_eq!(topic_level("A/C #1+2 100%"), "A%2FC %231%2B2 100%25");
+        let topic = format!("home/{}/{}/set", topic_level("A/C"), topic_level("#"));
+        assert!(topic_matches("home/+/+/set", &topic));
+    }
+}
@@ -0,0 +1,228 @@
+/// Connection to real MQTT broker with rumqttc
+/// Lost connection is reestablished by rumqttc event loop on the next poll,
+/// subscriptions and retained messages are renewed after reconnect
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rumqttc::{Client, Connection, Event, LastWill, MqttOptions, Packet, QoS, RecvTimeoutError};
+use tracing::{debug, warn};
+
+use super::{Message, MqttClient, Will};
+use crate::Result;
+
+// Requests queued until connection is polled
+const CAPACITY: usize = 64;
+// Pause before the next connection attempt
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+pub struct RumqttClient {
+    client: Client,
+    connection: Connection,
+    filters: Vec<String>,
+    retained: HashMap<String, String>, // Renewed after reconnect
+    connected: bool,
+    reconnect_delay: Duration,
+}
+
+impl RumqttClient {
+    /// Connection is established on the first poll
+    pub fn new(id: &str, host: &str, port: u16, will: Option<Will>) -> Self {
+        let mut options = MqttOptions::new(id, host, port);
+        options.set_keep_alive(Duration::from_secs(5));
+        if let Some(will) = will {
+            options.set_last_will(LastWill::new(
+                will.topic,
+                will.payload,
+                QoS::AtLeastOnce,
+                true,
+            ));
+        }
+        let (client, connection) = Client::new(options, CAPACITY);
+        Self {
+            client,
+            connection,
+            filters: vec![],
+            retained: HashMap::default(),
+            connected: false,
+            reconnect_delay: RECONNECT_DELAY,
+        }
+    }
+
+    /// Pause between connection attempts
+    pub fn with_reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+
+    /// Broker doesn't keep subscriptions of clean session,
+    /// retained status could be replaced with last will
+    fn renew(&mut self) -> Result<()> {
+        for filter in &self.filters {
+            self.client.subscribe(filter, QoS::AtLeastOnce)?;
+        }
+        for (topic, payload) in &self.retained {
+            self.client
+                .publish(topic, QoS::AtLeastOnce, true, payload.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl MqttClient for RumqttClient {
+    fn publish(&mut self, topic: &str, payload: &str, retain: bool) -> Result<()> {
+        self.client
+            .publish(topic, QoS::AtLeastOnce, retain, payload.as_bytes())?;
+        if retain {
+            self.retained.insert(topic.to_string(), payload.to_string());
+        }
+        Ok(())
+    }
+
+    fn subscribe(&mut self, filter: &str) -> Result<()> {
+        self.client.subscribe(filter, QoS::AtLeastOnce)?;
+        self.filters.push(filter.to_string());
+        Ok(())
+    }
+
+    /// Drive the connection until incoming publish or timeout
+    /// Connection errors are not returned, connection is retried until timeout
+    fn poll(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.connection.recv_timeout(left) {
+                Ok(Ok(Event::Incoming(Packet::Publish(p)))) => {
+                    return Ok(Some(Message {
+                        topic: p.topic,
+                        payload: String::from_utf8_lossy(&p.payload).to_string(),
+                        retain: p.retain,
+                    }))
+                }
+                Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                    if self.connected {
+                        debug!("reconnected to broker");
+                        self.renew()?;
+                    }
+                    self.connected = true;
+                }
+                Ok(Ok(_)) => continue,
+                Ok(Err(e)) => {
+                    warn!(error = %e, "broker connection failed");
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Ok(None);
+                    }
+                    thread::sleep(self.reconnect_delay.min(left));
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => return Err("Disconnected".into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+    };
+
+    use super::*;
+
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).ok()?;
+        let header = byte[0];
+        let (mut len, mut shift) = (0usize, 0);
+        loop {
+            stream.read_exact(&mut byte).ok()?;
+            len |= ((byte[0] & 0x7f) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).ok()?;
+        Some((header, body))
+    }
+
+    /// Minimal broker: acknowledges packets, reports retained publishes,
+    /// sends one message after subscribe and drops the first connection
+    fn broker(listener: TcpListener, retained: mpsc::Sender<(usize, String)>) {
+        for (n, stream) in listener.incoming().take(2).enumerate() {
+            let mut stream = stream.unwrap();
+            while let Some((header, body)) = read_packet(&mut stream) {
+                match header >> 4 {
+                    1 => stream.write_all(&[0x20, 2, 0, 0]).unwrap(),
+                    3 => {
+                        let len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                        let topic = String::from_utf8_lossy(&body[2..2 + len]).to_string();
+                        if header & 0x01 != 0 {
+                            let _ = retained.send((n, topic));
+                        }
+                        if header & 0x06 != 0 {
+                            stream
+                                .write_all(&[0x40, 2, body[2 + len], body[3 + len]])
+                                .unwrap();
+                        }
+                    }
+                    8 => {
+                        stream.write_all(&[0x90, 3, body[0], body[1], 1]).unwrap();
+                        let payload = n.to_string();
+                        let mut publish = vec![0x30, (2 + 6 + payload.len()) as u8, 0, 6];
+                        publish.extend(b"topic1");
+                        publish.extend(payload.as_bytes());
+                        stream.write_all(&publish).unwrap();
+                        if n == 0 {
+                            break;
+                        }
+                    }
+                    12 => stream.write_all(&[0xd0, 0]).unwrap(),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || broker(listener, tx));
+
+        let mut client = RumqttClient::new("test", "127.0.0.1", port, None)
+            .with_reconnect_delay(Duration::from_millis(10));
+        client.publish("status", "online", true).unwrap();
+        client.subscribe("topic1").unwrap();
+        let receive = |client: &mut RumqttClient| loop {
+            if let Some(message) = client.poll(Duration::from_secs(5)).unwrap() {
+                return message;
+            }
+        };
+        assert_eq!(receive(&mut client).payload, "0");
+        // Connection is lost, but message from renewed subscription is received
+        assert_eq!(receive(&mut client).payload, "1");
+        let timeout = Duration::from_secs(5);
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), (0, "status".into()));
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), (1, "status".into()));
+    }
+
+    #[test]
+    fn test_no_broker() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut client = RumqttClient::new("test", "127.0.0.1", port, None)
+            .with_reconnect_delay(Duration::from_millis(10));
+        assert_eq!(client.poll(Duration::from_millis(100)).unwrap(), None);
+    }
+}
@@ -0,0 +1,195 @@
+/// Periodic sampling of device readings into TimeSeries
+/// Readings are requested with commands, so local devices, Gateway or RemoteDevice
+/// could be sampled; recorder is ticked by the caller, same as Scheduler
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, TimeDelta};
+
+use crate::{
+    command::{CommandRequest, ResponseType},
+    device::{socket_power, Device},
+    scheduler::{Clock, SchedulerError, SystemClock},
+    timeseries::{Metric, TimeSeries},
+    Result,
+};
+
+struct Sampling {
+    device: String,
+    metric: Metric,
+    every: TimeDelta,
+    next: NaiveDateTime,
+}
+
+/// Result of sampling
+#[derive(Debug, PartialEq)]
+pub struct Reading {
+    pub device: String,
+    pub metric: Metric,
+    pub value: std::result::Result<f32, String>,
+}
+
+pub struct Recorder<C: Clock = SystemClock> {
+    clock: C,
+    store: TimeSeries,
+    samplings: Vec<Sampling>,
+    compact_every: TimeDelta,
+    next_compact: NaiveDateTime,
+}
+
+impl Recorder<SystemClock> {
+    pub fn new(store: TimeSeries) -> Self {
+        Self::with_clock(store, SystemClock)
+    }
+}
+
+impl<C: Clock> Recorder<C> {
+    /// Retention policy of the store is applied hourly
+    pub fn with_clock(store: TimeSeries, clock: C) -> Self {
+        let compact_every = TimeDelta::hours(1);
+        Self {
+            next_compact: clock.now() + compact_every,
+            clock,
+            store,
+            samplings: vec![],
+            compact_every,
+        }
+    }
+
+    /// Sample metric of the device every interval, first sample is taken on the next tick
+    pub fn add(
+        &mut self,
+        device: &str,
+        metric: Metric,
+        every: Duration,
+    ) -> std::result::Result<(), SchedulerError> {
+        let every = TimeDelta::from_std(every)
+            .ok()
+            .filter(|e| !e.is_zero())
+            .ok_or_else(|| SchedulerError::BadInterval(device.to_string()))?;
+        self.samplings.push(Sampling {
+            device: device.to_string(),
+            metric,
+            every,
+            next: self.clock.now(),
+        });
+        Ok(())
+    }
+
+    pub fn store(&self) -> &TimeSeries {
+        &self.store
+    }
+
+    /// Take due samples, failed readings are reported and not stored
+    pub fn tick(&mut self, device: &mut dyn Device) -> Result<Vec<Reading>> {
+        let now = self.clock.now();
+        let mut readings = vec![];
+        for sampling in self.samplings.iter_mut().filter(|s| s.next <= now) {
+            let value = read(device, &sampling.device, sampling.metric);
+            if let Ok(value) = value {
+                self.store
+                    .record(&sampling.device, sampling.metric, now, value)?;
+            }
+            readings.push(Reading {
+                device: sampling.device.clone(),
+                metric: sampling.metric,
+                value,
+            });
+            // Missed samples are not repeated, next one is the first slot after now
+            let next = (now - sampling.next)
+                .num_nanoseconds()
+                .zip(sampling.every.num_nanoseconds())
+                .and_then(|(passed, every)| (passed / every + 1).checked_mul(every))
+                .and_then(|n| sampling.next.checked_add_signed(TimeDelta::nanoseconds(n)));
+            sampling.next = next
+                .or_else(|| now.checked_add_signed(sampling.every))
+                .unwrap_or(NaiveDateTime::MAX);
+        }
+        if self.next_compact <= now {
+            self.store.compact(now)?;
+            self.next_compact = now + self.compact_every;
+        }
+        Ok(readings)
+    }
+}
+
+fn read(device: &mut dyn Device, id: &str, metric: Metric) -> std::result::Result<f32, String> {
+    let response = match metric {
+        Metric::Temperature => device.process(CommandRequest::builder().therm(id).get_temp()),
+        Metric::Power => device.process(CommandRequest::builder().socket(id).get_state()),
+    };
+    match (metric, response.response()) {
+        (_, ResponseType::Err(e)) => Err(e.clone()),
+        (Metric::Temperature, ResponseType::Success(t)) => {
+            t.parse().map_err(|_| format!("Wrong temperature {t}"))
+        }
+        (Metric::Power, _) => socket_power(&response).ok_or("No power consumption".to_string()),
+        (_, _) => Err("Unexpected response".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use smart_home::devices::{Socket, Thermometer};
+
+    use super::*;
+    use crate::{gateway::Gateway, scheduler::ManualClock};
+
+    #[test]
+    fn test_recorder() -> Result<()> {
+        let start = NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let clock = ManualClock::new(start);
+        let mut recorder = Recorder::with_clock(TimeSeries::new(), clock.clone());
+        recorder.add("t1", Metric::Temperature, Duration::from_secs(60))?;
+        recorder.add("s1", Metric::Power, Duration::from_secs(120))?;
+        recorder.add("s2", Metric::Power, Duration::from_secs(60))?;
+        assert!(recorder.add("s1", Metric::Power, Duration::ZERO).is_err());
+
+        let mut gateway = Gateway::new();
+        gateway.add_device("t1", Thermometer::new("t1"));
+        let mut socket = Socket::new("s1");
+        socket.turn_on().unwrap();
+        gateway.add_device("s1", socket);
+
+        for _ in 0..4 {
+            let readings = recorder.tick(&mut gateway)?;
+            assert!(readings
+                .iter()
+                .all(|r| r.device != "s2" || r.value.is_err()));
+            clock.advance(Duration::from_secs(60));
+        }
+        let end = start + TimeDelta::minutes(4);
+        let store = recorder.store();
+        assert_eq!(store.range("t1", Metric::Temperature, start, end).len(), 4);
+        assert_eq!(store.range("s1", Metric::Power, start, end).len(), 2);
+        assert!(store.range("s2", Metric::Power, start, end).is_empty());
+        let power = store.aggregate("s1", Metric::Power, start, end).unwrap();
+        assert!(power.min > 0.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_missed_samples() -> Result<()> {
+        let start = NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let clock = ManualClock::new(start);
+        let mut recorder = Recorder::with_clock(TimeSeries::new(), clock.clone());
+        recorder.add("t1", Metric::Temperature, Duration::from_nanos(3))?;
+        let mut gateway = Gateway::new();
+        gateway.add_device("t1", Thermometer::new("t1"));
+
+        recorder.tick(&mut gateway)?;
+        // Year of missed samples is skipped at once
+        clock.advance(Duration::from_secs(365 * 24 * 3600));
+        assert_eq!(recorder.tick(&mut gateway)?.len(), 1);
+        assert!(recorder.tick(&mut gateway)?.is_empty());
+        clock.advance(Duration::from_nanos(3));
+        assert_eq!(recorder.tick(&mut gateway)?.len(), 1);
+        Ok(())
+    }
+}
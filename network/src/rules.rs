@@ -0,0 +1,377 @@
+/// Automation rules engine
+/// Rule has condition over device readings and states and actions
+/// (CommandRequest) which are issued when condition becomes true.
+/// Devices are accessed through Device trait, so rules work with local devices,
+/// Gateway or RemoteDevice with the same code
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use crate::{
+    command::{CommandRequest, CommandResponse, ResponseType},
+    device::{socket_state, Device},
+};
+use smart_home::devices::SocketState;
+
+#[derive(Debug, PartialEq)]
+pub enum RulesError {
+    DupRule(String), // Rule with such name already exists
+    NoRule(String),  // No rule with such name
+}
+
+impl Display for RulesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DupRule(r) => write!(f, "Rule {} already exists", r),
+            Self::NoRule(r) => write!(f, "No such rule {}", r),
+        }
+    }
+}
+
+impl Error for RulesError {}
+
+/// Condition over readings and states of the devices
+/// Devices are referenced by id
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    TemperatureBelow { device: String, value: f32 },
+    TemperatureAbove { device: String, value: f32 },
+    SocketOn(String),
+    SocketOff(String),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn temperature_below(device: &str, value: f32) -> Self {
+        Self::TemperatureBelow {
+            device: device.to_string(),
+            value,
+        }
+    }
+    pub fn temperature_above(device: &str, value: f32) -> Self {
+        Self::TemperatureAbove {
+            device: device.to_string(),
+            value,
+        }
+    }
+    pub fn socket_on(device: &str) -> Self {
+        Self::SocketOn(device.to_string())
+    }
+    pub fn socket_off(device: &str) -> Self {
+        Self::SocketOff(device.to_string())
+    }
+
+    /// Evaluate condition
+    /// Thresholds are widened by margin (hysteresis of active rule),
+    /// unavailable readings make condition false
+    fn evaluate(&self, readings: &mut Readings, margin: f32) -> bool {
+        match self {
+            Self::TemperatureBelow { device, value } => readings
+                .temperature(device)
+                .is_some_and(|t| t < value + margin),
+            Self::TemperatureAbove { device, value } => readings
+                .temperature(device)
+                .is_some_and(|t| t > value - margin),
+            Self::SocketOn(device) => readings.socket_on(device) == Some(true),
+            Self::SocketOff(device) => readings.socket_on(device) == Some(false),
+            Self::All(conditions) => conditions.iter().all(|c| c.evaluate(readings, margin)),
+            Self::Any(conditions) => conditions.iter().any(|c| c.evaluate(readings, margin)),
+            Self::Not(condition) => !condition.evaluate(readings, -margin),
+        }
+    }
+}
+
+/// Readings cache, each device is requested once per evaluation
+struct Readings<'a> {
+    device: &'a mut dyn Device,
+    temperatures: HashMap<String, Option<f32>>,
+    sockets: HashMap<String, Option<bool>>,
+}
+
+impl<'a> Readings<'a> {
+    fn new(device: &'a mut dyn Device) -> Self {
+        Self {
+            device,
+            temperatures: HashMap::default(),
+            sockets: HashMap::default(),
+        }
+    }
+
+    fn temperature(&mut self, id: &str) -> Option<f32> {
+        if let Some(t) = self.temperatures.get(id) {
+            return *t;
+        }
+        let response = self
+            .device
+            .process(CommandRequest::builder().therm(id).get_temp());
+        let t = match response.response() {
+            ResponseType::Success(t) => t.parse().ok(),
+            _ => None,
+        };
+        self.temperatures.insert(id.to_string(), t);
+        t
+    }
+
+    fn socket_on(&mut self, id: &str) -> Option<bool> {
+        if let Some(s) = self.sockets.get(id) {
+            return *s;
+        }
+        let response = self
+            .device
+            .process(CommandRequest::builder().socket(id).get_state());
+        let s = socket_state(&response).ok().map(|s| s == SocketState::On);
+        self.sockets.insert(id.to_string(), s);
+        s
+    }
+}
+
+/// Rule fires actions when condition becomes true
+/// and release actions when condition becomes false again
+pub struct Rule {
+    name: String,
+    condition: Condition,
+    actions: Vec<CommandRequest>,
+    release_actions: Vec<CommandRequest>,
+    hysteresis: f32,
+    enabled: bool,
+    active: bool,
+}
+
+impl Rule {
+    pub fn new(name: &str, condition: Condition) -> Self {
+        Self {
+            name: name.to_string(),
+            condition,
+            actions: Vec::default(),
+            release_actions: Vec::default(),
+            hysteresis: 0.,
+            enabled: true,
+            active: false,
+        }
+    }
+    /// Add action issued when condition becomes true
+    pub fn then(mut self, action: CommandRequest) -> Self {
+        self.actions.push(action);
+        self
+    }
+    /// Add action issued when condition becomes false
+    pub fn release(mut self, action: CommandRequest) -> Self {
+        self.release_actions.push(action);
+        self
+    }
+    /// Active rule keeps being active until reading
+    /// crosses the threshold by hysteresis value
+    pub fn hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+    /// name getter
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+/// Issued action with device response
+/// Response is None in dry-run mode
+#[derive(Debug, PartialEq)]
+pub struct Firing {
+    pub rule: String,
+    pub request: CommandRequest,
+    pub response: Option<CommandResponse>,
+}
+
+#[derive(Default)]
+pub struct RulesEngine {
+    rules: Vec<Rule>,
+    dry_run: bool,
+}
+
+impl RulesEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register rule, rule name should be unique
+    pub fn add_rule(&mut self, rule: Rule) -> Result<(), RulesError> {
+        if self.rules.iter().any(|r| r.name == rule.name) {
+            return Err(RulesError::DupRule(rule.name));
+        }
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    pub fn remove_rule(&mut self, name: &str) -> Result<Rule, RulesError> {
+        match self.rules.iter().position(|r| r.name == name) {
+            Some(i) => Ok(self.rules.remove(i)),
+            None => Err(RulesError::NoRule(name.to_string())),
+        }
+    }
+
+    pub fn rule(&self, name: &str) -> Option<&Rule> {
+        self.rules.iter().find(|r| r.name == name)
+    }
+
+    pub fn enable(&mut self, name: &str) -> Result<(), RulesError> {
+        self.rule_mut(name)?.enabled = true;
+        Ok(())
+    }
+
+    /// Disabled rule is not evaluated and loses its active state
+    pub fn disable(&mut self, name: &str) -> Result<(), RulesError> {
+        let rule = self.rule_mut(name)?;
+        rule.enabled = false;
+        rule.active = false;
+        Ok(())
+    }
+
+    /// In dry-run mode actions are reported but not sent to devices
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Evaluate enabled rules in order of registration
+    /// Returns actions issued during evaluation
+    pub fn evaluate(&mut self, device: &mut dyn Device) -> Vec<Firing> {
+        let mut readings = Readings::new(device);
+        let mut firings = vec![];
+        for rule in self.rules.iter_mut().filter(|r| r.enabled) {
+            let margin = if rule.active { rule.hysteresis } else { 0. };
+            let active = rule.condition.evaluate(&mut readings, margin);
+            let actions = match (rule.active, active) {
+                (false, true) => &rule.actions,
+                (true, false) => &rule.release_actions,
+                _ => continue,
+            };
+            rule.active = active;
+            for request in actions {
+                let response = if self.dry_run {
+                    None
+                } else {
+                    Some(readings.device.process(request.clone()))
+                };
+                firings.push(Firing {
+                    rule: rule.name.clone(),
+                    request: request.clone(),
+                    response,
+                });
+            }
+            // Actions could change devices state
+            if !self.dry_run {
+                readings.sockets.clear();
+            }
+        }
+        firings
+    }
+
+    fn rule_mut(&mut self, name: &str) -> Result<&mut Rule, RulesError> {
+        self.rules
+            .iter_mut()
+            .find(|r| r.name == name)
+            .ok_or(RulesError::NoRule(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gateway::Gateway;
+    use smart_home::devices::Socket;
+
+    /// Thermometer with controlled temperature
+    struct FakeTherm(f32);
+
+    impl Device for FakeTherm {
+        fn process(&mut self, request: CommandRequest) -> CommandResponse {
+            CommandResponse::new(request.id(), ResponseType::Success(self.0.to_string()))
+        }
+    }
+
+    fn heating_rule() -> Rule {
+        Rule::new("heating", Condition::temperature_below("t1", 21.))
+            .then(CommandRequest::builder().socket("s1").turn_on())
+            .release(CommandRequest::builder().socket("s1").turn_off())
+            .hysteresis(1.)
+    }
+
+    fn socket_is_on(g: &mut Gateway) -> bool {
+        Readings::new(g).socket_on("s1").unwrap()
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let mut g = Gateway::new();
+        g.add_device("s1", Socket::new("s1"));
+        let mut engine = RulesEngine::new();
+        engine.add_rule(heating_rule()).unwrap();
+
+        for (t, on, fired) in [
+            (22., false, 0),
+            (20.5, true, 1),
+            (21.5, true, 0), // within hysteresis
+            (22.5, false, 1),
+            (21.5, false, 0),
+        ] {
+            g.add_device("t1", FakeTherm(t));
+            let firings = engine.evaluate(&mut g);
+            assert_eq!(firings.len(), fired, "temperature {t}");
+            assert_eq!(socket_is_on(&mut g), on, "temperature {t}");
+        }
+    }
+
+    #[test]
+    fn test_dry_run_and_disable() {
+        let mut g = Gateway::new();
+        g.add_device("s1", Socket::new("s1"));
+        g.add_device("t1", FakeTherm(20.));
+        let mut engine = RulesEngine::new();
+        engine.add_rule(heating_rule()).unwrap();
+        assert_eq!(
+            engine.add_rule(heating_rule()),
+            Err(RulesError::DupRule("heating".into()))
+        );
+
+        engine.set_dry_run(true);
+        let firings = engine.evaluate(&mut g);
+        assert_eq!(
+            firings,
+            vec![Firing {
+                rule: "heating".into(),
+                request: CommandRequest::builder().socket("s1").turn_on(),
+                response: None
+            }]
+        );
+        assert!(!socket_is_on(&mut g));
+
+        engine.set_dry_run(false);
+        engine.disable("heating").unwrap();
+        assert!(engine.evaluate(&mut g).is_empty());
+        engine.enable("heating").unwrap();
+        assert_eq!(engine.evaluate(&mut g).len(), 1);
+        assert!(socket_is_on(&mut g));
+        assert_eq!(
+            engine.disable("cooling"),
+            Err(RulesError::NoRule("cooling".into()))
+        );
+    }
+
+    #[test]
+    fn test_composite_condition() {
+        let mut g = Gateway::new();
+        g.add_device("s1", Socket::new("s1"));
+        g.add_device("t1", FakeTherm(20.));
+        let condition = Condition::All(vec![
+            Condition::temperature_below("t1", 21.),
+            Condition::Not(Box::new(Condition::socket_on("s1"))),
+        ]);
+        let mut readings = Readings::new(&mut g);
+        assert!(condition.evaluate(&mut readings, 0.));
+        // missing device makes condition false
+        assert!(!Condition::temperature_above("t2", 0.).evaluate(&mut readings, 0.));
+    }
+}
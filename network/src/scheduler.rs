@@ -0,0 +1,580 @@
+/// Time based scheduler of CommandRequest
+/// Supports one-shot, interval and cron-like schedules.
+/// Jobs are executed on Device (local device, Gateway or RemoteDevice)
+/// when scheduler is ticked, so the caller decides how often to poll it
+use std::{
+    error::Error,
+    fmt::Display,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Datelike, Local, NaiveDateTime, TimeDelta, Timelike};
+use serde::{Deserialize, Serialize};
+use smart_home::events::Event;
+
+use crate::{
+    command::{CommandRequest, CommandResponse},
+    device::Device,
+    Result,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum SchedulerError {
+    DupJob(String),      // Job with such name already exists
+    NoJob(String),       // No job with such name
+    BadCron(String),     // Cron expression could not be parsed
+    BadInterval(String), // Zero or too large interval (over ~292 years)
+}
+
+impl Display for SchedulerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DupJob(j) => write!(f, "Job {} already exists", j),
+            Self::NoJob(j) => write!(f, "No such job {}", j),
+            Self::BadCron(c) => write!(f, "Wrong cron expression {}", c),
+            Self::BadInterval(j) => write!(f, "Wrong interval for job {}", j),
+        }
+    }
+}
+
+impl Error for SchedulerError {}
+
+/// Source of current local time
+pub trait Clock {
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// Wall clock in the local timezone
+#[derive(Default, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+/// Clock which is moved manually, used for deterministic tests
+/// Clones share the same time
+#[derive(Clone)]
+pub struct ManualClock(Arc<Mutex<NaiveDateTime>>);
+
+impl ManualClock {
+    pub fn new(now: NaiveDateTime) -> Self {
+        Self(Arc::new(Mutex::new(now)))
+    }
+    pub fn set(&self, now: NaiveDateTime) {
+        *self.0.lock().unwrap() = now;
+    }
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.0.lock().unwrap();
+        *now += TimeDelta::from_std(duration).unwrap();
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> NaiveDateTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Cron-like expression with five fields:
+/// minute (0-59), hour (0-23), day of month (1-31), month (1-12),
+/// day of week (0-6, Sunday is 0)
+/// Each field is "*", number, range "a-b", list "a,b" or step "*/n", "a-b/n", "a/n"
+/// Step from a single value runs to the end of the range, "50/5" is "50-59/5"
+/// As in cron, if both day fields are restricted (don't start with "*"),
+/// time matches when either of them matches
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    expr: String,
+    fields: [u64; 5], // bitmasks of allowed values
+    any_day: bool,    // day of month or day of week is enough
+}
+
+impl Cron {
+    const RANGES: [(u32, u32); 5] = [(0, 59), (0, 23), (1, 31), (1, 12), (0, 6)];
+
+    pub fn parse(expr: &str) -> std::result::Result<Self, SchedulerError> {
+        let err = || SchedulerError::BadCron(expr.to_string());
+        let parts = expr.split_whitespace().collect::<Vec<_>>();
+        if parts.len() != 5 {
+            return Err(err());
+        }
+        let mut fields = [0u64; 5];
+        for (i, part) in parts.iter().enumerate() {
+            fields[i] = Self::parse_field(part, Self::RANGES[i]).ok_or_else(err)?;
+        }
+        Ok(Self {
+            expr: expr.to_string(),
+            fields,
+            any_day: !parts[2].starts_with('*') && !parts[4].starts_with('*'),
+        })
+    }
+
+    fn parse_field(field: &str, (min, max): (u32, u32)) -> Option<u64> {
+        let mut mask = 0;
+        for item in field.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, Some(step.parse::<u32>().ok()?)),
+                None => (item, None),
+            };
+            let (low, high) = match range {
+                "*" => (min, max),
+                r => match r.split_once('-') {
+                    Some((l, h)) => (l.parse().ok()?, h.parse().ok()?),
+                    None => {
+                        let v = r.parse().ok()?;
+                        (v, if step.is_some() { max } else { v })
+                    }
+                },
+            };
+            let step = step.unwrap_or(1);
+            if step == 0 || low < min || high > max || low > high {
+                return None;
+            }
+            for v in (low..=high).step_by(step as usize) {
+                mask |= 1 << v;
+            }
+        }
+        Some(mask)
+    }
+
+    fn matches(&self, t: &NaiveDateTime) -> bool {
+        let values = [
+            t.minute(),
+            t.hour(),
+            t.day(),
+            t.month(),
+            t.weekday().num_days_from_sunday(),
+        ];
+        let allowed = |i: usize| self.fields[i] & (1 << values[i]) != 0;
+        let day = if self.any_day {
+            allowed(2) || allowed(4)
+        } else {
+            allowed(2) && allowed(4)
+        };
+        allowed(0) && allowed(1) && allowed(3) && day
+    }
+
+    /// First matching minute strictly after t
+    /// Searches no further than 5 years ahead (e.g. for "0 0 30 2 *")
+    pub fn next_after(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut next = t.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        let limit = t + TimeDelta::days(5 * 366);
+        while next < limit {
+            if self.matches(&next) {
+                return Some(next);
+            }
+            // Skip whole hour if it doesn't match
+            if self.fields[1] & (1 << next.hour()) == 0 {
+                next = next.with_minute(0)? + TimeDelta::hours(1);
+            } else {
+                next += TimeDelta::minutes(1);
+            }
+        }
+        None
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = SchedulerError;
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<Cron> for String {
+    fn from(value: Cron) -> Self {
+        value.expr
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Once(NaiveDateTime),
+    Interval {
+        start: NaiveDateTime,
+        every: Duration,
+    },
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// Next run time strictly after t
+    fn next_after(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Self::Once(at) => (*at > t).then_some(*at),
+            Self::Interval { start, every } => {
+                if *start > t {
+                    return Some(*start);
+                }
+                // Nanoseconds keep sub-millisecond intervals exact,
+                // overflow means no next run
+                let every = i64::try_from(every.as_nanos()).ok().filter(|e| *e > 0)?;
+                let passed = (t - *start).num_nanoseconds()? / every;
+                let next = passed.checked_add(1)?.checked_mul(every)?;
+                start.checked_add_signed(TimeDelta::nanoseconds(next))
+            }
+            Self::Cron(cron) => cron.next_after(t),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Job {
+    name: String,
+    schedule: Schedule,
+    request: CommandRequest,
+    next_run: Option<NaiveDateTime>, // None if job is finished
+}
+
+impl Job {
+    /// name getter
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+    pub fn request(&self) -> &CommandRequest {
+        &self.request
+    }
+    pub fn next_run(&self) -> Option<NaiveDateTime> {
+        self.next_run
+    }
+}
+
+/// Outcome of job run
+#[derive(Debug, PartialEq)]
+pub struct RunReport {
+    pub job: String,
+    pub at: NaiveDateTime,
+    pub request: CommandRequest,
+    pub response: CommandResponse,
+}
+
+pub struct Scheduler<C: Clock = SystemClock> {
+    clock: C,
+    jobs: Vec<Job>,
+}
+
+impl Scheduler<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl Default for Scheduler<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            clock,
+            jobs: Vec::default(),
+        }
+    }
+
+    /// Register job, job name should be unique
+    pub fn add(
+        &mut self,
+        name: &str,
+        schedule: Schedule,
+        request: CommandRequest,
+    ) -> std::result::Result<(), SchedulerError> {
+        if self.job(name).is_some() {
+            return Err(SchedulerError::DupJob(name.to_string()));
+        }
+        if let Schedule::Interval { every, .. } = &schedule {
+            if every.is_zero() || i64::try_from(every.as_nanos()).is_err() {
+                return Err(SchedulerError::BadInterval(name.to_string()));
+            }
+        }
+        let now = self.clock.now();
+        // Job scheduled exactly on current time runs on the next tick
+        let next_run = schedule.next_after(now - TimeDelta::milliseconds(1));
+        self.jobs.push(Job {
+            name: name.to_string(),
+            schedule,
+            request,
+            next_run,
+        });
+        Ok(())
+    }
+
+    /// One-shot job which runs after delay from now
+    pub fn add_after(
+        &mut self,
+        name: &str,
+        delay: Duration,
+        request: CommandRequest,
+    ) -> std::result::Result<(), SchedulerError> {
+        let delay =
+            TimeDelta::from_std(delay).map_err(|_| SchedulerError::BadInterval(name.into()))?;
+        self.add(name, Schedule::Once(self.clock.now() + delay), request)
+    }
+
+    pub fn remove(&mut self, name: &str) -> std::result::Result<Job, SchedulerError> {
+        match self.jobs.iter().position(|j| j.name == name) {
+            Some(i) => Ok(self.jobs.remove(i)),
+            None => Err(SchedulerError::NoJob(name.to_string())),
+        }
+    }
+
+    /// Follow DeviceMoved events of SmartHome, returns number of changed jobs
+    /// Device name in the home is used as request id, see CommandSwitch
+    pub fn follow(&mut self, event: &Event) -> usize {
+        match event {
+            Event::DeviceMoved {
+                from_device,
+                to_device,
+                ..
+            } => self.rename_device(from_device, to_device),
+            _ => 0,
+        }
+    }
+
+    /// Retarget jobs after device was renamed, returns number of changed jobs
+    pub fn rename_device(&mut self, id: &str, new_id: &str) -> usize {
+        let mut changed = 0;
+        for job in self.jobs.iter_mut().filter(|j| j.request.id() == id) {
+            job.request.set_id(new_id);
+            changed += 1;
+        }
+        changed
+    }
+
+    pub fn job(&self, name: &str) -> Option<&Job> {
+        self.jobs.iter().find(|j| j.name == name)
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// Run jobs which are due
+    /// Missed runs are not repeated: job runs once and is rescheduled after now
+    pub fn tick(&mut self, device: &mut dyn Device) -> Vec<RunReport> {
+        let now = self.clock.now();
+        let mut reports = vec![];
+        for job in self.jobs.iter_mut() {
+            match job.next_run {
+                Some(at) if at <= now => {
+                    let response = device.process(job.request.clone());
+                    reports.push(RunReport {
+                        job: job.name.clone(),
+                        at: now,
+                        request: job.request.clone(),
+                        response,
+                    });
+                    job.next_run = job.schedule.next_after(now);
+                }
+                _ => {}
+            }
+        }
+        // Finished one-shot jobs are not kept
+        self.jobs.retain(|j| j.next_run.is_some());
+        reports
+    }
+
+    /// Persist jobs to file as JSON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let buf = serde_json::to_vec_pretty(&self.jobs)?;
+        fs::write(path, buf)?;
+        Ok(())
+    }
+
+    /// Load jobs from file, previously registered jobs are replaced
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let buf = fs::read(path)?;
+        self.jobs = serde_json::from_slice(&buf)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::ResponseType;
+    use chrono::NaiveDate;
+    use smart_home::{devices::Socket, events::EventBus, SmartHome};
+
+    fn at(day: u32, h: u32, m: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 7, day)
+            .unwrap()
+            .and_hms_opt(h, m, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cron() {
+        let cron = Cron::parse("0 23 * * *").unwrap();
+        assert_eq!(cron.next_after(at(1, 12, 0)), Some(at(1, 23, 0)));
+        assert_eq!(cron.next_after(at(1, 23, 0)), Some(at(2, 23, 0)));
+        // 2024-07-06 is Saturday
+        let cron = Cron::parse("*/15 8-9 * * 1-5").unwrap();
+        assert_eq!(cron.next_after(at(5, 9, 50)), Some(at(8, 8, 0)));
+        assert_eq!(cron.next_after(at(8, 8, 0)), Some(at(8, 8, 15)));
+        // Day of month or day of week, 2024-07-01 is Monday
+        let cron = Cron::parse("0 12 10 * 1").unwrap();
+        assert_eq!(cron.next_after(at(1, 13, 0)), Some(at(8, 12, 0)));
+        assert_eq!(cron.next_after(at(8, 13, 0)), Some(at(10, 12, 0)));
+        // Step from a value runs to the end of the range
+        let cron = Cron::parse("50/5 * * * *").unwrap();
+        assert_eq!(cron.next_after(at(1, 12, 51)), Some(at(1, 12, 55)));
+        assert_eq!(cron.next_after(at(1, 12, 55)), Some(at(1, 13, 50)));
+        for bad in ["* * * *", "60 * * * *", "*/0 * * * *", "a * * * *"] {
+            assert_eq!(Cron::parse(bad), Err(SchedulerError::BadCron(bad.into())));
+        }
+    }
+
+    #[test]
+    fn test_schedules() {
+        let clock = ManualClock::new(at(1, 22, 0));
+        let mut scheduler = Scheduler::with_clock(clock.clone());
+        let mut socket = Socket::new("s1000");
+        let off = CommandRequest::builder().socket("s1000").turn_off();
+        scheduler
+            .add(
+                "night",
+                Schedule::Cron(Cron::parse("0 23 * * *").unwrap()),
+                off.clone(),
+            )
+            .unwrap();
+        scheduler
+            .add_after("later", Duration::from_secs(30 * 60), off.clone())
+            .unwrap();
+        scheduler
+            .add(
+                "often",
+                Schedule::Interval {
+                    start: at(1, 22, 0),
+                    every: Duration::from_secs(20 * 60),
+                },
+                off.clone(),
+            )
+            .unwrap();
+        assert_eq!(
+            scheduler.add("night", Schedule::Once(at(1, 1, 1)), off.clone()),
+            Err(SchedulerError::DupJob("night".into()))
+        );
+
+        let names =
+            |reports: Vec<RunReport>| reports.into_iter().map(|r| r.job).collect::<Vec<_>>();
+        assert_eq!(names(scheduler.tick(&mut socket)), vec!["often"]);
+        clock.advance(Duration::from_secs(30 * 60));
+        assert_eq!(names(scheduler.tick(&mut socket)), vec!["later", "often"]);
+        assert!(scheduler.job("later").is_none());
+        clock.set(at(1, 23, 0));
+        let reports = scheduler.tick(&mut socket);
+        assert_eq!(reports[0].job, "night");
+        assert_eq!(
+            reports[0].response.response(),
+            &ResponseType::Success("".into())
+        );
+        assert_eq!(
+            scheduler.job("night").unwrap().next_run(),
+            Some(at(2, 23, 0))
+        );
+        assert_eq!(
+            scheduler.job("often").unwrap().next_run(),
+            Some(at(1, 23, 20))
+        );
+    }
+
+    #[test]
+    fn test_rename_device() {
+        let bus = EventBus::new();
+        let (_, rx) = bus.subscribe_channel();
+        let mut home = SmartHome::new("My home");
+        home.set_event_bus(bus);
+        home.add_device("kitchen", "s1000").unwrap();
+        home.add_device("kitchen", "s2000").unwrap();
+        let mut scheduler = Scheduler::with_clock(ManualClock::new(at(1, 22, 0)));
+        for (name, id) in [("night", "s1000"), ("day", "s1000"), ("other", "s2000")] {
+            let request = CommandRequest::builder().socket(id).turn_off();
+            scheduler
+                .add(name, Schedule::Once(at(2, 0, 0)), request)
+                .unwrap();
+        }
+        let id = |scheduler: &Scheduler<ManualClock>, job: &str| {
+            scheduler.job(job).unwrap().request().id().to_string()
+        };
+
+        assert_eq!(scheduler.rename_device("s1000", "s1001"), 2);
+        assert_eq!(id(&scheduler, "night"), "s1001");
+        assert_eq!(id(&scheduler, "day"), "s1001");
+        assert_eq!(id(&scheduler, "other"), "s2000");
+
+        home.rename_device("kitchen", "s2000", "s2001").unwrap();
+        home.rename_room("kitchen", "hall").unwrap();
+        let changed: usize = rx.try_iter().map(|e| scheduler.follow(&e)).sum();
+        assert_eq!(changed, 1);
+        assert_eq!(id(&scheduler, "other"), "s2001");
+        assert_eq!(id(&scheduler, "night"), "s1001");
+    }
+
+    #[test]
+    fn test_short_interval() {
+        let clock = ManualClock::new(at(1, 22, 0));
+        let mut scheduler = Scheduler::with_clock(clock.clone());
+        let mut socket = Socket::new("s1000");
+        let off = CommandRequest::builder().socket("s1000").turn_off();
+        let every = |every| Schedule::Interval {
+            start: at(1, 22, 0),
+            every,
+        };
+        scheduler
+            .add("fast", every(Duration::from_micros(500)), off.clone())
+            .unwrap();
+        assert_eq!(scheduler.tick(&mut socket).len(), 1);
+        // Long pause is skipped with single run
+        clock.advance(Duration::from_secs(24 * 3600) + Duration::from_micros(100));
+        assert_eq!(scheduler.tick(&mut socket).len(), 1);
+        assert_eq!(
+            scheduler.job("fast").unwrap().next_run(),
+            Some(at(2, 22, 0) + TimeDelta::microseconds(500))
+        );
+        assert_eq!(
+            scheduler.add("zero", every(Duration::ZERO), off.clone()),
+            Err(SchedulerError::BadInterval("zero".into()))
+        );
+        assert_eq!(
+            scheduler.add("huge", every(Duration::MAX), off),
+            Err(SchedulerError::BadInterval("huge".into()))
+        );
+    }
+
+    #[test]
+    fn test_persistence() {
+        let clock = ManualClock::new(at(1, 22, 0));
+        let mut scheduler = Scheduler::with_clock(clock.clone());
+        let off = CommandRequest::builder().socket("s1000").turn_off();
+        scheduler
+            .add(
+                "night",
+                Schedule::Cron(Cron::parse("0 23 * * *").unwrap()),
+                off.clone(),
+            )
+            .unwrap();
+        scheduler
+            .add_after("later", Duration::from_secs(60), off)
+            .unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("smart_home_scheduler_{}.json", std::process::id()));
+        scheduler.save(&path).unwrap();
+        let mut restored = Scheduler::with_clock(clock);
+        restored.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(restored.jobs(), scheduler.jobs());
+    }
+}
@@ -0,0 +1,79 @@
+/// Socket files of Unix domain sockets: stale files cleanup,
+/// permissions and paths of datagram clients
+use std::{
+    fs::{self, DirBuilder, Permissions},
+    io,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixDatagram, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::error::{NetworkError, NetworkResult};
+
+/// Owner and group could connect
+pub const DEFAULT_MODE: u32 = 0o660;
+
+static CLIENT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+static BIND_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Remove socket file left by server which is not running anymore
+/// Error if the file is not a socket or the server is alive
+pub(crate) fn remove_stale(path: &Path, datagram: bool) -> NetworkResult<()> {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !meta.file_type().is_socket() {
+        return Err(NetworkError::Address(format!(
+            "{} is not a socket",
+            path.display()
+        )));
+    }
+    let alive = if datagram {
+        UnixDatagram::unbound()?.connect(path).is_ok()
+    } else {
+        UnixStream::connect(path).is_ok()
+    };
+    if alive {
+        return Err(NetworkError::Address(format!(
+            "{} is in use",
+            path.display()
+        )));
+    }
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Bind socket with the mode, access is controlled with file permissions
+/// Socket is bound in a private directory and linked to the path
+/// when permissions are set, so it is never reachable with default ones
+pub(crate) fn bind<T, F>(path: &Path, mode: u32, bind: F) -> NetworkResult<T>
+where
+    F: FnOnce(&Path) -> io::Result<T>,
+{
+    let n = BIND_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(format!(".{name}.{}.{n}", std::process::id()));
+    DirBuilder::new().mode(0o700).create(&dir)?;
+    let private = dir.join("socket");
+    let socket = bind(&private).and_then(|socket| {
+        fs::set_permissions(&private, Permissions::from_mode(mode))?;
+        // Unlike rename, link doesn't replace file which appeared at the path
+        fs::hard_link(&private, path)?;
+        Ok(socket)
+    });
+    let _ = fs::remove_file(&private);
+    let _ = fs::remove_dir(&dir);
+    Ok(socket?)
+}
+
+/// Datagram client should be bound to receive responses
+pub(crate) fn client_path() -> PathBuf {
+    let n = CLIENT_COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("smart_home_{}_{n}.sock", std::process::id()))
+}
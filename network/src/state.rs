@@ -0,0 +1,404 @@
+/// Persistent state of devices
+/// Socket state is saved to the store on change, request statistics
+/// at most once per stats interval, and restored when device is started again
+/// State is read and applied with commands, so any Device (e.g. Gateway) could be wrapped
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use smart_home::devices::SocketState;
+use tracing::error;
+
+use crate::{
+    command::{CommandRequest, CommandResponse, RequestType, ResponseType},
+    device::{socket_state, Device, Peer},
+    Result,
+};
+
+/// Default interval of saving statistics without state changes
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Handled requests of the device
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub requests: u64,
+    pub errors: u64,
+    pub switches: u64, // Successful turn on and turn off
+    pub last_switch: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub state: Option<SocketState>, // None for devices without switch
+    pub stats: Stats,
+}
+
+/// Storage of snapshots by device id
+pub trait StateStore {
+    fn load(&mut self) -> Result<HashMap<String, Snapshot>>;
+    fn save(&mut self, snapshots: &HashMap<String, Snapshot>) -> Result<()>;
+}
+
+/// Snapshots in JSON file
+/// File is replaced atomically: new content is written to temporary file,
+/// synced and renamed, so the file is never partially written
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl StateStore for FileStore {
+    /// Missing file is empty store
+    fn load(&mut self) -> Result<HashMap<String, Snapshot>> {
+        match fs::read(&self.path) {
+            Ok(buf) => Ok(serde_json::from_slice(&buf)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&mut self, snapshots: &HashMap<String, Snapshot>) -> Result<()> {
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(snapshots)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Store kept in memory, cloned handle shares snapshots
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    snapshots: Arc<Mutex<HashMap<String, Snapshot>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn load(&mut self) -> Result<HashMap<String, Snapshot>> {
+        Ok(self.snapshots.lock().unwrap().clone())
+    }
+
+    fn save(&mut self, snapshots: &HashMap<String, Snapshot>) -> Result<()> {
+        *self.snapshots.lock().unwrap() = snapshots.clone();
+        Ok(())
+    }
+}
+
+/// Socket state after start
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerOn {
+    #[default]
+    Restore, // Last saved state
+    Off, // Always off
+}
+
+/// Device which saves state of registered devices
+/// State changed by device itself, e.g. overload trip, is taken from state responses
+/// and refreshed after failed requests
+/// Commands for other devices are passed without tracking
+/// Pending statistics are saved when dropped
+pub struct Persistent<D: Device> {
+    inner: D,
+    store: Box<dyn StateStore + Send + Sync>,
+    power_on: HashMap<String, PowerOn>,
+    snapshots: HashMap<String, Snapshot>,
+    stats_interval: Duration,
+    saved: Instant,
+    pending: bool, // Statistics are not saved
+}
+
+impl<D: Device> Persistent<D> {
+    pub fn new<S: StateStore + Send + Sync + 'static>(inner: D, store: S) -> Self {
+        Self {
+            inner,
+            store: Box::new(store),
+            power_on: HashMap::new(),
+            snapshots: HashMap::new(),
+            stats_interval: DEFAULT_STATS_INTERVAL,
+            saved: Instant::now(),
+            pending: false,
+        }
+    }
+
+    /// Save statistics without state changes at most once per interval
+    pub fn with_stats_interval(mut self, interval: Duration) -> Self {
+        self.stats_interval = interval;
+        self
+    }
+
+    /// Track device with request id
+    pub fn device(mut self, id: &str, power_on: PowerOn) -> Self {
+        self.power_on.insert(id.to_string(), power_on);
+        self
+    }
+
+    /// Load snapshots and apply power on behaviour, should be called before serving
+    /// Saved snapshots of devices which are not tracked anymore are dropped
+    pub fn restore(mut self) -> Result<Self> {
+        let mut saved = self.store.load()?;
+        for (id, power_on) in self.power_on.clone() {
+            let mut snapshot = saved.remove(&id).unwrap_or_default();
+            let target = match power_on {
+                PowerOn::Restore => snapshot.state,
+                PowerOn::Off => Some(SocketState::Off),
+            };
+            let request = CommandRequest::builder().socket(&id);
+            match target {
+                Some(SocketState::On) => _ = self.inner.process(request.turn_on()),
+                Some(SocketState::Off) => _ = self.inner.process(request.turn_off()),
+                None => {}
+            }
+            // Device without switch, e.g. thermometer, has no state
+            let request = CommandRequest::builder().socket(&id).get_state();
+            snapshot.state = socket_state(&self.inner.process(request)).ok();
+            self.snapshots.insert(id, snapshot);
+        }
+        self.store.save(&self.snapshots)?;
+        self.saved = Instant::now();
+        Ok(self)
+    }
+
+    /// Saved snapshot of the device
+    pub fn snapshot(&self, id: &str) -> Option<&Snapshot> {
+        self.snapshots.get(id)
+    }
+
+    /// Update snapshot of tracked device, returns true if state is changed
+    fn track(&mut self, request: &CommandRequest, response: &CommandResponse) -> bool {
+        let Some(snapshot) = self.snapshots.get_mut(request.id()) else {
+            return false;
+        };
+        self.pending = true;
+        snapshot.stats.requests += 1;
+        let state = match (request.req_type(), response.response()) {
+            (_, ResponseType::Err(_)) => {
+                snapshot.stats.errors += 1;
+                // Failed request could change state, e.g. overload on turn on trips socket
+                match snapshot.state {
+                    Some(_) => {
+                        let request = CommandRequest::builder().socket(request.id()).get_state();
+                        socket_state(&self.inner.process(request)).ok()
+                    }
+                    None => None,
+                }
+            }
+            (RequestType::SocketTurnOn, _) => {
+                snapshot.stats.switches += 1;
+                Some(SocketState::On)
+            }
+            (RequestType::SocketTurnOff | RequestType::SocketTrip { .. }, _) => {
+                snapshot.stats.switches += 1;
+                Some(SocketState::Off)
+            }
+            (RequestType::SocketGetState, _) => socket_state(response).ok(),
+            _ => None,
+        };
+        match state {
+            Some(state) if snapshot.state != Some(state) => {
+                snapshot.state = Some(state);
+                snapshot.stats.last_switch = Some(Utc::now());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn save(&mut self) {
+        match self.store.save(&self.snapshots) {
+            Ok(()) => {
+                self.saved = Instant::now();
+                self.pending = false;
+            }
+            Err(e) => error!(error = %e, "state is not saved"),
+        }
+    }
+}
+
+impl<D: Device> Device for Persistent<D> {
+    fn process(&mut self, request: CommandRequest) -> CommandResponse {
+        self.handle_from(&Peer::default(), request)
+    }
+
+    fn handle(&mut self, request: CommandRequest) -> CommandResponse {
+        self.handle_from(&Peer::default(), request)
+    }
+
+    /// Store failure doesn't affect the response, state is saved on the next request
+    fn handle_from(&mut self, peer: &Peer, request: CommandRequest) -> CommandResponse {
+        let response = self.inner.handle_from(peer, request.clone());
+        let changed = match (request.req_type(), response.response()) {
+            (RequestType::Batch { commands, .. }, ResponseType::Batch(responses)) => commands
+                .iter()
+                .zip(responses)
+                .fold(false, |changed, (c, r)| self.track(c, r) || changed),
+            (RequestType::Batch { .. }, _) => false,
+            (_, _) => self.track(&request, &response),
+        };
+        if changed || (self.pending && self.saved.elapsed() >= self.stats_interval) {
+            self.save();
+        }
+        response
+    }
+}
+
+impl<D: Device> Drop for Persistent<D> {
+    fn drop(&mut self) {
+        if self.pending {
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smart_home::devices::{Socket, Thermometer};
+
+    use super::*;
+    use crate::gateway::Gateway;
+
+    fn gateway(store: MemoryStore) -> Result<Persistent<Gateway>> {
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        gateway.add_device("s2", Socket::new("s2"));
+        gateway.add_device("t1", Thermometer::new("t1"));
+        Persistent::new(gateway, store)
+            .device("s1", PowerOn::Restore)
+            .device("s2", PowerOn::Off)
+            .device("t1", PowerOn::Restore)
+            .restore()
+    }
+
+    fn state(device: &mut impl Device, id: &str) -> Option<SocketState> {
+        let request = CommandRequest::builder().socket(id).get_state();
+        socket_state(&device.process(request)).ok()
+    }
+
+    #[test]
+    fn test_restart() -> Result<()> {
+        let store = MemoryStore::new();
+        let mut device = gateway(store.clone())?;
+        assert_eq!(device.snapshot("s1").unwrap().state, Some(SocketState::Off));
+        assert_eq!(device.snapshot("t1").unwrap().state, None);
+        device.handle(CommandRequest::builder().socket("s1").turn_on());
+        device.handle(CommandRequest::builder().socket("s2").turn_on());
+        device.handle(CommandRequest::builder().therm("t1").get_temp());
+        device.handle(CommandRequest::builder().socket("t1").turn_on());
+        drop(device);
+
+        let mut device = gateway(store)?;
+        assert_eq!(state(&mut device, "s1"), Some(SocketState::On));
+        // Always off on start
+        assert_eq!(state(&mut device, "s2"), Some(SocketState::Off));
+        let s1 = device.snapshot("s1").unwrap();
+        // State request after restart is counted too
+        assert_eq!((s1.stats.requests, s1.stats.switches), (2, 1));
+        assert!(s1.stats.last_switch.is_some());
+        let t1 = device.snapshot("t1").unwrap();
+        assert_eq!((t1.stats.requests, t1.stats.errors), (2, 1));
+        Ok(())
+    }
+
+    /// Memory store which counts saves
+    struct CountingStore(MemoryStore, Arc<Mutex<usize>>);
+
+    impl StateStore for CountingStore {
+        fn load(&mut self) -> Result<HashMap<String, Snapshot>> {
+            self.0.load()
+        }
+
+        fn save(&mut self, snapshots: &HashMap<String, Snapshot>) -> Result<()> {
+            *self.1.lock().unwrap() += 1;
+            self.0.save(snapshots)
+        }
+    }
+
+    #[test]
+    fn test_saves() -> Result<()> {
+        let saves = Arc::new(Mutex::new(0));
+        let store = CountingStore(MemoryStore::new(), saves.clone());
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        let mut device = Persistent::new(gateway, store)
+            .device("s1", PowerOn::Restore)
+            .restore()?;
+        assert_eq!(*saves.lock().unwrap(), 1);
+        // Reads don't change state, statistics are saved later
+        for _ in 0..10 {
+            state(&mut device, "s1");
+        }
+        assert_eq!(*saves.lock().unwrap(), 1);
+        device.handle(CommandRequest::builder().socket("s1").turn_on());
+        assert_eq!(*saves.lock().unwrap(), 2);
+        let mut device = device.with_stats_interval(Duration::ZERO);
+        state(&mut device, "s1");
+        assert_eq!(*saves.lock().unwrap(), 3);
+        // Nothing is pending
+        drop(device);
+        assert_eq!(*saves.lock().unwrap(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trip() -> Result<()> {
+        let mut store = MemoryStore::new();
+        let mut socket = Socket::new("s1");
+        // Load is random from 20W to 1000W
+        socket.set_max_load(510.);
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", socket);
+        let mut device = Persistent::new(gateway, store.clone())
+            .device("s1", PowerOn::Restore)
+            .restore()?;
+        let socket = || CommandRequest::builder().socket("s1");
+        // Load is checked on turn on until socket trips
+        loop {
+            device.handle(socket().turn_off());
+            let response = device.handle(socket().turn_on());
+            if matches!(response.response(), ResponseType::Err(_)) {
+                break;
+            }
+        }
+        assert_eq!(store.load()?["s1"].state, Some(SocketState::Off));
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_store() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("state_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut store = FileStore::new(&path);
+        assert!(store.load()?.is_empty());
+        let snapshots = HashMap::from([(
+            "s1".to_string(),
+            Snapshot {
+                state: Some(SocketState::On),
+                stats: Stats::default(),
+            },
+        )]);
+        store.save(&snapshots)?;
+        assert_eq!(FileStore::new(&path).load()?, snapshots);
+        Ok(())
+    }
+}
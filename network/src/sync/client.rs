@@ -1,18 +1,21 @@
 /// Module provides Client trait and Clients for TCP and UDP protocols
 use std::{
-    io::{Read, Write},
     net::{TcpStream, ToSocketAddrs, UdpSocket},
+    time::Duration,
 };
 
+use tracing::debug;
+
 use crate::{
     command::{CommandRequest, CommandResponse},
-    Result, BUFLEN,
+    error::NetworkResult,
+    frame, hooks, BUFLEN,
 };
 
 /// Client which unite TCP and UDP sockets
 pub trait Client {
-    fn send(&mut self, request: CommandRequest) -> Result<()>;
-    fn receive(&mut self) -> Result<CommandResponse>;
+    fn send(&mut self, request: CommandRequest) -> NetworkResult<()>;
+    fn receive(&mut self) -> NetworkResult<CommandResponse>;
 }
 
 pub struct TCPClient {
@@ -20,23 +23,28 @@ pub struct TCPClient {
 }
 
 impl TCPClient {
-    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+    pub fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
         let stream = TcpStream::connect(addr)?;
         Ok(Self { stream })
     }
+
+    /// Fail with NetworkError::Timeout if server doesn't answer in time
+    pub fn with_timeout(self, timeout: Duration) -> NetworkResult<Self> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.stream.set_write_timeout(Some(timeout))?;
+        Ok(self)
+    }
 }
 
 impl Client for TCPClient {
-    fn send(&mut self, request: CommandRequest) -> Result<()> {
-        let buf = serde_json::to_vec(&request)?;
-        self.stream.write_all(&buf)?;
-        Ok(())
+    fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
+        frame::write(&mut self.stream, &request)
     }
 
-    fn receive(&mut self) -> Result<CommandResponse> {
-        let mut buf = vec![0; BUFLEN];
-        let size = self.stream.read(&mut buf)?;
-        let resp: CommandResponse = serde_json::from_slice(&buf[0..size])?;
+    fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let resp: CommandResponse = frame::read(&mut self.stream)?;
+        debug!(device = resp.id(), "received response");
         Ok(resp)
     }
 }
@@ -46,23 +54,31 @@ pub struct UDPClient {
 }
 
 impl UDPClient {
-    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+    pub fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
         let socket = UdpSocket::bind("0.0.0.0:0")?;
         socket.connect(addr)?;
         Ok(Self { socket })
     }
+
+    /// Fail with NetworkError::Timeout if server doesn't answer in time
+    pub fn with_timeout(self, timeout: Duration) -> NetworkResult<Self> {
+        self.socket.set_read_timeout(Some(timeout))?;
+        Ok(self)
+    }
 }
 
 impl Client for UDPClient {
-    fn send(&mut self, request: CommandRequest) -> Result<()> {
+    fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
         let buf = serde_json::to_vec(&request)?;
         self.socket.send(&buf)?;
         Ok(())
     }
-    fn receive(&mut self) -> Result<CommandResponse> {
+    fn receive(&mut self) -> NetworkResult<CommandResponse> {
         let mut buf = vec![0; BUFLEN];
         let size = self.socket.recv(&mut buf)?;
-        let resp: CommandResponse = serde_json::from_slice(&buf[0..size])?;
+        let resp = CommandResponse::response_from(&buf[0..size])?;
+        debug!(device = resp.id(), "received response");
         Ok(resp)
     }
 }
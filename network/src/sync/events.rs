@@ -0,0 +1,373 @@
+/// Forwarding of smart home events to remote subscribers over UDP
+/// Subscriber registers itself with Subscribe datagram,
+/// then each Event from the bus is sent to it as JSON datagram
+/// Subscription expires unless it is renewed within time to live,
+/// number of subscribers is limited and unreachable ones are removed
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use smart_home::events::{Event, EventBus};
+use tracing::{debug, warn};
+
+use crate::{
+    error::{NetworkError, NetworkResult},
+    BUFLEN,
+};
+
+/// Time to wait for acknowledgement of subscription
+const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Subscription which is not renewed in time is removed
+pub const DEFAULT_SUBSCRIPTION_TTL: Duration = Duration::from_secs(60);
+/// New subscribers are not acknowledged when there are that many active ones
+pub const DEFAULT_MAX_SUBSCRIBERS: usize = 64;
+/// Subscriber is removed after that many failed sends in a row
+const MAX_FAILURES: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Subscription {
+    Subscribe,
+    Subscribed { ttl: Duration }, // Acknowledgement, subscription should be renewed within ttl
+    Unsubscribe,
+}
+
+struct Subscriber {
+    expires: Instant,
+    failures: u32, // Failed sends in a row
+}
+
+type Subscribers = Arc<Mutex<HashMap<SocketAddr, Subscriber>>>;
+
+pub struct EventForwarder {
+    socket: UdpSocket,
+    subscribers: Subscribers,
+    ttl: Duration,
+    max_subscribers: usize,
+}
+
+impl EventForwarder {
+    pub fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        Ok(Self {
+            socket,
+            subscribers: Arc::default(),
+            ttl: DEFAULT_SUBSCRIPTION_TTL,
+            max_subscribers: DEFAULT_MAX_SUBSCRIBERS,
+        })
+    }
+
+    /// Subscribers renew subscription within the time, others are removed
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Limit of active subscribers
+    pub fn with_max_subscribers(mut self, max: usize) -> Self {
+        self.max_subscribers = max;
+        self
+    }
+
+    /// Bound address, e.g. when bound to port 0
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Forward events from the bus to subscribers
+    /// Subscriptions are handled in a separate thread
+    /// Failed send to one subscriber doesn't stop forwarding to others,
+    /// subscriber is removed after MAX_FAILURES in a row
+    pub fn listen(&self, events: &EventBus) -> NetworkResult<()> {
+        let (_, rx) = events.subscribe_channel();
+        let socket = self.socket.try_clone()?;
+        let subscribers = self.subscribers.clone();
+        let (ttl, max) = (self.ttl, self.max_subscribers);
+        thread::spawn(move || Self::handle_subscriptions(socket, subscribers, ttl, max));
+        for event in rx {
+            let buf = serde_json::to_vec(&event)?;
+            let now = Instant::now();
+            let mut subscribers = self.subscribers.lock().unwrap();
+            subscribers.retain(|addr, subscriber| {
+                if subscriber.expires <= now {
+                    debug!(%addr, "subscription is expired");
+                    return false;
+                }
+                match self.socket.send_to(&buf, addr) {
+                    Ok(_) => subscriber.failures = 0,
+                    Err(e) => {
+                        warn!(%addr, error = %e, "event is not forwarded");
+                        subscriber.failures += 1;
+                    }
+                }
+                subscriber.failures < MAX_FAILURES
+            });
+        }
+        Ok(())
+    }
+
+    /// Register, renew and unregister subscribers
+    /// Subscribe is acknowledged with Subscribed, new subscriber over the limit
+    /// is not acknowledged
+    /// Unreachable subscriber is reported by the next receive, it is skipped
+    fn handle_subscriptions(
+        socket: UdpSocket,
+        subscribers: Subscribers,
+        ttl: Duration,
+        max: usize,
+    ) -> NetworkResult<()> {
+        let mut buf = vec![0u8; BUFLEN];
+        loop {
+            let (size, addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => match NetworkError::from(e) {
+                    NetworkError::ConnectionRefused | NetworkError::Closed => continue,
+                    e => return Err(e),
+                },
+            };
+            let Ok(subscription) = serde_json::from_slice::<Subscription>(&buf[0..size]) else {
+                continue;
+            };
+            let mut subscribers = subscribers.lock().unwrap();
+            match subscription {
+                Subscription::Subscribe => {
+                    let now = Instant::now();
+                    if !subscribers.contains_key(&addr) {
+                        subscribers.retain(|_, s| s.expires > now);
+                        if subscribers.len() >= max {
+                            warn!(%addr, "subscription is rejected: too many subscribers");
+                            continue;
+                        }
+                    }
+                    subscribers.insert(
+                        addr,
+                        Subscriber {
+                            expires: now + ttl,
+                            failures: 0,
+                        },
+                    );
+                    let ack = serde_json::to_vec(&Subscription::Subscribed { ttl })?;
+                    if let Err(e) = socket.send_to(&ack, addr) {
+                        warn!(%addr, error = %e, "subscription is not acknowledged");
+                    }
+                }
+                Subscription::Unsubscribe => {
+                    subscribers.remove(&addr);
+                }
+                Subscription::Subscribed { .. } => {}
+            }
+        }
+    }
+}
+
+/// Remote subscriber of the EventForwarder
+/// Subscription is renewed while events are received, unsubscribes when dropped
+pub struct EventSubscriber {
+    socket: UdpSocket,
+    renew: Duration, // Half of the subscription time to live
+    renewed: Instant,
+    timeout: Option<Duration>,
+}
+
+impl EventSubscriber {
+    /// Subscribe and wait for acknowledgement for SUBSCRIBE_TIMEOUT
+    pub fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
+        Self::subscribe(addr, SUBSCRIBE_TIMEOUT)
+    }
+
+    /// Subscribe, Timeout is reported if forwarder doesn't acknowledge in time
+    pub fn subscribe<A: ToSocketAddrs>(addr: A, timeout: Duration) -> NetworkResult<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        socket.set_read_timeout(Some(timeout))?;
+        socket.send(&serde_json::to_vec(&Subscription::Subscribe)?)?;
+        let mut buf = vec![0u8; BUFLEN];
+        let size = socket.recv(&mut buf)?;
+        let Subscription::Subscribed { ttl } = serde_json::from_slice(&buf[0..size])? else {
+            return Err(NetworkError::Framing(
+                "Unexpected subscription message".into(),
+            ));
+        };
+        Ok(Self {
+            socket,
+            renew: ttl / 2,
+            renewed: Instant::now(),
+            timeout: None,
+        })
+    }
+
+    /// Events are waited for the timeout, receive reports Timeout after it
+    pub fn with_timeout(mut self, timeout: Duration) -> NetworkResult<Self> {
+        self.timeout = Some(timeout);
+        Ok(self)
+    }
+
+    /// Wait for the next event, subscription is renewed meanwhile
+    pub fn receive(&mut self) -> NetworkResult<Event> {
+        let started = Instant::now();
+        let mut buf = vec![0u8; BUFLEN];
+        loop {
+            if self.renewed.elapsed() >= self.renew {
+                self.socket
+                    .send(&serde_json::to_vec(&Subscription::Subscribe)?)?;
+                self.renewed = Instant::now();
+            }
+            let mut wait = self.renew.saturating_sub(self.renewed.elapsed());
+            if let Some(timeout) = self.timeout {
+                let left = timeout.saturating_sub(started.elapsed());
+                if left.is_zero() {
+                    return Err(NetworkError::Timeout);
+                }
+                wait = wait.min(left);
+            }
+            // Zero timeout is not allowed by the socket
+            self.socket
+                .set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+            match self.socket.recv(&mut buf) {
+                // Acknowledgements of renewals are skipped
+                Ok(size) => match serde_json::from_slice(&buf[0..size]) {
+                    Ok(event) => return Ok(event),
+                    Err(_) => continue,
+                },
+                Err(e) => match NetworkError::from(e) {
+                    NetworkError::Timeout => continue,
+                    e => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+impl Drop for EventSubscriber {
+    fn drop(&mut self) {
+        if let Ok(buf) = serde_json::to_vec(&Subscription::Unsubscribe) {
+            let _ = self.socket.send(&buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smart_home::{
+        devices::{Socket, SocketState},
+        SmartHome,
+    };
+
+    #[test]
+    fn test_forwarding() {
+        let bus = EventBus::new();
+        let forwarder = EventForwarder::new("127.0.0.1:0").unwrap();
+        let addr = forwarder.local_addr().unwrap();
+        let b = bus.clone();
+        thread::spawn(move || forwarder.listen(&b));
+
+        // Subscriber which disappears without unsubscribing
+        let gone = UdpSocket::bind("127.0.0.1:0").unwrap();
+        gone.connect(addr).unwrap();
+        gone.send(&serde_json::to_vec(&Subscription::Subscribe).unwrap())
+            .unwrap();
+        gone.recv(&mut [0u8; BUFLEN]).unwrap();
+        drop(gone);
+
+        let mut subscriber = EventSubscriber::new(addr)
+            .unwrap()
+            .with_timeout(Duration::from_secs(1))
+            .unwrap();
+        let mut home = SmartHome::new("My home");
+        home.set_event_bus(bus.clone());
+        let mut socket = Socket::new("s1000");
+        socket.set_event_bus(bus);
+
+        home.add_room("kitchen").unwrap();
+        socket.turn_on().unwrap();
+        assert_eq!(
+            subscriber.receive().unwrap(),
+            Event::RoomAdded {
+                room: "kitchen".into()
+            }
+        );
+        assert_eq!(
+            subscriber.receive().unwrap(),
+            Event::SocketStateChanged {
+                device: "s1000".into(),
+                state: SocketState::On
+            }
+        );
+        socket.turn_off().unwrap();
+        assert!(subscriber.receive().is_ok());
+    }
+
+    #[test]
+    fn test_subscribe_timeout() {
+        // Nobody acknowledges subscription
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let subscriber =
+            EventSubscriber::subscribe(silent.local_addr().unwrap(), Duration::from_millis(100));
+        assert!(matches!(subscriber, Err(NetworkError::Timeout)));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let bus = EventBus::new();
+        let forwarder = EventForwarder::new("127.0.0.1:0")
+            .unwrap()
+            .with_ttl(Duration::from_millis(200));
+        let addr = forwarder.local_addr().unwrap();
+        let b = bus.clone();
+        thread::spawn(move || forwarder.listen(&b));
+
+        // Subscriber which doesn't renew subscription
+        let stale = UdpSocket::bind("127.0.0.1:0").unwrap();
+        stale.connect(addr).unwrap();
+        stale
+            .send(&serde_json::to_vec(&Subscription::Subscribe).unwrap())
+            .unwrap();
+        stale.recv(&mut [0u8; BUFLEN]).unwrap();
+
+        let mut subscriber = EventSubscriber::new(addr)
+            .unwrap()
+            .with_timeout(Duration::from_secs(2))
+            .unwrap();
+        let event = Event::RoomAdded {
+            room: "kitchen".into(),
+        };
+        let e = event.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            bus.publish(e);
+        });
+        assert_eq!(subscriber.receive().unwrap(), event);
+        stale
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        assert!(stale.recv(&mut [0u8; BUFLEN]).is_err());
+    }
+
+    #[test]
+    fn test_max_subscribers() {
+        let forwarder = EventForwarder::new("127.0.0.1:0")
+            .unwrap()
+            .with_max_subscribers(1);
+        let addr = forwarder.local_addr().unwrap();
+        thread::spawn(move || forwarder.listen(&EventBus::new()));
+
+        let first = EventSubscriber::new(addr).unwrap();
+        let second = EventSubscriber::subscribe(addr, Duration::from_millis(100));
+        assert!(matches!(second, Err(NetworkError::Timeout)));
+        // Renewal of active subscriber is acknowledged
+        first
+            .socket
+            .send(&serde_json::to_vec(&Subscription::Subscribe).unwrap())
+            .unwrap();
+        first
+            .socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        assert!(first.socket.recv(&mut [0u8; BUFLEN]).is_ok());
+    }
+}
@@ -0,0 +1,173 @@
+/// In-memory transport over channels, no ports are used
+/// Requests and responses are serialized as with network transports,
+/// so protocol and device logic could be tested hermetically
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    command::{CommandRequest, CommandResponse},
+    device::Device,
+    error::{NetworkError, NetworkResult},
+    sync::{Client, NetworkDevice, RemoteDevice, Server, SharedDevice},
+};
+
+// Request with sender for the response
+type Envelope = (Vec<u8>, Sender<Vec<u8>>);
+
+/// Listener stops when all connectors and clients are dropped
+pub struct MemoryServer {
+    incoming: Receiver<Envelope>,
+}
+
+impl MemoryServer {
+    /// Server and connector which creates clients
+    pub fn new() -> (Self, MemoryConnector) {
+        let (tx, incoming) = channel();
+        (Self { incoming }, MemoryConnector { tx })
+    }
+}
+
+impl Server for MemoryServer {
+    fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        while let Ok((request, reply)) = self.incoming.recv() {
+            let request = CommandRequest::request_from(&request)?;
+            let response = device.write().unwrap().handle(request);
+            // Client could be dropped before response
+            let _ = reply.send(response.into());
+        }
+        Ok(())
+    }
+}
+
+/// Address of the server, clones connect to the same server
+#[derive(Clone)]
+pub struct MemoryConnector {
+    tx: Sender<Envelope>,
+}
+
+impl MemoryConnector {
+    pub fn connect(&self) -> MemoryClient {
+        let (reply, responses) = channel();
+        MemoryClient {
+            server: self.tx.clone(),
+            reply,
+            responses: Mutex::new(responses),
+        }
+    }
+}
+
+pub struct MemoryClient {
+    server: Sender<Envelope>,
+    reply: Sender<Vec<u8>>,
+    responses: Mutex<Receiver<Vec<u8>>>, // Client could be shared as RemoteDevice
+}
+
+impl Client for MemoryClient {
+    fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        let buf = serde_json::to_vec(&request)?;
+        self.server
+            .send((buf, self.reply.clone()))
+            .map_err(|_| NetworkError::Closed)?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let buf = self
+            .responses
+            .get_mut()
+            .unwrap()
+            .recv()
+            .map_err(|_| NetworkError::Closed)?;
+        let resp: CommandResponse = serde_json::from_slice(&buf)?;
+        Ok(resp)
+    }
+}
+
+/// Test harness: devices served in background threads over in-memory transport
+/// Threads stop when harness and its clients are dropped
+#[derive(Default)]
+pub struct Harness {
+    devices: HashMap<String, MemoryConnector>,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve device with the id
+    pub fn spawn<D: Device + Send + Sync + 'static>(&mut self, id: &str, device: D) {
+        let (server, connector) = MemoryServer::new();
+        let device = NetworkDevice::with_server(device, server);
+        thread::spawn(move || device.listen());
+        self.devices.insert(id.to_string(), connector);
+    }
+
+    pub fn client(&self, id: &str) -> NetworkResult<MemoryClient> {
+        let connector = self
+            .devices
+            .get(id)
+            .ok_or_else(|| NetworkError::Address(format!("No such device {id}")))?;
+        Ok(connector.connect())
+    }
+
+    /// Device behind the transport, e.g. for Gateway or RulesEngine
+    pub fn remote(&self, id: &str) -> NetworkResult<RemoteDevice<MemoryClient>> {
+        Ok(RemoteDevice::new(self.client(id)?))
+    }
+
+    /// Send request to the device with request id and wait for response
+    pub fn request(&self, request: CommandRequest) -> NetworkResult<CommandResponse> {
+        let mut client = self.client(request.id())?;
+        client.send(request)?;
+        client.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smart_home::devices::{Socket, Thermometer};
+
+    use super::*;
+    use crate::command::ResponseType;
+
+    #[test]
+    fn test_memory_transport() {
+        let (server, connector) = MemoryServer::new();
+        let device = NetworkDevice::with_server(Socket::new("s1"), server);
+        let t = thread::spawn(move || device.listen());
+
+        let mut client = connector.connect();
+        client
+            .send(CommandRequest::builder().socket("s1").turn_on())
+            .unwrap();
+        let response = client.receive().unwrap();
+        assert_eq!(response.response(), &ResponseType::Success("".into()));
+
+        // Listener stops without connections
+        drop((client, connector));
+        assert!(t.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_harness() {
+        let mut harness = Harness::new();
+        harness.spawn("s1", Socket::new("s1"));
+        harness.spawn("t1", Thermometer::new("t1"));
+
+        let response = harness
+            .request(CommandRequest::builder().therm("t1").get_temp())
+            .unwrap();
+        assert!(matches!(response.response(), ResponseType::Success(_)));
+        let mut remote = harness.remote("s1").unwrap();
+        let response = remote.process(CommandRequest::builder().socket("s1").get_state());
+        assert!(matches!(response.response(), ResponseType::Success(_)));
+        assert!(harness.client("s2").is_err());
+    }
+}
@@ -1,7 +1,19 @@
 pub mod client;
+pub mod events;
+pub mod memory;
 mod network_device;
+mod remote_device;
 pub mod server;
+pub mod tls;
+#[cfg(unix)]
+pub mod unix;
 
 pub use client::{Client, TCPClient, UDPClient};
+pub use events::{EventForwarder, EventSubscriber};
+pub use memory::{Harness, MemoryClient, MemoryConnector, MemoryServer};
 pub use network_device::NetworkDevice;
-pub use server::{Server, SharedDevice, TCPServer, UDPServer};
+pub use remote_device::RemoteDevice;
+pub use server::{Bind, Server, SharedDevice, TCPServer, UDPServer};
+pub use tls::{TLSClient, TLSServer};
+#[cfg(unix)]
+pub use unix::{UnixClient, UnixDatagramClient, UnixDatagramServer, UnixServer};
@@ -6,8 +6,8 @@ use std::sync::{Arc, RwLock};
 
 use crate::{
     device::Device,
-    sync::{Server, SharedDevice},
-    Result,
+    error::NetworkResult,
+    sync::{Bind, Server, SharedDevice},
 };
 
 pub struct NetworkDevice<T: Server> {
@@ -15,20 +15,27 @@ pub struct NetworkDevice<T: Server> {
     device: SharedDevice,
 }
 
-impl<T: Server> NetworkDevice<T> {
+impl<T: Server + Bind> NetworkDevice<T> {
     pub fn new<A: ToSocketAddrs, D: Device + Send + Sync + 'static>(
         device: D,
         addr: A,
-    ) -> Result<Self> {
+    ) -> NetworkResult<Self> {
         let listener = T::new(addr)?;
+        Ok(Self::with_server(device, listener))
+    }
+}
+
+impl<T: Server> NetworkDevice<T> {
+    /// Device with already created server, e.g. bound to Unix socket
+    pub fn with_server<D: Device + Send + Sync + 'static>(device: D, server: T) -> Self {
         let device = Arc::new(RwLock::new(device)) as SharedDevice;
-        Ok(Self {
-            transport: listener,
+        Self {
+            transport: server,
             device,
-        })
+        }
     }
 
-    pub fn listen(&self) -> Result<()> {
+    pub fn listen(&self) -> NetworkResult<()> {
         self.transport.listen(self.device.clone())
     }
 }
@@ -0,0 +1,38 @@
+/// Implements RemoteDevice structure,
+/// which makes device behind Client look like local Device
+use crate::{
+    command::{CommandRequest, CommandResponse, ResponseType},
+    device::Device,
+    sync::Client,
+};
+
+pub struct RemoteDevice<C: Client> {
+    client: C,
+}
+
+impl<C: Client> RemoteDevice<C> {
+    pub fn new(client: C) -> Self {
+        Self { client }
+    }
+}
+
+impl<C: Client> Device for RemoteDevice<C> {
+    /// Send request and wait for response
+    /// Transport errors are returned as ResponseType::Err
+    fn process(&mut self, request: CommandRequest) -> CommandResponse {
+        let id = request.id().to_string();
+        match self
+            .client
+            .send(request)
+            .and_then(|_| self.client.receive())
+        {
+            Ok(response) => response,
+            Err(e) => CommandResponse::new(&id, ResponseType::Err(e.to_string())),
+        }
+    }
+
+    /// Batch is sent to the remote side in one request
+    fn handle(&mut self, request: CommandRequest) -> CommandResponse {
+        self.process(request)
+    }
+}
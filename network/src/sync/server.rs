@@ -1,24 +1,36 @@
 /// Provides Transport trait and UDP and TCP types
 use std::{
     io::{Read, Write},
-    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    net::{SocketAddr, TcpListener, ToSocketAddrs, UdpSocket},
     sync::{Arc, RwLock},
+    time::Instant,
 };
 
+use tracing::{debug, info_span, warn};
+
 use crate::{
-    command::{CommandRequest, CommandResponse},
-    device::Device,
-    Result, BUFLEN,
+    command::{CommandRequest, CommandResponse, Rejected},
+    device::{Device, Peer},
+    error::{NetworkError, NetworkResult},
+    frame,
+    hooks::{self, Hooks},
+    limit::{RateLimiter, Verdict},
+    metrics::ServerMetrics,
+    BUFLEN,
 };
 
 pub type SharedDevice = Arc<RwLock<dyn Device + Send + Sync>>;
 
-/// Clue for UDP and TCP sockets
+/// Clue for UDP, TCP and Unix sockets
 /// Each socket could receive CommandRequest, redirect it to NetworkDevice
 /// and send CommandResponse back
-pub trait Server: Sized {
-    fn new<A: ToSocketAddrs>(addr: A) -> Result<Self>;
-    fn listen(&self, device: SharedDevice) -> Result<()>;
+pub trait Server {
+    fn listen(&self, device: SharedDevice) -> NetworkResult<()>;
+}
+
+/// Server bound to IP address
+pub trait Bind: Sized {
+    fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self>;
 }
 
 /// Single threaded listener
@@ -26,49 +38,96 @@ pub trait Server: Sized {
 /// only once
 pub struct TCPServer {
     listener: TcpListener,
+    hooks: Hooks,
 }
 
 impl TCPServer {
+    /// Bound address, e.g. when bound to port 0
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Limit requests of clients
+    pub fn with_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.hooks.limiter = Some(limiter);
+        self
+    }
+
+    /// Count requests, errors and latency
+    pub fn with_metrics(mut self, metrics: ServerMetrics) -> Self {
+        self.hooks.metrics = Some(metrics);
+        self
+    }
+
     /// Handle "one time" connection
-    fn handle(mut con: TcpStream, device: SharedDevice) -> Result<()> {
+    /// Any stream could be handled, e.g. Unix socket
+    /// Connection is closed if request over the limit should be dropped
+    pub(super) fn handle<S: Read + Write>(
+        mut con: S,
+        peer: Peer,
+        device: SharedDevice,
+        hooks: &Hooks,
+    ) -> NetworkResult<()> {
+        let _connection = hooks.connect();
+        let span = info_span!("connection", %peer);
+        let _entered = span.enter();
+        debug!("accepted connection");
         // receive CommandRequest
         while let Ok(request) = Self::receive(&mut con) {
-            // obtain NetworkDevice
-            let mut device = device.write().unwrap();
-            // process CommandRequest
-            let resp = device.process(request);
+            let started = Instant::now();
+            let kind = request.req_type().name();
+            debug!(device = request.id(), kind, "received request");
+            let resp = match hooks.check(&peer) {
+                Verdict::Allow => {
+                    // obtain NetworkDevice
+                    let mut device = device.write().unwrap();
+                    // process CommandRequest
+                    hooks::span(&request).in_scope(|| device.handle_from(&peer, request))
+                }
+                Verdict::Drop => break,
+                Verdict::Reject(e) => CommandResponse::rejected(request.id(), Rejected::Limit(e)),
+            };
+            hooks.observe(kind, started, &resp);
             // send CommandResponse back
             Self::send(&mut con, resp)?;
         }
+        debug!("connection closed");
         Ok(())
     }
 
-    /// Receive CommandRequest from tcp stream
-    /// receiver buffer is limited with BUFLEN
-    fn receive(con: &mut TcpStream) -> Result<CommandRequest> {
-        let mut buf = vec![0u8; BUFLEN];
-        let size = con.read(&mut buf)?;
-        let req = CommandRequest::request_from(&buf[0..size])?;
-        Ok(req)
+    /// Receive framed CommandRequest from the stream
+    fn receive<S: Read>(con: &mut S) -> NetworkResult<CommandRequest> {
+        frame::read(con)
     }
 
-    /// Send CommandResponse back
-    fn send(con: &mut TcpStream, resp: CommandResponse) -> Result<()> {
-        let buf: Vec<u8> = resp.into();
-        con.write_all(&buf)?;
-        Ok(())
+    /// Send framed CommandResponse back
+    fn send<S: Write>(con: &mut S, resp: CommandResponse) -> NetworkResult<()> {
+        frame::write(con, &resp)
     }
 }
 
-impl Server for TCPServer {
-    fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+impl Bind for TCPServer {
+    fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
         let listener = TcpListener::bind(addr)?;
-        Ok(Self { listener })
+        Ok(Self {
+            listener,
+            hooks: Hooks::default(),
+        })
     }
-    fn listen(&self, device: SharedDevice) -> Result<()> {
+}
+
+impl Server for TCPServer {
+    fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
         for con in self.listener.incoming() {
             let con = con?;
-            Self::handle(con, device.clone())?;
+            // Client could reset connection before it is handled
+            let Ok(addr) = con.peer_addr() else {
+                debug!("connection is reset by client");
+                continue;
+            };
+            if let Err(e) = Self::handle(con, Peer::new(addr.ip()), device.clone(), &self.hooks) {
+                warn!(%addr, error = %e, "connection failed");
+            }
         }
         Ok(())
     }
@@ -76,39 +135,93 @@ impl Server for TCPServer {
 
 pub struct UDPServer {
     socket: UdpSocket,
+    hooks: Hooks,
 }
 
 impl UDPServer {
+    /// Bound address, e.g. when bound to port 0
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Limit requests of clients
+    pub fn with_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.hooks.limiter = Some(limiter);
+        self
+    }
+
+    /// Count requests, errors and latency
+    pub fn with_metrics(mut self, metrics: ServerMetrics) -> Self {
+        self.hooks.metrics = Some(metrics);
+        self
+    }
+
     // Receive CommandRequest from socket
-    fn receive(&self) -> Result<(SocketAddr, CommandRequest)> {
+    // Malformed datagram is skipped, as well as error of previous send
+    // to unreachable client, which is reported on some platforms
+    fn receive(&self) -> NetworkResult<Option<(SocketAddr, CommandRequest)>> {
         let mut buf = vec![0u8; BUFLEN];
-        let (size, addr) = self.socket.recv_from(&mut buf)?;
-        let req = CommandRequest::request_from(&buf[0..size])?;
-        Ok((addr, req))
+        let (size, addr) = match self.socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) => match NetworkError::from(e) {
+                NetworkError::ConnectionRefused | NetworkError::Closed => return Ok(None),
+                e => return Err(e),
+            },
+        };
+        match CommandRequest::request_from(&buf[0..size]) {
+            Ok(req) => Ok(Some((addr, req))),
+            Err(e) => {
+                debug!(%addr, error = %e, "malformed request is skipped");
+                Ok(None)
+            }
+        }
     }
 
     /// Send CommandResponse to addr
-    fn send<A: ToSocketAddrs>(&self, resp: CommandResponse, addr: A) -> Result<()> {
+    fn send<A: ToSocketAddrs>(&self, resp: CommandResponse, addr: A) -> NetworkResult<()> {
         let buf: Vec<u8> = resp.into();
         self.socket.send_to(&buf, addr)?;
         Ok(())
     }
 
-    fn handle(&self, device: SharedDevice) -> Result<()> {
-        let (addr, request) = self.receive()?;
-        let mut device = device.write().unwrap();
-        let response = device.process(request);
-        self.send(response, addr)?;
+    /// Requests over the limit are checked before device is locked
+    /// Only failure of the socket is returned, failed datagrams are skipped
+    fn handle(&self, device: SharedDevice) -> NetworkResult<()> {
+        let Some((addr, request)) = self.receive()? else {
+            return Ok(());
+        };
+        let peer = Peer::new(addr.ip());
+        let started = Instant::now();
+        let kind = request.req_type().name();
+        debug!(%peer, device = request.id(), kind, "received request");
+        let response = match self.hooks.check(&peer) {
+            Verdict::Allow => {
+                let mut device = device.write().unwrap();
+                hooks::span(&request).in_scope(|| device.handle_from(&peer, request))
+            }
+            Verdict::Drop => return Ok(()),
+            Verdict::Reject(e) => CommandResponse::rejected(request.id(), Rejected::Limit(e)),
+        };
+        self.hooks.observe(kind, started, &response);
+        if let Err(e) = self.send(response, addr) {
+            warn!(%addr, error = %e, "response is not sent");
+        }
         Ok(())
     }
 }
 
-impl Server for UDPServer {
-    fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+impl Bind for UDPServer {
+    fn new<A: ToSocketAddrs>(addr: A) -> NetworkResult<Self> {
         let socket = UdpSocket::bind(addr)?;
-        Ok(Self { socket })
+        Ok(Self {
+            socket,
+            hooks: Hooks::default(),
+        })
     }
-    fn listen(&self, device: SharedDevice) -> Result<()> {
+}
+
+impl Server for UDPServer {
+    fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
         loop {
             self.handle(device.clone())?;
         }
@@ -117,36 +230,111 @@ impl Server for UDPServer {
 
 #[cfg(test)]
 mod tests {
-    use crate::sync::{Client, TCPClient, UDPClient};
-    use smart_home::devices::Thermometer;
-    use std::thread;
+    use crate::{
+        command::ResponseType,
+        gateway::Gateway,
+        sync::{Client, TCPClient, UDPClient},
+    };
+    use smart_home::devices::{Socket, Thermometer};
+    use std::{thread, time::Duration};
 
     use super::*;
     #[test]
     fn test_tcp_listener() {
-        let listener = TCPServer::new("127.0.0.1:8010").unwrap();
+        let listener = TCPServer::new("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
         let device = Arc::new(RwLock::new(Thermometer::new("123")));
         let _t = thread::spawn(move || listener.listen(device));
 
-        let mut s = TCPClient::new("127.0.0.1:8010").unwrap();
+        let mut s = TCPClient::new(addr).unwrap();
         s.send(CommandRequest::builder().therm("123").get_temp())
             .unwrap();
 
         let resp = s.receive().unwrap();
-        println!("{resp:?}");
+        assert!(matches!(resp.response(), ResponseType::Success(_)));
+    }
+
+    #[test]
+    fn test_tcp_batch() {
+        let listener = TCPServer::new("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut gateway = Gateway::new();
+        gateway.add_device("s1", Socket::new("s1"));
+        gateway.add_device("t1", Thermometer::new("t1"));
+        let device = Arc::new(RwLock::new(gateway));
+        let _t = thread::spawn(move || listener.listen(device));
+
+        let mut s = TCPClient::new(addr).unwrap();
+        let batch = CommandRequest::builder()
+            .batch("gw")
+            .command(CommandRequest::builder().socket("s1").turn_on())
+            .command(CommandRequest::builder().socket("s1").get_state())
+            .command(CommandRequest::builder().therm("t1").get_temp())
+            .build();
+        s.send(batch).unwrap();
+
+        let resp = s.receive().unwrap();
+        match resp.response() {
+            ResponseType::Batch(responses) => assert_eq!(responses.len(), 3),
+            r => panic!("Unexpected response {r:?}"),
+        }
+    }
+
+    #[test]
+    fn test_tcp_large_batch() {
+        let listener = TCPServer::new("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let device = Arc::new(RwLock::new(Socket::new("s1")));
+        let _t = thread::spawn(move || listener.listen(device));
+
+        // Response doesn't fit single read of BUFLEN
+        let batch = (0..300).fold(CommandRequest::builder().batch("s1"), |batch, _| {
+            batch.command(CommandRequest::builder().socket("s1").get_state())
+        });
+        let mut s = TCPClient::new(addr).unwrap();
+        s.send(batch.build()).unwrap();
+        let resp = s.receive().unwrap();
+        assert!(serde_json::to_vec(&resp).unwrap().len() > BUFLEN);
+        match resp.response() {
+            ResponseType::Batch(responses) => assert_eq!(responses.len(), 300),
+            r => panic!("Unexpected response {r:?}"),
+        }
     }
 
     #[test]
     fn test_udp_listener() {
-        let listener = UDPServer::new("127.0.0.1:8011").unwrap();
+        let listener = UDPServer::new("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
         let device = Arc::new(RwLock::new(Thermometer::new("123")));
         let _t = thread::spawn(move || listener.listen(device));
 
-        let mut s = UDPClient::new("127.0.0.1:8011").unwrap();
+        let mut s = UDPClient::new(addr).unwrap();
         s.send(CommandRequest::builder().therm("123").get_temp())
             .unwrap();
 
         let resp = s.receive().unwrap();
-        println!("{resp:?}");
+        assert!(matches!(resp.response(), ResponseType::Success(_)));
+    }
+
+    #[test]
+    fn test_udp_malformed() {
+        let listener = UDPServer::new("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let device = Arc::new(RwLock::new(Thermometer::new("123")));
+        let t = thread::spawn(move || listener.listen(device));
+
+        // Junk and datagram over the buffer don't stop the server
+        let junk = UdpSocket::bind("127.0.0.1:0").unwrap();
+        junk.send_to(b"garbage", addr).unwrap();
+        junk.send_to(&[b'{'; BUFLEN + 1], addr).unwrap();
+        let mut s = UDPClient::new(addr)
+            .unwrap()
+            .with_timeout(Duration::from_secs(1))
+            .unwrap();
+        s.send(CommandRequest::builder().therm("123").get_temp())
+            .unwrap();
+        let resp = s.receive().unwrap();
+        assert!(matches!(resp.response(), ResponseType::Success(_)));
+        assert!(!t.is_finished());
     }
 }
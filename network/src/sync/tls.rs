@@ -0,0 +1,108 @@
+/// TLS variant of TCP server and client
+/// Configs are created with crate::tls from certificate and key files
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+};
+
+use rustls::{ClientConnection, ServerConfig, ServerConnection, StreamOwned};
+
+use tracing::{debug, warn};
+
+use crate::{
+    command::{CommandRequest, CommandResponse},
+    device::Peer,
+    error::NetworkResult,
+    frame,
+    hooks::{self, Hooks},
+    limit::RateLimiter,
+    metrics::ServerMetrics,
+    sync::{Client, Server, SharedDevice, TCPServer},
+    tls::{server_name, TlsClientConfig},
+};
+
+/// Single threaded listener, same as TCPServer
+/// Connection is closed if handshake fails, e.g. client certificate is not valid
+pub struct TLSServer {
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+    hooks: Hooks,
+}
+
+impl TLSServer {
+    pub fn new<A: ToSocketAddrs>(addr: A, config: Arc<ServerConfig>) -> NetworkResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(Self {
+            listener,
+            config,
+            hooks: Hooks::default(),
+        })
+    }
+
+    /// Bound address, e.g. when bound to port 0
+    pub fn local_addr(&self) -> NetworkResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Limit requests of clients
+    pub fn with_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.hooks.limiter = Some(limiter);
+        self
+    }
+
+    /// Count requests, errors and latency
+    pub fn with_metrics(mut self, metrics: ServerMetrics) -> Self {
+        self.hooks.metrics = Some(metrics);
+        self
+    }
+}
+
+impl Server for TLSServer {
+    fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        for con in self.listener.incoming() {
+            let con = con?;
+            // Client could reset connection before it is handled
+            let Ok(addr) = con.peer_addr() else {
+                debug!("connection is reset by client");
+                continue;
+            };
+            let connection = ServerConnection::new(self.config.clone())?;
+            let stream = StreamOwned::new(connection, con);
+            let peer = Peer::new(addr.ip());
+            if let Err(e) = TCPServer::handle(stream, peer, device.clone(), &self.hooks) {
+                warn!(%addr, error = %e, "connection failed");
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct TLSClient {
+    stream: StreamOwned<ClientConnection, TcpStream>,
+}
+
+impl TLSClient {
+    /// Server certificate should be valid for the name
+    pub fn new<A: ToSocketAddrs>(
+        addr: A,
+        name: &str,
+        config: Arc<TlsClientConfig>,
+    ) -> NetworkResult<Self> {
+        let connection = ClientConnection::new(config, server_name(name)?)?;
+        let stream = StreamOwned::new(connection, TcpStream::connect(addr)?);
+        Ok(Self { stream })
+    }
+}
+
+impl Client for TLSClient {
+    fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
+        frame::write(&mut self.stream, &request)
+    }
+
+    fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let resp: CommandResponse = frame::read(&mut self.stream)?;
+        debug!(device = resp.id(), "received response");
+        Ok(resp)
+    }
+}
@@ -0,0 +1,309 @@
+/// Servers and clients over Unix domain sockets: stream and datagram
+/// Access is controlled with permissions of the socket file,
+/// the file is removed when server is dropped
+use std::{
+    fs,
+    os::unix::net::{UnixDatagram, UnixListener, UnixStream},
+    path::{Path, PathBuf},
+};
+
+use tracing::{debug, warn};
+
+use crate::{
+    command::{CommandRequest, CommandResponse},
+    device::Peer,
+    error::NetworkResult,
+    frame,
+    hooks::{self, Hooks},
+    socket_file::{self, DEFAULT_MODE},
+    sync::{Client, Server, SharedDevice, TCPServer},
+    BUFLEN,
+};
+
+/// Single threaded listener, same as TCPServer
+pub struct UnixServer {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixServer {
+    pub fn new<P: AsRef<Path>>(path: P) -> NetworkResult<Self> {
+        Self::with_mode(path, DEFAULT_MODE)
+    }
+
+    /// Bind with permissions of the socket file, e.g. 0o600 for owner only
+    pub fn with_mode<P: AsRef<Path>>(path: P, mode: u32) -> NetworkResult<Self> {
+        let path = path.as_ref();
+        socket_file::remove_stale(path, false)?;
+        let listener = socket_file::bind(path, mode, |p| UnixListener::bind(p))?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Server for UnixServer {
+    fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        for con in self.listener.incoming() {
+            if let Err(e) =
+                TCPServer::handle(con?, Peer::default(), device.clone(), &Hooks::default())
+            {
+                warn!(error = %e, "connection failed");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UnixServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct UnixDatagramServer {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl UnixDatagramServer {
+    pub fn new<P: AsRef<Path>>(path: P) -> NetworkResult<Self> {
+        Self::with_mode(path, DEFAULT_MODE)
+    }
+
+    /// Bind with permissions of the socket file, e.g. 0o600 for owner only
+    pub fn with_mode<P: AsRef<Path>>(path: P, mode: u32) -> NetworkResult<Self> {
+        let path = path.as_ref();
+        socket_file::remove_stale(path, true)?;
+        let socket = socket_file::bind(path, mode, |p| UnixDatagram::bind(p))?;
+        Ok(Self {
+            socket,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Only failure of the socket is returned, failed datagrams are skipped
+    fn handle(&self, device: SharedDevice) -> NetworkResult<()> {
+        let mut buf = vec![0u8; BUFLEN];
+        let (size, addr) = self.socket.recv_from(&mut buf)?;
+        let request = match CommandRequest::request_from(&buf[0..size]) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!(error = %e, "malformed request is skipped");
+                return Ok(());
+            }
+        };
+        debug!(
+            device = request.id(),
+            kind = request.req_type().name(),
+            "received request"
+        );
+        let mut device = device.write().unwrap();
+        let response = hooks::span(&request).in_scope(|| device.handle(request));
+        // Response could be sent only to bound client, which is still there
+        let Some(addr) = addr.as_pathname() else {
+            warn!("response is not sent: client socket is not bound");
+            return Ok(());
+        };
+        let buf: Vec<u8> = response.into();
+        if let Err(e) = self.socket.send_to(&buf, addr) {
+            warn!(addr = %addr.display(), error = %e, "response is not sent");
+        }
+        Ok(())
+    }
+}
+
+impl Server for UnixDatagramServer {
+    fn listen(&self, device: SharedDevice) -> NetworkResult<()> {
+        loop {
+            self.handle(device.clone())?;
+        }
+    }
+}
+
+impl Drop for UnixDatagramServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct UnixClient {
+    stream: UnixStream,
+}
+
+impl UnixClient {
+    pub fn new<P: AsRef<Path>>(path: P) -> NetworkResult<Self> {
+        let stream = UnixStream::connect(path)?;
+        Ok(Self { stream })
+    }
+}
+
+impl Client for UnixClient {
+    fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
+        frame::write(&mut self.stream, &request)
+    }
+
+    fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let resp: CommandResponse = frame::read(&mut self.stream)?;
+        debug!(device = resp.id(), "received response");
+        Ok(resp)
+    }
+}
+
+/// Client is bound to temporary socket file to receive responses
+pub struct UnixDatagramClient {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl UnixDatagramClient {
+    pub fn new<P: AsRef<Path>>(path: P) -> NetworkResult<Self> {
+        let client_path = socket_file::client_path();
+        let socket = UnixDatagram::bind(&client_path)?;
+        let client = Self {
+            socket,
+            path: client_path,
+        };
+        client.socket.connect(path)?;
+        Ok(client)
+    }
+}
+
+impl Client for UnixDatagramClient {
+    fn send(&mut self, request: CommandRequest) -> NetworkResult<()> {
+        hooks::sending(&request);
+        let buf = serde_json::to_vec(&request)?;
+        self.socket.send(&buf)?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> NetworkResult<CommandResponse> {
+        let mut buf = vec![0; BUFLEN];
+        let size = self.socket.recv(&mut buf)?;
+        let resp = CommandResponse::response_from(&buf[0..size])?;
+        debug!(device = resp.id(), "received response");
+        Ok(resp)
+    }
+}
+
+impl Drop for UnixDatagramClient {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::{fs::PermissionsExt, net::UnixListener},
+        thread,
+        time::Duration,
+    };
+
+    use smart_home::devices::{Socket, Thermometer};
+
+    use super::*;
+    use crate::{command::ResponseType, sync::NetworkDevice};
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{name}_{}.sock", std::process::id()))
+    }
+
+    #[test]
+    fn test_unix_stream() {
+        let path = socket_path("unix_stream");
+        let server = UnixServer::with_mode(&path, 0o600).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Private directory used for binding is removed
+        let name = format!(".{}.", path.file_name().unwrap().to_string_lossy());
+        let private = fs::read_dir(std::env::temp_dir()).unwrap().filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&name)
+        });
+        assert_eq!(private.count(), 0);
+        // Socket file is used by running server
+        assert!(UnixServer::new(&path).is_err());
+
+        let device = NetworkDevice::with_server(Socket::new("s1"), server);
+        thread::spawn(move || device.listen());
+        let mut client = UnixClient::new(&path).unwrap();
+        client
+            .send(CommandRequest::builder().socket("s1").turn_on())
+            .unwrap();
+        let response = client.receive().unwrap();
+        assert_eq!(response.response(), &ResponseType::Success("".into()));
+    }
+
+    #[test]
+    fn test_unix_datagram() {
+        let path = socket_path("unix_datagram");
+        let server = UnixDatagramServer::new(&path).unwrap();
+        let device = NetworkDevice::with_server(Thermometer::new("t1"), server);
+        thread::spawn(move || device.listen());
+
+        let mut client = UnixDatagramClient::new(&path).unwrap();
+        client
+            .send(CommandRequest::builder().therm("t1").get_temp())
+            .unwrap();
+        let response = client.receive().unwrap();
+        assert!(matches!(response.response(), ResponseType::Success(_)));
+        let client_path = client.path.clone();
+        drop(client);
+        assert!(!client_path.exists());
+    }
+
+    #[test]
+    fn test_unix_datagram_failures() {
+        let path = socket_path("unix_datagram_failures");
+        let server = UnixDatagramServer::new(&path).unwrap();
+        let device = NetworkDevice::with_server(Thermometer::new("t1"), server);
+        let t = thread::spawn(move || device.listen());
+
+        let request =
+            serde_json::to_vec(&CommandRequest::builder().therm("t1").get_temp()).unwrap();
+        // Junk and request from unbound client
+        let unbound = UnixDatagram::unbound().unwrap();
+        unbound.send_to(b"garbage", &path).unwrap();
+        unbound.send_to(&request, &path).unwrap();
+        // Client which is gone before response
+        let gone_path = socket_file::client_path();
+        let gone = UnixDatagram::bind(&gone_path).unwrap();
+        gone.send_to(&request, &path).unwrap();
+        drop(gone);
+        fs::remove_file(&gone_path).unwrap();
+
+        let mut client = UnixDatagramClient::new(&path).unwrap();
+        client
+            .socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        client
+            .send(CommandRequest::builder().therm("t1").get_temp())
+            .unwrap();
+        let response = client.receive().unwrap();
+        assert!(matches!(response.response(), ResponseType::Success(_)));
+        assert!(!t.is_finished());
+    }
+
+    #[test]
+    fn test_stale_socket() {
+        let path = socket_path("unix_stale");
+        // Listener doesn't remove the file
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let server = UnixServer::new(&path).unwrap();
+        drop(server);
+        assert!(!path.exists());
+
+        // Regular file is not removed
+        fs::write(&path, "data").unwrap();
+        assert!(UnixServer::new(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+/// Time-series storage of device readings
+/// Points are kept in memory and appended to JSON lines file,
+/// old points are downsampled and expired by retention policy
+/// Time is local time of the Clock, same as for Scheduler
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::Result;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    Temperature, // Thermometer reading
+    Power,       // Socket power consumption
+}
+
+/// Single reading or aggregate of several readings, e.g. downsampled
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub at: NaiveDateTime, // Start of the interval for aggregate
+    pub min: f32,
+    pub max: f32,
+    pub avg: f32,
+    pub count: u32,
+}
+
+impl Point {
+    pub fn new(at: NaiveDateTime, value: f32) -> Self {
+        Self {
+            at,
+            min: value,
+            max: value,
+            avg: value,
+            count: 1,
+        }
+    }
+
+    /// Aggregate points at the time, average is weighted by count
+    pub fn merge<'a, I: IntoIterator<Item = &'a Point>>(
+        at: NaiveDateTime,
+        points: I,
+    ) -> Option<Self> {
+        let mut result: Option<Self> = None;
+        for p in points {
+            result = Some(match result {
+                None => Self { at, ..*p },
+                Some(r) => {
+                    let count = r.count + p.count;
+                    Self {
+                        at,
+                        min: r.min.min(p.min),
+                        max: r.max.max(p.max),
+                        avg: (r.avg * r.count as f32 + p.avg * p.count as f32) / count as f32,
+                        count,
+                    }
+                }
+            });
+        }
+        result
+    }
+}
+
+/// Raw points older than raw are merged into buckets,
+/// all points older than keep are removed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    pub raw: Duration,
+    pub bucket: Duration,
+    pub keep: Duration,
+}
+
+impl Default for Retention {
+    /// Day of raw readings, hourly points for a year
+    fn default() -> Self {
+        Self {
+            raw: Duration::from_secs(24 * 3600),
+            bucket: Duration::from_secs(3600),
+            keep: Duration::from_secs(365 * 24 * 3600),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    device: String,
+    metric: Metric,
+    #[serde(flatten)]
+    point: Point,
+}
+
+type Key = (String, Metric);
+
+/// Store of series by device and metric
+#[derive(Default)]
+pub struct TimeSeries {
+    series: BTreeMap<Key, Vec<Point>>, // Sorted by time
+    path: Option<PathBuf>,
+    retention: Retention,
+}
+
+impl TimeSeries {
+    /// Store without file
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load points from file, new points are appended to it
+    /// Incomplete last line, e.g. left by crash, is removed from the file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut store = Self::new();
+        if path.exists() {
+            let buf = fs::read(&path)?;
+            // Length of complete lines
+            let complete = buf.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+            for line in buf[..complete]
+                .split(|b| *b == b'\n')
+                .filter(|l| !l.is_empty())
+            {
+                let record: Record = serde_json::from_slice(line)?;
+                store.insert(record.device, record.metric, record.point);
+            }
+            if complete < buf.len() {
+                warn!(path = %path.display(), "incomplete time series record is removed");
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)?
+                    .set_len(complete as u64)?;
+            }
+        }
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    pub fn record(
+        &mut self,
+        device: &str,
+        metric: Metric,
+        at: NaiveDateTime,
+        value: f32,
+    ) -> Result<()> {
+        let point = Point::new(at, value);
+        if let Some(path) = &self.path {
+            let record = Record {
+                device: device.to_string(),
+                metric,
+                point,
+            };
+            let mut line = serde_json::to_vec(&record)?;
+            line.push(b'\n');
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            file.write_all(&line)?;
+            file.sync_data()?;
+        }
+        self.insert(device.to_string(), metric, point);
+        Ok(())
+    }
+
+    /// Points within [from, to)
+    pub fn range(
+        &self,
+        device: &str,
+        metric: Metric,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> &[Point] {
+        let Some(points) = self.series.get(&(device.to_string(), metric)) else {
+            return &[];
+        };
+        let start = points.partition_point(|p| p.at < from);
+        let end = points.partition_point(|p| p.at < to);
+        &points[start..end]
+    }
+
+    /// Aggregate of points within [from, to)
+    pub fn aggregate(
+        &self,
+        device: &str,
+        metric: Metric,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Option<Point> {
+        Point::merge(from, self.range(device, metric, from, to))
+    }
+
+    /// Points within [from, to) merged into buckets starting from `from`, empty buckets are skipped
+    pub fn downsample(
+        &self,
+        device: &str,
+        metric: Metric,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        bucket: Duration,
+    ) -> Vec<Point> {
+        let points = self.range(device, metric, from, to);
+        let Ok(bucket) = TimeDelta::from_std(bucket) else {
+            return vec![];
+        };
+        bucketed(points, from, bucket)
+    }
+
+    /// Devices and metrics with points
+    pub fn series(&self) -> Vec<(&str, Metric)> {
+        self.series.keys().map(|(d, m)| (d.as_str(), *m)).collect()
+    }
+
+    /// Apply retention policy, file is rewritten atomically
+    pub fn compact(&mut self, now: NaiveDateTime) -> Result<()> {
+        let delta = |d: Duration| TimeDelta::from_std(d).unwrap_or(TimeDelta::MAX);
+        let raw_start = now
+            .checked_sub_signed(delta(self.retention.raw))
+            .unwrap_or(NaiveDateTime::MIN);
+        let keep_start = now
+            .checked_sub_signed(delta(self.retention.keep))
+            .unwrap_or(NaiveDateTime::MIN);
+        let bucket = delta(self.retention.bucket);
+        for points in self.series.values_mut() {
+            let split = points.partition_point(|p| p.at < raw_start);
+            let old = points
+                .drain(..split)
+                .filter(|p| p.at >= keep_start)
+                .collect::<Vec<_>>();
+            // Buckets are aligned to the epoch, so merged points stay the same
+            let mut merged = bucketed(&old, NaiveDateTime::default(), bucket);
+            merged.append(points);
+            *points = merged;
+        }
+        self.series.retain(|_, p| !p.is_empty());
+        self.save()
+    }
+
+    fn insert(&mut self, device: String, metric: Metric, point: Point) {
+        let points = self.series.entry((device, metric)).or_default();
+        let i = points.partition_point(|p| p.at <= point.at);
+        points.insert(i, point);
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        for ((device, metric), points) in &self.series {
+            for point in points {
+                let record = Record {
+                    device: device.clone(),
+                    metric: *metric,
+                    point: *point,
+                };
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                file.write_all(&line)?;
+            }
+        }
+        file.sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Merge sorted points into buckets of the size starting from start
+fn bucketed(points: &[Point], start: NaiveDateTime, bucket: TimeDelta) -> Vec<Point> {
+    let bucket_ms = bucket.num_milliseconds().max(1);
+    let mut result = vec![];
+    for chunk in points.chunk_by(|a, b| {
+        (a.at - start).num_milliseconds() / bucket_ms
+            == (b.at - start).num_milliseconds() / bucket_ms
+    }) {
+        let index = (chunk[0].at - start).num_milliseconds() / bucket_ms;
+        let at = start + TimeDelta::milliseconds(index * bucket_ms);
+        result.extend(Point::merge(at, chunk));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(h: u32, m: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(h, m, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_queries() -> Result<()> {
+        let mut ts = TimeSeries::new();
+        for (m, value) in [(0, 20.), (10, 22.), (40, 24.), (70, 30.)] {
+            ts.record(
+                "t1",
+                Metric::Temperature,
+                at(0, 0) + TimeDelta::minutes(m),
+                value,
+            )?;
+        }
+        assert_eq!(
+            ts.range("t1", Metric::Temperature, at(0, 10), at(1, 0))
+                .len(),
+            2
+        );
+        assert!(ts.range("t1", Metric::Power, at(0, 0), at(2, 0)).is_empty());
+
+        let aggregate = ts
+            .aggregate("t1", Metric::Temperature, at(0, 0), at(1, 0))
+            .unwrap();
+        assert_eq!(
+            (aggregate.min, aggregate.max, aggregate.avg, aggregate.count),
+            (20., 24., 22., 3)
+        );
+
+        let hourly = ts.downsample(
+            "t1",
+            Metric::Temperature,
+            at(0, 0),
+            at(2, 0),
+            Duration::from_secs(3600),
+        );
+        assert_eq!(hourly.len(), 2);
+        assert_eq!((hourly[1].at, hourly[1].avg), (at(1, 0), 30.));
+        Ok(())
+    }
+
+    #[test]
+    fn test_retention() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("series_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let retention = Retention {
+            raw: Duration::from_secs(3600),
+            bucket: Duration::from_secs(3600),
+            keep: Duration::from_secs(5 * 3600),
+        };
+        let mut ts = TimeSeries::open(&path)?.with_retention(retention);
+        for h in 0..10 {
+            for m in [0, 30] {
+                ts.record("s1", Metric::Power, at(h, m), h as f32)?;
+            }
+        }
+        ts.compact(at(10, 0))?;
+        // Points before 5:00 are removed, 5:00-9:00 merged hourly, 9:00 and 9:30 are raw
+        let points = ts.range("s1", Metric::Power, at(0, 0), at(10, 0));
+        assert_eq!(points.len(), 6);
+        assert_eq!((points[0].at, points[0].count), (at(5, 0), 2));
+        assert_eq!(points[5].count, 1);
+
+        // Compacted file is loaded back
+        let ts = TimeSeries::open(&path)?;
+        assert_eq!(ts.range("s1", Metric::Power, at(0, 0), at(10, 0)), points);
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_record() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("series_tail_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut ts = TimeSeries::open(&path)?;
+        ts.record("t1", Metric::Temperature, at(0, 0), 20.)?;
+        // Crash in the middle of the record
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(br#"{"device":"t1","metric":"temp"#)?;
+
+        let mut ts = TimeSeries::open(&path)?;
+        assert_eq!(
+            ts.range("t1", Metric::Temperature, at(0, 0), at(1, 0))
+                .len(),
+            1
+        );
+        ts.record("t1", Metric::Temperature, at(0, 30), 21.)?;
+        let ts = TimeSeries::open(&path)?;
+        assert_eq!(
+            ts.range("t1", Metric::Temperature, at(0, 0), at(1, 0))
+                .len(),
+            2
+        );
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+/// TLS configuration for TLS transports
+/// Certificates and keys are loaded from PEM files,
+/// client certificates are verified if client CA is given (mutual TLS)
+use std::{path::Path, sync::Arc};
+
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    server::WebPkiClientVerifier,
+    ClientConfig, RootCertStore, ServerConfig,
+};
+
+use crate::{
+    error::{NetworkError, NetworkResult},
+    Result,
+};
+
+pub use rustls::{ClientConfig as TlsClientConfig, ServerConfig as TlsServerConfig};
+
+/// Server config with certificate chain and private key
+/// If client_ca is given, clients should present certificate signed by it
+pub fn server_config<P: AsRef<Path>>(
+    cert: P,
+    key: P,
+    client_ca: Option<P>,
+) -> Result<Arc<ServerConfig>> {
+    let builder =
+        ServerConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+    let builder = match client_ca {
+        Some(ca) => {
+            let roots = Arc::new(roots(ca)?);
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(roots, provider()).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_single_cert(certs(cert)?, PrivateKeyDer::from_pem_file(key)?)?;
+    Ok(Arc::new(config))
+}
+
+/// Client config which trusts server certificates signed by ca
+/// Identity (certificate and key) is needed for mutual TLS
+pub fn client_config<P: AsRef<Path>>(ca: P, identity: Option<(P, P)>) -> Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots(ca)?);
+    let config = match identity {
+        Some((cert, key)) => {
+            builder.with_client_auth_cert(certs(cert)?, PrivateKeyDer::from_pem_file(key)?)?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// Name which should match server certificate, e.g. "localhost"
+pub(crate) fn server_name(name: &str) -> NetworkResult<ServerName<'static>> {
+    ServerName::try_from(name.to_string()).map_err(|e| NetworkError::Address(e.to_string()))
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn certs<P: AsRef<Path>>(path: P) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)?.collect::<std::result::Result<_, _>>()?;
+    Ok(certs)
+}
+
+fn roots<P: AsRef<Path>>(path: P) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
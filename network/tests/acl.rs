@@ -0,0 +1,59 @@
+/// Access control by authenticated identity and source address
+use std::thread;
+
+use network::{
+    acl::{AccessList, Acl, AclRule, Action},
+    auth::{AuthClient, Authenticated, Key},
+    command::{CommandRequest, ResponseType},
+    gateway::Gateway,
+    sync::{Bind, Client, NetworkDevice, TCPClient, TCPServer},
+    Result,
+};
+use smart_home::devices::{Socket, Thermometer};
+
+#[test]
+fn test_acl_tcp() -> Result<()> {
+    let mut gateway = Gateway::new();
+    gateway.add_device("s1", Socket::new("s1"));
+    gateway.add_device("t1", Thermometer::new("t1"));
+    let access = AccessList::new(Action::Deny)
+        .rule(AclRule::allow().identity("admin"))
+        .rule(AclRule::allow().identity("guest").request("ThermGetTemp"));
+    let acl = Acl::new(gateway, access);
+    let audit = acl.audit();
+    let device = Authenticated::new(acl)
+        .with_key(Key::new("admin", b"admin secret"))
+        .with_key(Key::new("guest", b"guest secret"));
+    let server = TCPServer::new("127.0.0.1:0")?;
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(device, server);
+    thread::spawn(move || device.listen());
+
+    let guest = Key::new("guest", b"guest secret");
+    let mut client = AuthClient::new(TCPClient::new(addr)?, guest);
+    client.send(CommandRequest::builder().therm("t1").get_temp())?;
+    assert!(matches!(
+        client.receive()?.response(),
+        ResponseType::Success(_)
+    ));
+    client.send(CommandRequest::builder().socket("s1").turn_off())?;
+    assert!(matches!(
+        client.receive()?.response(),
+        ResponseType::Err(e) if e.starts_with("Forbidden")
+    ));
+    drop(client);
+
+    let admin = Key::new("admin", b"admin secret");
+    let mut client = AuthClient::new(TCPClient::new(addr)?, admin);
+    client.send(CommandRequest::builder().socket("s1").turn_off())?;
+    assert_eq!(
+        client.receive()?.response(),
+        &ResponseType::Success("".into())
+    );
+
+    let rejections = audit.entries();
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].peer.identity.as_deref(), Some("guest"));
+    assert_eq!(rejections[0].peer.addr, Some([127, 0, 0, 1].into()));
+    Ok(())
+}
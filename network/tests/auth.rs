@@ -0,0 +1,69 @@
+/// Signed requests over real transports
+use std::thread;
+
+use network::{
+    auth::{AuthClient, AuthError, Authenticated, Key},
+    command::{CommandRequest, ResponseType},
+    error::NetworkError,
+    r#async::{ClientAsync, HarnessAsync},
+    sync::{Bind, Client, NetworkDevice, UDPClient, UDPServer},
+    Result,
+};
+use smart_home::devices::{Socket, Thermometer};
+
+#[test]
+fn test_auth_udp() -> Result<()> {
+    let device = Authenticated::new(Socket::new("s1")).with_key(Key::new("admin", b"secret"));
+    let server = UDPServer::new("127.0.0.1:0")?;
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(device, server);
+    thread::spawn(move || device.listen());
+
+    let mut client = AuthClient::new(UDPClient::new(addr)?, Key::new("admin", b"secret"));
+    client.send(CommandRequest::builder().socket("s1").turn_on())?;
+    assert_eq!(
+        client.receive()?.response(),
+        &ResponseType::Success("".into())
+    );
+
+    // Client without key gets auth failure
+    let mut client = UDPClient::new(addr)?;
+    client.send(CommandRequest::builder().socket("s1").get_state())?;
+    let response = client.receive()?;
+    assert_eq!(
+        response.response(),
+        &ResponseType::Err(AuthError::Missing.to_string())
+    );
+    assert!(matches!(
+        response.into_result(),
+        Err(NetworkError::Auth(AuthError::Missing))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_auth_async() -> Result<()> {
+    let mut harness = HarnessAsync::new();
+    let device = Authenticated::new(Thermometer::new("t1")).with_key(Key::new("t", b"secret"));
+    harness.spawn("t1", device);
+
+    let mut client = AuthClient::new(harness.client("t1")?, Key::new("t", b"secret"));
+    client
+        .send(CommandRequest::builder().therm("t1").get_temp())
+        .await?;
+    assert!(matches!(
+        client.receive().await?.response(),
+        ResponseType::Success(_)
+    ));
+
+    // Failure is signed with the device key, so wrong secret doesn't verify it
+    let mut client = AuthClient::new(harness.client("t1")?, Key::new("t", b"guess"));
+    client
+        .send(CommandRequest::builder().therm("t1").get_temp())
+        .await?;
+    assert!(matches!(
+        client.receive().await,
+        Err(NetworkError::Auth(AuthError::BadSignature))
+    ));
+    Ok(())
+}
@@ -1,77 +1,52 @@
 use network::{
-    command::CommandRequest,
+    command::{CommandRequest, ResponseType},
     r#async::{
-        ClientAsync, NetworkDeviceAsync, TCPClientAsync, TCPServerAsync, UDPClientAsync,
+        BindAsync, ClientAsync, NetworkDeviceAsync, TCPClientAsync, TCPServerAsync, UDPClientAsync,
         UDPServerAsync,
     },
     Result,
 };
 use smart_home::devices::*;
 
-/// Send command with client, get and print response
-async fn send<C: ClientAsync>(client: &mut C, command: CommandRequest) -> Result<()> {
+/// Send command with client and get response
+async fn send<C: ClientAsync>(client: &mut C, command: CommandRequest) -> Result<ResponseType> {
     client.send(command).await?;
-    let response = client.receive().await?;
-    println!("Response {response:?}");
+    Ok(client.receive().await?.response().clone())
+}
+
+/// Switch the socket on and check its state before and after
+async fn switch<C: ClientAsync>(client: &mut C, id: &str) -> Result<()> {
+    let socket = || CommandRequest::builder().socket(id);
+    let state = send(client, socket().get_state()).await?;
+    assert!(matches!(state, ResponseType::Success(s) if s.starts_with("State: off")));
+    assert_eq!(
+        send(client, socket().turn_on()).await?,
+        ResponseType::Success("".into())
+    );
+    let state = send(client, socket().get_state()).await?;
+    assert!(matches!(state, ResponseType::Success(s) if s.starts_with("State: on")));
     Ok(())
 }
 
 /// Test two async sockets with different transports: TCP and UDP
-async fn sockets() -> Result<()> {
-    println!("start");
+#[tokio::test]
+async fn test_sockets() -> Result<()> {
     // Sockets devices
     let socket1 = Socket::new("s1000");
     let socket2 = Socket::new("s1001");
-    // Wrap with NetworkDevice with different transports
-    let socket1_tcp: NetworkDeviceAsync<TCPServerAsync> =
-        NetworkDeviceAsync::new(socket1, "127.0.0.1:8000").await?;
-    let socket2_udp: NetworkDeviceAsync<UDPServerAsync> =
-        NetworkDeviceAsync::new(socket2, "127.0.0.1:8001").await?;
+    // Wrap with NetworkDevice with different transports on free ports
+    let tcp_server = TCPServerAsync::new("127.0.0.1:0").await?;
+    let udp_server = UDPServerAsync::new("127.0.0.1:0").await?;
+    let (tcp_addr, udp_addr) = (tcp_server.local_addr()?, udp_server.local_addr()?);
+    let socket1_tcp = NetworkDeviceAsync::with_server(socket1, tcp_server);
+    let socket2_udp = NetworkDeviceAsync::with_server(socket2, udp_server);
     // Run listeners (servers)
     tokio::spawn(async move { socket1_tcp.listen().await });
     tokio::spawn(async move { socket2_udp.listen().await });
     // Clients for network devices
-    let mut tcp_client = TCPClientAsync::new("127.0.0.1:8000").await?;
-    let mut udp_client = UDPClientAsync::new("127.0.0.1:8001").await?;
-    // Send different requests
-    send(
-        &mut tcp_client,
-        CommandRequest::builder().socket("s1000").get_state(),
-    )
-    .await?;
-    send(
-        &mut tcp_client,
-        CommandRequest::builder().socket("s1000").turn_on(),
-    )
-    .await?;
-    send(
-        &mut tcp_client,
-        CommandRequest::builder().socket("s1000").get_state(),
-    )
-    .await?;
-
-    send(
-        &mut udp_client,
-        CommandRequest::builder().socket("s1001").get_state(),
-    )
-    .await?;
-    send(
-        &mut udp_client,
-        CommandRequest::builder().socket("s1001").turn_on(),
-    )
-    .await?;
-    send(
-        &mut udp_client,
-        CommandRequest::builder().socket("s1001").get_state(),
-    )
-    .await?;
-
+    let mut tcp_client = TCPClientAsync::new(tcp_addr).await?;
+    let mut udp_client = UDPClientAsync::new(udp_addr).await?;
+    switch(&mut tcp_client, "s1000").await?;
+    switch(&mut udp_client, "s1001").await?;
     Ok(())
 }
-
-#[tokio::test]
-async fn main() {
-    if let Err(e) = sockets().await {
-        println!("Error {e}");
-    }
-}
@@ -0,0 +1,67 @@
+/// Devices behind in-memory transport: no ports, tests could run in parallel
+use network::{
+    command::{CommandRequest, ResponseType},
+    device::{socket_state, Device},
+    gateway::Gateway,
+    r#async::HarnessAsync,
+    rules::{Condition, Rule, RulesEngine},
+    sync::Harness,
+    Result,
+};
+use smart_home::devices::{Socket, SocketState, Thermometer};
+
+#[test]
+fn test_gateway_over_memory() -> Result<()> {
+    let mut harness = Harness::new();
+    harness.spawn("s1", Socket::new("s1"));
+    harness.spawn("s2", Socket::new("s2"));
+    let mut gateway = Gateway::new();
+    gateway.add_device("s1", harness.remote("s1")?);
+    gateway.add_device("s2", harness.remote("s2")?);
+
+    // Batch is expanded by the gateway and sent to remote devices
+    let batch = CommandRequest::builder()
+        .batch("all")
+        .command(CommandRequest::builder().socket("s1").turn_on())
+        .command(CommandRequest::builder().socket("s2").turn_on())
+        .build();
+    let response = gateway.handle(batch);
+    let ResponseType::Batch(responses) = response.response() else {
+        panic!("Batch response expected");
+    };
+    assert!(responses
+        .iter()
+        .all(|r| !matches!(r.response(), ResponseType::Err(_))));
+
+    let state = harness.request(CommandRequest::builder().socket("s2").get_state())?;
+    assert_eq!(socket_state(&state), Ok(SocketState::On));
+    Ok(())
+}
+
+#[test]
+fn test_rules_over_memory() -> Result<()> {
+    let mut harness = Harness::new();
+    harness.spawn("s1", Socket::new("s1"));
+    let mut engine = RulesEngine::new();
+    let rule = Rule::new("off", Condition::socket_off("s1"))
+        .then(CommandRequest::builder().socket("s1").turn_on());
+    engine.add_rule(rule)?;
+
+    let firings = engine.evaluate(&mut harness.remote("s1")?);
+    assert_eq!(firings.len(), 1);
+    let state = harness.request(CommandRequest::builder().socket("s1").get_state())?;
+    assert_eq!(socket_state(&state), Ok(SocketState::On));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_harness_async() -> Result<()> {
+    let mut harness = HarnessAsync::new();
+    harness.spawn("t1", Thermometer::new("t1"));
+    let response = harness
+        .request(CommandRequest::builder().therm("t1").get_temp())
+        .await?;
+    assert!(matches!(response.response(), ResponseType::Success(_)));
+    assert!(harness.client("t2").is_err());
+    Ok(())
+}
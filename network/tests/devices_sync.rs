@@ -1,67 +1,49 @@
 use network::{
-    command::CommandRequest,
-    sync::{Client, NetworkDevice, TCPClient, TCPServer, UDPClient, UDPServer},
+    command::{CommandRequest, ResponseType},
+    sync::{Bind, Client, NetworkDevice, TCPClient, TCPServer, UDPClient, UDPServer},
     Result,
 };
 use smart_home::devices::*;
 use std::thread;
 
-/// Send command with client, get and print response
-fn send<C: Client>(client: &mut C, command: CommandRequest) -> Result<()> {
+/// Send command with client and get response
+fn send<C: Client + ?Sized>(client: &mut C, command: CommandRequest) -> Result<ResponseType> {
     client.send(command)?;
-    let response = client.receive();
-    println!("Response {response:?}");
-    Ok(())
+    Ok(client.receive()?.response().clone())
 }
 
 /// Test two sockets with different transports: TCP and UDP
-fn sockets() -> Result<()> {
-    println!("start");
+#[test]
+fn test_sockets() -> Result<()> {
     // Sockets devices
     let socket1 = Socket::new("s1000");
     let socket2 = Socket::new("s1001");
-    // Wrap with NetworkDevice with different transports
-    let socket1_tcp: NetworkDevice<TCPServer> = NetworkDevice::new(socket1, "127.0.0.1:8000")?;
-    let socket2_udp: NetworkDevice<UDPServer> = NetworkDevice::new(socket2, "127.0.0.1:8001")?;
+    // Wrap with NetworkDevice with different transports on free ports
+    let tcp_server = TCPServer::new("127.0.0.1:0")?;
+    let udp_server = UDPServer::new("127.0.0.1:0")?;
+    let (tcp_addr, udp_addr) = (tcp_server.local_addr()?, udp_server.local_addr()?);
+    let socket1_tcp = NetworkDevice::with_server(socket1, tcp_server);
+    let socket2_udp = NetworkDevice::with_server(socket2, udp_server);
     // Run listeners (servers)
     thread::spawn(move || socket1_tcp.listen());
     thread::spawn(move || socket2_udp.listen());
     // Clients for network devices
-    let mut tcp_client = TCPClient::new("127.0.0.1:8000")?;
-    let mut udp_client = UDPClient::new("127.0.0.1:8001")?;
+    let mut tcp_client = TCPClient::new(tcp_addr)?;
+    let mut udp_client = UDPClient::new(udp_addr)?;
     // Send different requests
-    send(
-        &mut tcp_client,
-        CommandRequest::builder().socket("s1000").get_state(),
-    )?;
-    send(
-        &mut tcp_client,
-        CommandRequest::builder().socket("s1000").turn_on(),
-    )?;
-    send(
-        &mut tcp_client,
-        CommandRequest::builder().socket("s1000").get_state(),
-    )?;
-
-    send(
-        &mut udp_client,
-        CommandRequest::builder().socket("s1001").get_state(),
-    )?;
-    send(
-        &mut udp_client,
-        CommandRequest::builder().socket("s1001").turn_on(),
-    )?;
-    send(
-        &mut udp_client,
-        CommandRequest::builder().socket("s1001").get_state(),
-    )?;
-
-    Ok(())
-}
-
-#[test]
-fn main() {
-    if let Err(e) = sockets() {
-        println!("Error {e}");
+    for (client, id) in [
+        (&mut tcp_client as &mut dyn Client, "s1000"),
+        (&mut udp_client, "s1001"),
+    ] {
+        let socket = || CommandRequest::builder().socket(id);
+        let state = send(client, socket().get_state())?;
+        assert!(matches!(state, ResponseType::Success(s) if s.starts_with("State: off")));
+        assert_eq!(
+            send(client, socket().turn_on())?,
+            ResponseType::Success("".into())
+        );
+        let state = send(client, socket().get_state())?;
+        assert!(matches!(state, ResponseType::Success(s) if s.starts_with("State: on")));
     }
+    Ok(())
 }
@@ -0,0 +1,73 @@
+/// Typed errors of clients
+use std::{
+    net::{TcpListener, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+use network::{
+    command::CommandRequest,
+    error::NetworkError,
+    sync::{Bind, Client, NetworkDevice, TCPClient, UDPClient, UDPServer},
+    Result,
+};
+use smart_home::devices::Socket;
+
+#[test]
+fn test_connection_refused() {
+    // Nobody listens on the port which was free
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let error = TCPClient::new(addr).err().unwrap();
+    assert!(matches!(error, NetworkError::ConnectionRefused));
+}
+
+#[test]
+fn test_timeout() -> Result<()> {
+    // Socket is bound, but never answers
+    let silent = UdpSocket::bind("127.0.0.1:0")?;
+    let mut client =
+        UDPClient::new(silent.local_addr()?)?.with_timeout(Duration::from_millis(100))?;
+    client.send(CommandRequest::builder().socket("s1").get_state())?;
+    assert!(matches!(client.receive(), Err(NetworkError::Timeout)));
+    Ok(())
+}
+
+#[test]
+fn test_malformed_response() -> Result<()> {
+    let server = UdpSocket::bind("127.0.0.1:0")?;
+    let addr = server.local_addr()?;
+    thread::spawn(move || {
+        let mut buf = [0; 64];
+        if let Ok((_, addr)) = server.recv_from(&mut buf) {
+            let _ = server.send_to(b"not a response", addr);
+        }
+    });
+    let mut client = UDPClient::new(addr)?;
+    client.send(CommandRequest::builder().socket("s1").get_state())?;
+    assert!(matches!(
+        client.receive(),
+        Err(NetworkError::Serialization(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_device_error() -> Result<()> {
+    let server = UDPServer::new("127.0.0.1:0")?;
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(Socket::new("s1"), server);
+    thread::spawn(move || device.listen());
+
+    let mut client = UDPClient::new(addr)?;
+    client.send(CommandRequest::builder().therm("s1").get_temp())?;
+    match client.receive()?.into_result() {
+        Err(NetworkError::Device { device, message }) => {
+            assert_eq!((device.as_str(), message.as_str()), ("s1", "Wrong request"))
+        }
+        r => panic!("Unexpected result {r:?}"),
+    }
+    Ok(())
+}
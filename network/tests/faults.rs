@@ -0,0 +1,68 @@
+/// Device errors with injected faults
+use std::{thread, time::Duration};
+
+use network::{
+    command::{CommandRequest, ResponseType},
+    error::NetworkError,
+    sync::{Bind, Client, NetworkDevice, UDPClient, UDPServer},
+    Result,
+};
+use smart_home::devices::{FaultInjector, Socket, SocketError, Thermometer, ThermometerError};
+
+#[test]
+fn test_socket_faults() -> Result<()> {
+    let faults = FaultInjector::new();
+    let mut socket = Socket::new("s1");
+    socket.set_faults(faults.clone());
+    let server = UDPServer::new("127.0.0.1:0")?;
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(socket, server);
+    thread::spawn(move || device.listen());
+
+    let mut client = UDPClient::new(addr)?;
+    let mut turn_on = || -> Result<ResponseType> {
+        client.send(CommandRequest::builder().socket("s1").turn_on())?;
+        Ok(client.receive()?.response().clone())
+    };
+    faults.fail_next(1, SocketError::HardwareFault);
+    assert_eq!(
+        turn_on()?,
+        ResponseType::Err(SocketError::HardwareFault.to_string())
+    );
+    assert!(matches!(turn_on()?, ResponseType::Success(_)));
+    faults.fail_randomly(1., SocketError::Overload(1500.));
+    assert_eq!(
+        turn_on()?,
+        ResponseType::Err("Socket is overloaded: 1500.0W".into())
+    );
+    Ok(())
+}
+
+#[test]
+fn test_thermometer_timeout() -> Result<()> {
+    let faults = FaultInjector::new();
+    let mut therm = Thermometer::new("t1");
+    therm.set_faults(faults.clone());
+    let server = UDPServer::new("127.0.0.1:0")?;
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(therm, server);
+    thread::spawn(move || device.listen());
+
+    let mut client = UDPClient::new(addr)?.with_timeout(Duration::from_millis(100))?;
+    faults.time_out_next(1, Duration::from_millis(300));
+    client.send(CommandRequest::builder().therm("t1").get_temp())?;
+    assert!(matches!(client.receive(), Err(NetworkError::Timeout)));
+
+    // Late response is received after the device recovers
+    thread::sleep(Duration::from_millis(300));
+    let late = client.receive()?.into_result();
+    assert!(matches!(late, Err(NetworkError::Device { message, .. })
+        if message == ThermometerError::Timeout.to_string()));
+    faults.fail_next(1, ThermometerError::Disconnected);
+    client.send(CommandRequest::builder().therm("t1").get_temp())?;
+    assert_eq!(
+        client.receive()?.response(),
+        &ResponseType::Err(ThermometerError::Disconnected.to_string())
+    );
+    Ok(())
+}
@@ -0,0 +1,75 @@
+/// Servers with rate limits
+use std::{thread, time::Duration};
+
+use network::{
+    command::{CommandRequest, CommandResponse, ResponseType},
+    error::NetworkError,
+    limit::{Excess, LimitError, Rate, RateLimiter},
+    r#async::{BindAsync, ClientAsync, NetworkDeviceAsync, TCPClientAsync, TCPServerAsync},
+    sync::{Bind, Client, NetworkDevice, UDPClient, UDPServer},
+    Result,
+};
+use smart_home::devices::{Socket, Thermometer};
+
+#[test]
+fn test_udp_limit() -> Result<()> {
+    let limiter = RateLimiter::new(Excess::Error).per_source(Rate::new(0.1, 2));
+    let server = UDPServer::new("127.0.0.1:0")?.with_limiter(limiter);
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(Socket::new("s1"), server);
+    thread::spawn(move || device.listen());
+
+    let mut client = UDPClient::new(addr)?;
+    let mut request = || -> Result<CommandResponse> {
+        client.send(CommandRequest::builder().socket("s1").get_state())?;
+        Ok(client.receive()?)
+    };
+    assert!(matches!(request()?.response(), ResponseType::Success(_)));
+    assert!(matches!(request()?.response(), ResponseType::Success(_)));
+    let response = request()?;
+    assert_eq!(
+        response.response(),
+        &ResponseType::Err(LimitError::TooManyRequests.to_string())
+    );
+    // Rejection is told apart from device error
+    assert!(matches!(
+        response.into_result(),
+        Err(NetworkError::Limit(LimitError::TooManyRequests))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_ban_async() -> Result<()> {
+    let limiter = RateLimiter::new(Excess::Ban(Duration::from_secs(60)))
+        .per_source(Rate::new(0.1, 1))
+        .global(Rate::new(100., 100));
+    let server = TCPServerAsync::new("127.0.0.1:0")
+        .await?
+        .with_limiter(limiter);
+    let addr = server.local_addr()?;
+    let device = NetworkDeviceAsync::with_server(Thermometer::new("t1"), server);
+    tokio::spawn(async move { device.listen().await });
+
+    let mut client = TCPClientAsync::new(addr).await?;
+    client
+        .send(CommandRequest::builder().therm("t1").get_temp())
+        .await?;
+    assert!(matches!(
+        client.receive().await?.response(),
+        ResponseType::Success(_)
+    ));
+    // Connection is closed, source is banned
+    client
+        .send(CommandRequest::builder().therm("t1").get_temp())
+        .await?;
+    assert!(client.receive().await.is_err());
+    drop(client);
+
+    let mut client = TCPClientAsync::new(addr).await?;
+    client
+        .send(CommandRequest::builder().therm("t1").get_temp())
+        .await?;
+    assert!(client.receive().await.is_err());
+    Ok(())
+}
@@ -0,0 +1,64 @@
+/// Servers with metrics
+use std::{thread, time::Duration};
+
+use network::{
+    command::{CommandRequest, ResponseType},
+    metrics::ServerMetrics,
+    r#async::{BindAsync, ClientAsync, NetworkDeviceAsync, UDPClientAsync, UDPServerAsync},
+    sync::{Bind, Client, NetworkDevice, TCPClient, TCPServer},
+    Result,
+};
+use smart_home::devices::{Socket, Thermometer};
+
+#[test]
+fn test_tcp_metrics() -> Result<()> {
+    let metrics = ServerMetrics::new("tcp");
+    let server = TCPServer::new("127.0.0.1:0")?.with_metrics(metrics.clone());
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(Socket::new("s1"), server);
+    thread::spawn(move || device.listen());
+
+    let mut client = TCPClient::new(addr)?;
+    client.send(CommandRequest::builder().socket("s1").turn_on())?;
+    assert!(matches!(
+        client.receive()?.response(),
+        ResponseType::Success(_)
+    ));
+    client.send(CommandRequest::builder().therm("s1").get_temp())?;
+    assert!(matches!(client.receive()?.response(), ResponseType::Err(_)));
+    assert_eq!(metrics.connections(), 1);
+    assert_eq!(metrics.requests("SocketTurnOn"), 1);
+    assert_eq!(metrics.errors("SocketTurnOn"), 0);
+    assert_eq!(metrics.errors("ThermGetTemp"), 1);
+
+    drop(client);
+    // Connection is closed by the server after client is dropped
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(metrics.connections(), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_metrics_async() -> Result<()> {
+    let metrics = ServerMetrics::new("udp");
+    let server = UDPServerAsync::new("127.0.0.1:0")
+        .await?
+        .with_metrics(metrics.clone());
+    let addr = server.local_addr()?;
+    let device = NetworkDeviceAsync::with_server(Thermometer::new("t1"), server);
+    tokio::spawn(async move { device.listen().await });
+
+    let mut client = UDPClientAsync::new(addr).await?;
+    for _ in 0..2 {
+        client
+            .send(CommandRequest::builder().therm("t1").get_temp())
+            .await?;
+        client.receive().await?;
+    }
+    assert_eq!(metrics.requests("ThermGetTemp"), 2);
+    let text = metrics.render();
+    assert!(text.contains(
+        r#"smart_home_server_request_duration_seconds_count{server="udp",type="ThermGetTemp"} 2"#
+    ));
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::{thread, time::Duration};
+
+use network::{
+    mqtt::{LocalBroker, LocalClient, Message, MqttBridge, MqttClient},
+    sync::{Bind, NetworkDevice, RemoteDevice, TCPClient, TCPServer},
+    Result,
+};
+use smart_home::{
+    devices::{Socket, Thermometer},
+    query::DeviceKind,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Bridge local socket and thermometer, network socket and device which doesn't respond
+#[test]
+fn test_mqtt_bridge() -> Result<()> {
+    let server = TCPServer::new("127.0.0.1:0")?;
+    let addr = server.local_addr()?;
+    let remote = NetworkDevice::with_server(Socket::new("s2"), server);
+    thread::spawn(move || remote.listen());
+
+    let broker = LocalBroker::new();
+    let client = broker.connect(Some(MqttBridge::<LocalClient>::will("home")));
+    let mut bridge = MqttBridge::new(client, "home")?;
+    bridge.add_device(
+        "Kitchen",
+        "Socket",
+        "s1",
+        DeviceKind::Socket,
+        Socket::new("s1"),
+    );
+    let therm = Thermometer::new("t1");
+    bridge.add_device("Kitchen", "Therm", "t1", DeviceKind::Thermometer, therm);
+    let remote = RemoteDevice::new(TCPClient::new(addr)?);
+    bridge.add_device("Hall", "Socket", "s2", DeviceKind::Socket, remote);
+    // Device id is not matched, so it is always unavailable
+    bridge.add_device("Hall", "Lamp", "s3", DeviceKind::Socket, Socket::new("s4"));
+    bridge.publish_states()?;
+
+    // Retained state is available for late subscribers
+    let retained = |topic: &str| broker.retained(&format!("home/{topic}"));
+    assert_eq!(retained("bridge/status").as_deref(), Some("online"));
+    assert_eq!(retained("Kitchen/Socket/state").as_deref(), Some("off"));
+    assert_eq!(retained("Hall/Socket/state").as_deref(), Some("off"));
+    assert!(retained("Kitchen/Therm/reading").is_some());
+    assert_eq!(retained("Kitchen/Therm/state"), None);
+    assert_eq!(
+        retained("Kitchen/Therm/availability").as_deref(),
+        Some("online")
+    );
+    assert_eq!(
+        retained("Hall/Lamp/availability").as_deref(),
+        Some("offline")
+    );
+
+    // Commands from other system
+    let mut dashboard = broker.connect(None);
+    dashboard.subscribe("home/+/+/error")?;
+    dashboard.publish("home/Kitchen/Socket/set", "on", false)?;
+    dashboard.publish("home/Hall/Socket/set", "ON", false)?;
+    dashboard.publish("home/Kitchen/Therm/set", "on", false)?;
+    bridge.run_once(TIMEOUT)?;
+    assert_eq!(retained("Kitchen/Socket/state").as_deref(), Some("on"));
+    assert_eq!(retained("Hall/Socket/state").as_deref(), Some("on"));
+    assert_eq!(
+        dashboard.poll(TIMEOUT)?,
+        Some(Message {
+            topic: "home/Kitchen/Therm/error".into(),
+            payload: "Wrong command on".into(),
+            retain: false
+        })
+    );
+
+    // Connection of the bridge is lost
+    drop(bridge);
+    assert_eq!(retained("bridge/status").as_deref(), Some("offline"));
+    Ok(())
+}
+
+/// Names with level separator and wildcards don't break topics
+#[test]
+fn test_escaped_names() -> Result<()> {
+    let broker = LocalBroker::new();
+    let mut bridge = MqttBridge::new(broker.connect(None), "home")?;
+    bridge.add_device(
+        "Room #1",
+        "A/C",
+        "s1",
+        DeviceKind::Socket,
+        Socket::new("s1"),
+    );
+    bridge.add_device("Room +", "Fan", "s2", DeviceKind::Socket, Socket::new("s2"));
+    bridge.publish_states()?;
+    let retained = |topic: &str| broker.retained(&format!("home/{topic}"));
+    assert_eq!(retained("Room %231/A%2FC/state").as_deref(), Some("off"));
+    assert_eq!(retained("Room #1/A/C/state"), None);
+
+    let mut dashboard = broker.connect(None);
+    dashboard.publish("home/Room %231/A%2FC/set", "on", false)?;
+    bridge.run_once(TIMEOUT)?;
+    assert_eq!(retained("Room %231/A%2FC/state").as_deref(), Some("on"));
+    assert_eq!(retained("Room %2B/Fan/state").as_deref(), Some("off"));
+    Ok(())
+}
@@ -0,0 +1,97 @@
+/// Socket overload protection and power budgets of the home
+use std::thread;
+
+use network::{
+    command::{CommandRequest, ResponseType},
+    device::{socket_state, CommandSwitch, Device},
+    gateway::Gateway,
+    sync::{Bind, Client, NetworkDevice, TCPClient, TCPServer},
+    Result,
+};
+use smart_home::{
+    devices::{Socket, SocketError, SocketState},
+    events::{Event, EventBus},
+    power::Budget,
+    SmartHome,
+};
+
+#[test]
+fn test_socket_trip_reset() -> Result<()> {
+    let bus = EventBus::new();
+    let (_, rx) = bus.subscribe_channel();
+    let mut socket = Socket::new("s1");
+    socket.set_event_bus(bus);
+    // Load is never below 20W
+    socket.set_max_load(10.);
+    let server = TCPServer::new("127.0.0.1:0")?;
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(socket, server);
+    thread::spawn(move || device.listen());
+
+    let mut client = TCPClient::new(addr)?;
+    let mut request = |request: CommandRequest| -> Result<ResponseType> {
+        client.send(request)?;
+        Ok(client.receive()?.response().clone())
+    };
+    let socket = || CommandRequest::builder().socket("s1");
+    assert!(matches!(request(socket().turn_on())?, ResponseType::Err(e)
+        if e.starts_with("Socket is overloaded")));
+    assert_eq!(
+        request(socket().get_state())?,
+        ResponseType::Success("State: off, tripped, power consumption 0.0W".into())
+    );
+    assert_eq!(
+        request(socket().turn_on())?,
+        ResponseType::Err(SocketError::Tripped.to_string())
+    );
+    assert_eq!(request(socket().reset())?, ResponseType::Success("".into()));
+    assert_eq!(
+        request(socket().get_state())?,
+        ResponseType::Success("State: off, power consumption 0.0W".into())
+    );
+    let events = rx.try_iter().collect::<Vec<_>>();
+    assert!(events.contains(&Event::SocketReset {
+        device: "s1".into()
+    }));
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, Event::SocketTripped { limit: 10., .. })));
+    Ok(())
+}
+
+#[test]
+fn test_home_budget() {
+    let mut gateway = Gateway::new();
+    gateway.add_device("s1", Socket::new("s1"));
+    gateway.add_device("s2", Socket::new("s2"));
+    gateway.add_device("s3", Socket::new("s3"));
+    for id in ["s1", "s2"] {
+        gateway.process(CommandRequest::builder().socket(id).turn_on());
+    }
+    let mut home = SmartHome::new("My home");
+    home.add_device("kitchen", "s1").unwrap();
+    home.add_device("kitchen", "s2").unwrap();
+    home.add_device("bedroom", "s3").unwrap();
+    // Two sockets never consume more than 2000W
+    home.set_room_budget("kitchen", 2000.).unwrap();
+    assert!(home
+        .enforce_budgets(&mut CommandSwitch(&mut gateway))
+        .is_empty());
+
+    home.set_home_budget(0.);
+    let trips = home.enforce_budgets(&mut CommandSwitch(&mut gateway));
+    assert_eq!(trips.len(), 2);
+    assert!(trips.iter().all(|t| t.budget == Budget::Home));
+    // Sockets are tripped, not just switched off
+    for id in ["s1", "s2"] {
+        let response = gateway.process(CommandRequest::builder().socket(id).get_state());
+        assert_eq!(socket_state(&response), Ok(SocketState::Off));
+        assert_eq!(
+            gateway
+                .process(CommandRequest::builder().socket(id).turn_on())
+                .response(),
+            &ResponseType::Err(SocketError::Tripped.to_string())
+        );
+    }
+    assert_eq!(home.trips(), trips.as_slice());
+}
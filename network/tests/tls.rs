@@ -0,0 +1,151 @@
+/// TLS transports with self-signed CA generated for the test
+use std::{fs, path::PathBuf, thread};
+
+use network::{
+    command::{CommandRequest, ResponseType},
+    r#async::{ClientAsync, NetworkDeviceAsync, TLSClientAsync, TLSServerAsync},
+    sync::{Client, NetworkDevice, TLSClient, TLSServer},
+    tls::{client_config, server_config},
+    Result,
+};
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use smart_home::devices::{Socket, Thermometer};
+
+/// PEM files of CA, server and client certificates
+/// Directory with the files is removed when dropped
+struct Certs {
+    dir: PathBuf,
+    ca: PathBuf,
+    server: (PathBuf, PathBuf),
+    client: (PathBuf, PathBuf),
+}
+
+impl Drop for Certs {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn generate(name: &str) -> Result<Certs> {
+    let dir = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let paths = |name: &str| {
+        (
+            dir.join(format!("{name}.pem")),
+            dir.join(format!("{name}.key")),
+        )
+    };
+    // Files are removed on failure as well
+    let certs = Certs {
+        ca: dir.join("ca.pem"),
+        server: paths("server"),
+        client: paths("client"),
+        dir: dir.clone(),
+    };
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca_key = KeyPair::generate()?;
+    let ca = ca_params.self_signed(&ca_key)?;
+    fs::write(&certs.ca, ca.pem())?;
+
+    for (cert_path, key_path) in [&certs.server, &certs.client] {
+        let key = KeyPair::generate()?;
+        let cert =
+            CertificateParams::new(vec!["localhost".to_string()])?.signed_by(&key, &ca, &ca_key)?;
+        fs::write(cert_path, cert.pem())?;
+        fs::write(key_path, key.serialize_pem())?;
+    }
+    Ok(certs)
+}
+
+#[test]
+fn test_tls() -> Result<()> {
+    let certs = generate("tls")?;
+    let config = server_config(&certs.server.0, &certs.server.1, None)?;
+    let server = TLSServer::new("127.0.0.1:0", config)?;
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(Socket::new("s1"), server);
+    thread::spawn(move || device.listen());
+
+    let config = client_config(&certs.ca, None)?;
+    let mut client = TLSClient::new(addr, "localhost", config.clone())?;
+    client.send(CommandRequest::builder().socket("s1").turn_on())?;
+    assert_eq!(
+        client.receive()?.response(),
+        &ResponseType::Success("".into())
+    );
+    client.send(CommandRequest::builder().socket("s1").get_state())?;
+    assert!(matches!(client.receive()?.response(), ResponseType::Success(s) if s.contains("on")));
+    drop(client);
+
+    // Certificate is not valid for the name
+    let mut client = TLSClient::new(addr, "example.com", config)?;
+    let request = CommandRequest::builder().socket("s1").get_state();
+    assert!(client.send(request).and_then(|_| client.receive()).is_err());
+
+    // Generated files are removed with the certificates
+    let dir = certs.dir.clone();
+    drop(certs);
+    assert!(!dir.exists());
+    Ok(())
+}
+
+#[test]
+fn test_mutual_tls() -> Result<()> {
+    let certs = generate("mtls")?;
+    let config = server_config(&certs.server.0, &certs.server.1, Some(&certs.ca))?;
+    let server = TLSServer::new("127.0.0.1:0", config)?;
+    let addr = server.local_addr()?;
+    let device = NetworkDevice::with_server(Socket::new("s1"), server);
+    thread::spawn(move || device.listen());
+
+    // Client without certificate is rejected
+    let config = client_config(&certs.ca, None)?;
+    let mut client = TLSClient::new(addr, "localhost", config)?;
+    let _ = client.send(CommandRequest::builder().socket("s1").get_state());
+    assert!(client.receive().is_err());
+    drop(client);
+
+    let identity = (&certs.client.0, &certs.client.1);
+    let config = client_config(&certs.ca, Some(identity))?;
+    let mut client = TLSClient::new(addr, "localhost", config)?;
+    client.send(CommandRequest::builder().socket("s1").get_state())?;
+    assert!(matches!(
+        client.receive()?.response(),
+        ResponseType::Success(_)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tls_async() -> Result<()> {
+    let certs = generate("tls_async")?;
+    let config = server_config(&certs.server.0, &certs.server.1, Some(&certs.ca))?;
+    let server = TLSServerAsync::new("127.0.0.1:0", config).await?;
+    let addr = server.local_addr()?;
+    let device = NetworkDeviceAsync::with_server(Thermometer::new("t1"), server);
+    tokio::spawn(async move { device.listen().await });
+
+    // Handshake fails without client certificate
+    let config = client_config(&certs.ca, None)?;
+    let rejected = match TLSClientAsync::new(addr, "localhost", config).await {
+        Ok(mut client) => {
+            let _ = client
+                .send(CommandRequest::builder().therm("t1").get_temp())
+                .await;
+            client.receive().await.is_err()
+        }
+        Err(_) => true,
+    };
+    assert!(rejected);
+
+    let identity = (&certs.client.0, &certs.client.1);
+    let config = client_config(&certs.ca, Some(identity))?;
+    let mut client = TLSClientAsync::new(addr, "localhost", config).await?;
+    client
+        .send(CommandRequest::builder().therm("t1").get_temp())
+        .await?;
+    let response = client.receive().await?;
+    assert!(matches!(response.response(), ResponseType::Success(_)));
+    Ok(())
+}
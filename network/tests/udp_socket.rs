@@ -1,6 +1,6 @@
 /// Test udp socket work
 /// Not otus task
-use std::net::{ToSocketAddrs, UdpSocket};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
 use std::sync::Arc;
 use std::thread::{self, sleep, spawn};
 use std::time::Duration;
@@ -9,18 +9,14 @@ fn handle<A: ToSocketAddrs>(socket: Arc<UdpSocket>, addr: A, val: usize) -> std:
     sleep(Duration::from_secs(1));
     let val = val + 1;
     socket.send_to(&val.to_be_bytes(), addr)?;
-    println!("Handler: sent {val}");
     Ok(())
 }
 
-fn server() -> std::io::Result<()> {
-    let s = UdpSocket::bind("127.0.0.1:20000").unwrap();
+fn server(s: UdpSocket) -> std::io::Result<()> {
     let s = Arc::new(s);
     loop {
         let mut buf = [0; 8];
-        println!("Server: wating for datagramms");
         let (_, addr) = s.recv_from(&mut buf)?;
-        println!("Server: got val from {addr}");
         let socket = s.clone();
         let val = usize::from_be_bytes(buf);
         spawn(move || handle(socket, addr, val));
@@ -29,16 +25,15 @@ fn server() -> std::io::Result<()> {
 
 #[test]
 fn main() {
-    println!("main");
-    let _t = thread::spawn(server);
-    sleep(Duration::from_secs(1));
-    let s = UdpSocket::bind("127.0.0.1:30000").unwrap();
+    let server_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr: SocketAddr = server_socket.local_addr().unwrap();
+    let _t = thread::spawn(move || server(server_socket));
+    let s = UdpSocket::bind("127.0.0.1:0").unwrap();
     let value: usize = 100;
     for _ in 0..2 {
         let mut buf = value.to_be_bytes();
-        println!("Client: sending value {value}");
-        s.send_to(&buf, "127.0.0.1:20000").unwrap();
+        s.send_to(&buf, addr).unwrap();
         s.recv(&mut buf).unwrap();
-        println!("Client: got buffer {:?}", buf);
+        assert_eq!(usize::from_be_bytes(buf), value + 1);
     }
 }
@@ -0,0 +1,178 @@
+/// Fault injection for simulated devices
+/// Injector is shared: cloned handle could program faults of the device
+/// while it is served, e.g. behind NetworkDevice
+/// Injected delays block the calling thread, so timeouts are meant for sync servers:
+/// under NetworkDeviceAsync the delay stalls the runtime worker
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use rand::{thread_rng, Rng};
+
+/// Device error which is reported after injected timeout
+pub trait TimeoutError {
+    fn timeout() -> Self;
+}
+
+struct Plan<E> {
+    next: VecDeque<(E, Duration)>, // Failures of the next calls with delay before failure
+    random: Option<(f64, E)>,      // Probability of failure
+    readings: VecDeque<f32>,       // Values of the next readings
+}
+
+/// Faults of device calls, e.g. turn_on or get_temperature
+/// Planned failures of the next calls are applied before random ones
+#[derive(Clone)]
+pub struct FaultInjector<E> {
+    plan: Arc<Mutex<Plan<E>>>,
+}
+
+impl<E: Clone + TimeoutError> FaultInjector<E> {
+    pub fn new() -> Self {
+        Self {
+            plan: Arc::new(Mutex::new(Plan {
+                next: VecDeque::new(),
+                random: None,
+                readings: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Next n calls fail with the error
+    pub fn fail_next(&self, n: usize, error: E) {
+        let mut plan = self.plan.lock().unwrap();
+        plan.next
+            .extend(std::iter::repeat_n((error, Duration::ZERO), n));
+    }
+
+    /// Each call fails with the error with probability p
+    /// p is clamped to 0..=1, NaN never fails
+    pub fn fail_randomly(&self, p: f64, error: E) {
+        let p = if p.is_nan() { 0. } else { p.clamp(0., 1.) };
+        self.plan.lock().unwrap().random = Some((p, error));
+    }
+
+    /// Next n calls hang for the delay and fail with timeout error
+    /// Delay blocks the thread, it isn't suitable for async servers
+    pub fn time_out_next(&self, n: usize, delay: Duration) {
+        let mut plan = self.plan.lock().unwrap();
+        plan.next
+            .extend(std::iter::repeat_n((E::timeout(), delay), n));
+    }
+
+    /// Next n readings return the value instead of simulated one,
+    /// e.g. temperature out of the sensor range
+    pub fn read_next(&self, n: usize, value: f32) {
+        let mut plan = self.plan.lock().unwrap();
+        plan.readings.extend(std::iter::repeat_n(value, n));
+    }
+
+    /// Remove all faults
+    pub fn clear(&self) {
+        let mut plan = self.plan.lock().unwrap();
+        plan.next.clear();
+        plan.random = None;
+        plan.readings.clear();
+    }
+
+    /// Injected value of the reading
+    pub(crate) fn reading(&self) -> Option<f32> {
+        self.plan.lock().unwrap().readings.pop_front()
+    }
+
+    /// Injected failure of the call
+    /// Lock is released before delay, so faults could be changed meanwhile
+    pub(crate) fn check(&self) -> Result<(), E> {
+        let planned = {
+            let mut plan = self.plan.lock().unwrap();
+            match plan.next.pop_front() {
+                Some(failure) => Some(failure),
+                None => plan
+                    .random
+                    .clone()
+                    .filter(|(p, _)| thread_rng().gen_bool(*p))
+                    .map(|(_, e)| (e, Duration::ZERO)),
+            }
+        };
+        match planned {
+            Some((error, delay)) => {
+                thread::sleep(delay);
+                Err(error)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<E: Clone + TimeoutError> Default for FaultInjector<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum TestError {
+        Fault,
+        Timeout,
+    }
+
+    impl TimeoutError for TestError {
+        fn timeout() -> Self {
+            Self::Timeout
+        }
+    }
+
+    #[test]
+    fn test_fail_next() {
+        let faults = FaultInjector::new();
+        faults.fail_next(2, TestError::Fault);
+        let handle = faults.clone();
+        assert_eq!(handle.check(), Err(TestError::Fault));
+        assert_eq!(handle.check(), Err(TestError::Fault));
+        assert_eq!(handle.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_fail_randomly() {
+        let faults = FaultInjector::new();
+        faults.fail_randomly(1., TestError::Fault);
+        assert!((0..10).all(|_| faults.check().is_err()));
+        faults.fail_randomly(0., TestError::Fault);
+        assert!((0..10).all(|_| faults.check().is_ok()));
+        faults.fail_randomly(f64::NAN, TestError::Fault);
+        assert!((0..10).all(|_| faults.check().is_ok()));
+        faults.fail_randomly(1., TestError::Fault);
+        faults.clear();
+        assert_eq!(faults.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_time_out_next() {
+        let faults = FaultInjector::new();
+        faults.time_out_next(1, Duration::from_millis(50));
+        let start = Instant::now();
+        assert_eq!(faults.check(), Err(TestError::Timeout));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(faults.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_read_next() {
+        let faults = FaultInjector::<TestError>::new();
+        faults.read_next(1, 200.);
+        assert_eq!(faults.reading(), Some(200.));
+        assert_eq!(faults.reading(), None);
+        faults.read_next(1, 200.);
+        faults.clear();
+        assert_eq!(faults.reading(), None);
+    }
+}
@@ -1,8 +1,10 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+mod faults;
 mod socket;
 mod therm;
 mod utils;
 
-pub use socket::{Socket, SocketState};
-pub use therm::{Thermometer, ThermometerState};
+pub use faults::{FaultInjector, TimeoutError};
+pub use socket::{Socket, SocketError, SocketState};
+pub use therm::{Thermometer, ThermometerError, ThermometerState};
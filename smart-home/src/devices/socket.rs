@@ -1,12 +1,23 @@
 use std::{error::Error, fmt::Display};
 
-use super::utils::RandomValue;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::{
+    faults::{FaultInjector, TimeoutError},
+    utils::RandomValue,
+};
+use crate::events::{Event, EventBus};
 
 type Result<T> = std::result::Result<T, SocketError>;
 
 pub struct Socket {
     id: String,         // description
     state: SocketState, // current state
+    events: Option<EventBus>,
+    faults: Option<FaultInjector<SocketError>>,
+    max_load: Option<f32>, // W
+    tripped: bool,         // Switched off by overload until reset
 }
 
 impl Socket {
@@ -15,22 +26,78 @@ impl Socket {
         Self {
             id: desc.to_string(),
             state: SocketState::Off,
+            events: None,
+            faults: None,
+            max_load: None,
+            tripped: false,
         }
     }
+    /// Publish state changes to the event bus
+    pub fn set_event_bus(&mut self, events: EventBus) {
+        self.events = Some(events);
+    }
+    /// Simulate failures of turn_on and turn_off
+    pub fn set_faults(&mut self, faults: FaultInjector<SocketError>) {
+        self.faults = Some(faults);
+    }
+    /// Socket trips when load exceeds the limit
+    pub fn set_max_load(&mut self, watts: f32) {
+        self.max_load = Some(watts);
+    }
+    pub fn max_load(&self) -> Option<f32> {
+        self.max_load
+    }
     /// id getter
     pub fn id(&self) -> &str {
         &self.id
     }
     /// Turn socket on
+    /// Tripped socket stays off until reset, overload on turn on trips it
+    /// Load is checked before switching, so overloaded socket is never reported on
     pub fn turn_on(&mut self) -> Result<()> {
-        self.state = SocketState::On;
+        self.check_faults()?;
+        if self.tripped {
+            return Err(SocketError::Tripped);
+        }
+        self.check_load(Socket::choose())?;
+        self.set_state(SocketState::On);
         Ok(())
     }
     /// Turn socket off
     pub fn turn_off(&mut self) -> Result<()> {
-        self.state = SocketState::Off;
+        self.check_faults()?;
+        self.set_state(SocketState::Off);
+        Ok(())
+    }
+    /// state getter
+    pub fn state(&self) -> SocketState {
+        self.state
+    }
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+    /// Clear overload trip, socket stays off
+    pub fn reset(&mut self) {
+        if self.tripped {
+            self.tripped = false;
+            debug!(device = self.id, "socket reset");
+            self.publish(Event::SocketReset {
+                device: self.id.clone(),
+            });
+        }
+    }
+    /// Trip by external protection, e.g. power budget of the home
+    /// Socket is switched off until reset, same as on overload
+    pub fn trip(&mut self, load: f32, limit: f32) -> Result<()> {
+        self.check_faults()?;
+        self.set_tripped(load, limit);
         Ok(())
     }
+    /// State with current load, e.g. "State: off, tripped, power consumption 0.0W"
+    /// Read only, overload is checked on switching
+    pub fn status(&self) -> String {
+        self.describe(self.power_consuption())
+    }
     /// Returns current power consumption (emulation)
     pub fn power_consuption(&self) -> f32 {
         match self.state {
@@ -40,15 +107,60 @@ impl Socket {
     }
 }
 
+impl Socket {
+    fn check_faults(&self) -> Result<()> {
+        self.faults.as_ref().map_or(Ok(()), |f| f.check())
+    }
+
+    fn set_state(&mut self, state: SocketState) {
+        if self.state == state {
+            return;
+        }
+        self.state = state;
+        debug!(device = self.id, ?state, "socket switched");
+        self.publish(Event::SocketStateChanged {
+            device: self.id.clone(),
+            state,
+        });
+    }
+
+    fn check_load(&mut self, load: f32) -> Result<f32> {
+        match self.max_load {
+            Some(limit) if load > limit => {
+                self.set_tripped(load, limit);
+                Err(SocketError::Overload(load))
+            }
+            _ => Ok(load),
+        }
+    }
+
+    fn set_tripped(&mut self, load: f32, limit: f32) {
+        warn!(device = self.id, load, limit, "socket tripped");
+        self.tripped = true;
+        self.set_state(SocketState::Off);
+        self.publish(Event::SocketTripped {
+            device: self.id.clone(),
+            load,
+            limit,
+        });
+    }
+
+    fn publish(&self, event: Event) {
+        if let Some(events) = &self.events {
+            events.publish(event);
+        }
+    }
+
+    fn describe(&self, load: f32) -> String {
+        let tripped = if self.tripped { ", tripped" } else { "" };
+        format!("{}{tripped}, power consumption {load:.1}W", self.state)
+    }
+}
+
 // Text representation used in report
 impl Display for Socket {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}, power consumption {:.1}W",
-            self.state,
-            self.power_consuption()
-        )
+        write!(f, "{}", self.describe(self.power_consuption()))
     }
 }
 
@@ -58,6 +170,7 @@ impl RandomValue for Socket {
     const MAX: f32 = 1000.;
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum SocketState {
     On,
     Off,
@@ -73,22 +186,37 @@ impl Display for SocketState {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SocketError {
-    // Different error types
+    Overload(f32), // Load over the limit, W
+    HardwareFault, // Relay or circuit failure
+    Timeout,       // Device doesn't respond
+    Tripped,       // Switched off by overload, reset is required
 }
 
 impl Display for SocketError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Socket error")
+        match self {
+            Self::Overload(p) => write!(f, "Socket is overloaded: {p:.1}W"),
+            Self::HardwareFault => write!(f, "Socket hardware fault"),
+            Self::Timeout => write!(f, "Socket timed out"),
+            Self::Tripped => write!(f, "Socket is tripped, reset is required"),
+        }
     }
 }
 
 impl Error for SocketError {}
 
+impl TimeoutError for SocketError {
+    fn timeout() -> Self {
+        Self::Timeout
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Socket;
+    use super::{FaultInjector, Socket, SocketError, SocketState};
+    use crate::events::{Event, EventBus};
 
     #[test]
     fn test_display() {
@@ -98,4 +226,104 @@ mod tests {
             "State: off, power consumption 0.0W".to_string()
         );
     }
+
+    #[test]
+    fn test_events() {
+        let bus = EventBus::new();
+        let (_, rx) = bus.subscribe_channel();
+        let mut s = Socket::new("Test");
+        s.set_event_bus(bus);
+        s.turn_on().unwrap();
+        s.turn_on().unwrap();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![Event::SocketStateChanged {
+                device: "Test".into(),
+                state: SocketState::On
+            }]
+        );
+    }
+
+    #[test]
+    fn test_faults() {
+        let faults = FaultInjector::new();
+        let mut s = Socket::new("Test");
+        s.set_faults(faults.clone());
+        faults.fail_next(1, SocketError::HardwareFault);
+        assert_eq!(s.turn_on(), Err(SocketError::HardwareFault));
+        assert_eq!(s.state(), SocketState::Off);
+        assert_eq!(s.turn_on(), Ok(()));
+        assert_eq!(s.state(), SocketState::On);
+    }
+
+    #[test]
+    fn test_overload() {
+        let bus = EventBus::new();
+        let (_, rx) = bus.subscribe_channel();
+        let mut s = Socket::new("Test");
+        s.set_event_bus(bus);
+        // Load is never below 20W
+        s.set_max_load(10.);
+        assert!(matches!(s.turn_on(), Err(SocketError::Overload(_))));
+        assert!(s.is_tripped());
+        assert_eq!(s.state(), SocketState::Off);
+        assert_eq!(s.status(), "State: off, tripped, power consumption 0.0W");
+        assert_eq!(s.turn_on(), Err(SocketError::Tripped));
+
+        s.set_max_load(2000.);
+        s.reset();
+        assert!(!s.is_tripped());
+        assert_eq!(s.turn_on(), Ok(()));
+        assert!(s.status().starts_with("State: on, power consumption"));
+        let events = rx.try_iter().collect::<Vec<_>>();
+        // Overloaded socket isn't switched on before trip
+        assert!(matches!(
+            events.as_slice(),
+            [
+                Event::SocketTripped { limit: 10., .. },
+                Event::SocketReset { .. },
+                Event::SocketStateChanged { .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_status_never_trips() {
+        let mut s = Socket::new("Test");
+        s.turn_on().unwrap();
+        // Limit is lowered after switching on
+        s.set_max_load(10.);
+        for _ in 0..100 {
+            s.status();
+        }
+        assert!(!s.is_tripped());
+        assert_eq!(s.state(), SocketState::On);
+    }
+
+    #[test]
+    fn test_trip() {
+        let bus = EventBus::new();
+        let (_, rx) = bus.subscribe_channel();
+        let mut s = Socket::new("Test");
+        s.turn_on().unwrap();
+        s.set_event_bus(bus);
+        assert_eq!(s.trip(300., 200.), Ok(()));
+        assert!(s.is_tripped());
+        assert_eq!(s.state(), SocketState::Off);
+        assert_eq!(s.turn_on(), Err(SocketError::Tripped));
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![
+                Event::SocketStateChanged {
+                    device: "Test".into(),
+                    state: SocketState::Off
+                },
+                Event::SocketTripped {
+                    device: "Test".into(),
+                    load: 300.,
+                    limit: 200.
+                },
+            ]
+        );
+    }
 }
@@ -1,12 +1,25 @@
 use std::{error::Error, fmt::Display};
 
-use super::utils::RandomValue;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace};
+
+use super::{
+    faults::{FaultInjector, TimeoutError},
+    utils::RandomValue,
+};
+use crate::events::{Event, EventBus};
 
 type Result<T> = std::result::Result<T, ThermometerError>;
 
+// Sensor range, Celsius
+const MIN_READING: f32 = -40.;
+const MAX_READING: f32 = 125.;
+
 pub struct Thermometer {
     id: String,
     state: ThermometerState, // state
+    events: Option<EventBus>,
+    faults: Option<FaultInjector<ThermometerError>>,
 }
 
 impl Thermometer {
@@ -14,24 +27,76 @@ impl Thermometer {
         Self {
             id: id.to_string(),
             state: ThermometerState::Off,
+            events: None,
+            faults: None,
         }
     }
+    /// Publish state changes and readings to the event bus
+    pub fn set_event_bus(&mut self, events: EventBus) {
+        self.events = Some(events);
+    }
+    /// Simulate failures of switching and readings, and wrong readings
+    pub fn set_faults(&mut self, faults: FaultInjector<ThermometerError>) {
+        self.faults = Some(faults);
+    }
     /// id getter
     pub fn id(&self) -> &str {
         &self.id
     }
+    /// state getter
+    pub fn state(&self) -> ThermometerState {
+        self.state
+    }
     pub fn turn_on(&mut self) -> Result<()> {
-        self.state = ThermometerState::On;
+        self.check_faults()?;
+        self.set_state(ThermometerState::On);
         Ok(())
     }
     pub fn turn_off(&mut self) -> Result<()> {
-        self.state = ThermometerState::Off;
+        self.check_faults()?;
+        self.set_state(ThermometerState::Off);
         Ok(())
     }
+    /// Reading outside of the sensor range is an error
     pub fn get_temperature(&mut self) -> Result<f32> {
-        let t = Self::choose();
+        self.check_faults()?;
+        let t = self
+            .faults
+            .as_ref()
+            .and_then(|f| f.reading())
+            .unwrap_or_else(Self::choose);
+        if !(MIN_READING..=MAX_READING).contains(&t) {
+            return Err(ThermometerError::OutOfRange(t));
+        }
+        trace!(device = self.id, temperature = t, "temperature read");
+        self.publish(Event::TemperatureRead {
+            device: self.id.clone(),
+            value: t,
+        });
         Ok(t)
     }
+
+    fn check_faults(&self) -> Result<()> {
+        self.faults.as_ref().map_or(Ok(()), |f| f.check())
+    }
+
+    fn set_state(&mut self, state: ThermometerState) {
+        if self.state == state {
+            return;
+        }
+        self.state = state;
+        debug!(device = self.id, ?state, "thermometer switched");
+        self.publish(Event::ThermometerStateChanged {
+            device: self.id.clone(),
+            state,
+        });
+    }
+
+    fn publish(&self, event: Event) {
+        if let Some(events) = &self.events {
+            events.publish(event);
+        }
+    }
 }
 
 // Text representation used in report
@@ -47,6 +112,7 @@ impl RandomValue for Thermometer {
     const MAX: Self::Value = 25.;
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum ThermometerState {
     On,
     Off,
@@ -62,24 +128,52 @@ impl Display for ThermometerState {
     }
 }
 
-#[derive(Debug)]
-pub enum ThermometerError {}
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThermometerError {
+    Disconnected,    // Sensor is not connected
+    OutOfRange(f32), // Reading outside of the sensor range
+    HardwareFault,   // Device failure
+    Timeout,         // Device doesn't respond
+}
 
 impl Display for ThermometerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Thermometer device error")
+        match self {
+            Self::Disconnected => write!(f, "Thermometer sensor is disconnected"),
+            Self::OutOfRange(t) => write!(f, "Temperature {t:.1} is out of range"),
+            Self::HardwareFault => write!(f, "Thermometer hardware fault"),
+            Self::Timeout => write!(f, "Thermometer timed out"),
+        }
     }
 }
 
 impl Error for ThermometerError {}
 
+impl TimeoutError for ThermometerError {
+    fn timeout() -> Self {
+        Self::Timeout
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Thermometer;
+    use super::{FaultInjector, Thermometer, ThermometerError};
 
     #[test]
     fn test_display() {
         let t = Thermometer::new("therm_123");
         assert_eq!(t.to_string(), "State: off".to_string());
     }
+
+    #[test]
+    fn test_out_of_range() {
+        let faults = FaultInjector::new();
+        let mut t = Thermometer::new("therm_123");
+        t.set_faults(faults.clone());
+        faults.read_next(1, 130.);
+        assert_eq!(t.get_temperature(), Err(ThermometerError::OutOfRange(130.)));
+        faults.read_next(1, -40.);
+        assert_eq!(t.get_temperature(), Ok(-40.));
+        assert!(t.get_temperature().is_ok());
+    }
 }
@@ -0,0 +1,167 @@
+/// Event bus for device and home changes
+/// Subscribers receive events via callbacks or channels
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Arc, Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    devices::{SocketState, ThermometerState},
+    power::Budget,
+    query::DeviceHandle,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Event {
+    SocketStateChanged {
+        device: String,
+        state: SocketState,
+    },
+    ThermometerStateChanged {
+        device: String,
+        state: ThermometerState,
+    },
+    // Socket switched off by overload, load and limit in W
+    SocketTripped {
+        device: String,
+        load: f32,
+        limit: f32,
+    },
+    SocketReset {
+        device: String,
+    },
+    TemperatureRead {
+        device: String,
+        value: f32,
+    },
+    RoomAdded {
+        room: String,
+    },
+    RoomRemoved {
+        room: String,
+    },
+    RoomRenamed {
+        from: String,
+        to: String,
+    },
+    DeviceAdded {
+        room: String,
+        device: String,
+    },
+    DeviceRemoved {
+        room: String,
+        device: String,
+    },
+    // Device renamed or moved to another room
+    DeviceMoved {
+        from_room: String,
+        from_device: String,
+        to_room: String,
+        to_device: String,
+    },
+    // Sockets with the highest load are switched off to fit the budget
+    PowerBudgetExceeded {
+        budget: Budget,
+        load: f32,
+        limit: f32,
+        tripped: Vec<DeviceHandle>,
+    },
+}
+
+pub type SubscriptionId = usize;
+
+enum Subscriber {
+    Callback(Box<dyn Fn(&Event) + Send>),
+    Channel(Sender<Event>),
+}
+
+#[derive(Default)]
+struct Subscribers {
+    next_id: SubscriptionId,
+    subscribers: Vec<(SubscriptionId, Subscriber)>,
+}
+
+/// Handle to the event bus
+/// Clones share subscribers, so the bus could be given
+/// to several devices and SmartHome
+#[derive(Default, Clone)]
+pub struct EventBus {
+    inner: Arc<Mutex<Subscribers>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe callback, it is called in the thread which publishes event
+    /// Callback should not publish to the same bus
+    pub fn subscribe<F: Fn(&Event) + Send + 'static>(&self, callback: F) -> SubscriptionId {
+        self.add(Subscriber::Callback(Box::new(callback)))
+    }
+
+    /// Subscribe with channel, subscription is removed when receiver is dropped
+    pub fn subscribe_channel(&self) -> (SubscriptionId, Receiver<Event>) {
+        let (tx, rx) = channel();
+        (self.add(Subscriber::Channel(tx)), rx)
+    }
+
+    /// Remove subscriber, returns false if there is no such subscription
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.subscribers.len();
+        inner.subscribers.retain(|(i, _)| *i != id);
+        len != inner.subscribers.len()
+    }
+
+    /// Deliver event to all subscribers
+    pub fn publish(&self, event: Event) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.subscribers.retain(|(_, s)| match s {
+            Subscriber::Callback(f) => {
+                f(&event);
+                true
+            }
+            Subscriber::Channel(tx) => tx.send(event.clone()).is_ok(),
+        });
+    }
+
+    fn add(&self, subscriber: Subscriber) -> SubscriptionId {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.subscribers.push((id, subscriber));
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, EventBus};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_subscribers() {
+        let bus = EventBus::new();
+        let (_, rx) = bus.subscribe_channel();
+        let received = Arc::new(Mutex::new(vec![]));
+        let r = received.clone();
+        let id = bus.subscribe(move |e| r.lock().unwrap().push(e.clone()));
+
+        let event = Event::RoomAdded {
+            room: "kitchen".into(),
+        };
+        bus.publish(event.clone());
+        assert_eq!(rx.try_recv(), Ok(event.clone()));
+        assert_eq!(*received.lock().unwrap(), vec![event.clone()]);
+
+        assert!(bus.unsubscribe(id));
+        assert!(!bus.unsubscribe(id));
+        drop(rx);
+        bus.publish(event);
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert!(bus.inner.lock().unwrap().subscribers.is_empty());
+    }
+}
@@ -4,7 +4,17 @@ use std::{
     fmt::Display,
 };
 
-use crate::DeviceInfoProvider;
+use tracing::debug;
+
+use crate::{
+    devices::SocketState,
+    events::{Event, EventBus},
+    power::{over_budget, Budget, Load, Trip},
+    query::{DeviceHandle, DeviceState, DeviceStatus, Query},
+    room::{Room, RoomInfo, Tags},
+    scene::{Scene, SceneReport, SceneResult},
+    DeviceInfoProvider, DeviceStatusProvider, DeviceSwitch,
+};
 
 #[derive(Debug, PartialEq)]
 pub enum SmartHomeError {
@@ -13,6 +23,13 @@ pub enum SmartHomeError {
     DupDevice { room: String, device: String }, // Duplicate device in the room
     NoDevice { room: String, device: String },  // No device in the room with such name
     NoRooms,                                    // No rooms in home
+    DupScene(String),                           // Duplicate scene
+    NoScene(String),                            // No such scene
+    DupFloor(String),                           // Duplicate floor
+    NoFloor(String),                            // No such floor
+    DupZone(String),                            // Duplicate zone
+    NoZone(String),                             // No such zone
+    NotInZone { zone: String, room: String },   // Room is not in the zone
 }
 
 impl Display for SmartHomeError {
@@ -27,6 +44,15 @@ impl Display for SmartHomeError {
             Self::NoDevice { room, device } => {
                 write!(f, "No device {} in the room {}", device, room)
             }
+            Self::DupScene(s) => write!(f, "Scene {} already exists", s),
+            Self::NoScene(s) => write!(f, "No such scene {}", s),
+            Self::DupFloor(fl) => write!(f, "Floor {} already exists", fl),
+            Self::NoFloor(fl) => write!(f, "No such floor {}", fl),
+            Self::DupZone(z) => write!(f, "Zone {} already exists", z),
+            Self::NoZone(z) => write!(f, "No such zone {}", z),
+            Self::NotInZone { zone, room } => {
+                write!(f, "Room {} is not in the zone {}", room, zone)
+            }
         }
     }
 }
@@ -34,13 +60,22 @@ impl Display for SmartHomeError {
 impl Error for SmartHomeError {}
 
 // Unique room name with unique devices
-type Rooms = HashMap<String, HashSet<String>>;
+type Rooms = HashMap<String, Room>;
+// Unique zone name with rooms, zones could overlap
+type Zones = HashMap<String, HashSet<String>>;
 
 /// Smart home contains rooms
 /// Room has unique name
+/// Rooms could be placed on floors and grouped into zones
 pub struct SmartHome {
     name: String,
     rooms: Rooms,
+    floors: Vec<String>,
+    zones: Zones,
+    scenes: HashMap<String, Scene>,
+    events: Option<EventBus>,
+    budget: Option<f32>, // W
+    trips: Vec<Trip>,    // Sockets switched off by budgets
 }
 
 impl SmartHome {
@@ -49,16 +84,30 @@ impl SmartHome {
         Self {
             name: name.to_string(),
             rooms: HashMap::default(),
+            floors: Vec::default(),
+            zones: HashMap::default(),
+            scenes: HashMap::default(),
+            events: None,
+            budget: None,
+            trips: Vec::default(),
         }
     }
 
+    /// Publish rooms and devices changes to the event bus
+    pub fn set_event_bus(&mut self, events: EventBus) {
+        self.events = Some(events);
+    }
+
     /// Register room in the home
     /// If room name exists, error is returned
     pub fn add_room(&mut self, room_name: &str) -> Result<(), SmartHomeError> {
         match self.rooms.get(room_name) {
             Some(_) => Err(SmartHomeError::DupRoom(room_name.to_string())),
             None => {
-                self.rooms.insert(room_name.to_string(), HashSet::default());
+                self.rooms.insert(room_name.to_string(), Room::default());
+                self.publish(Event::RoomAdded {
+                    room: room_name.to_string(),
+                });
                 Ok(())
             }
         }
@@ -68,7 +117,16 @@ impl SmartHome {
     /// If no room exists error is returned
     pub fn remove_room(&mut self, room_name: &str) -> Result<(), SmartHomeError> {
         match self.rooms.remove(room_name) {
-            Some(_) => Ok(()),
+            Some(_) => {
+                for rooms in self.zones.values_mut() {
+                    rooms.remove(room_name);
+                }
+                self.forget_scene_targets(|room, _| room == room_name);
+                self.publish(Event::RoomRemoved {
+                    room: room_name.to_string(),
+                });
+                Ok(())
+            }
             None => Err(SmartHomeError::NoRoom(room_name.to_string())),
         }
     }
@@ -78,22 +136,28 @@ impl SmartHome {
     /// Returns error if device exists
     pub fn add_device(&mut self, room_name: &str, dev_name: &str) -> Result<(), SmartHomeError> {
         match self.rooms.get_mut(room_name) {
-            Some(rooms) => {
-                if rooms.insert(dev_name.to_string()) {
-                    Ok(())
-                } else {
-                    Err(SmartHomeError::DupDevice {
+            Some(room) => {
+                if room.devices.contains_key(dev_name) {
+                    return Err(SmartHomeError::DupDevice {
                         room: room_name.to_string(),
                         device: dev_name.to_string(),
-                    })
+                    });
                 }
+                room.devices.insert(dev_name.to_string(), Tags::default());
             }
             None => {
                 self.rooms
-                    .insert(room_name.to_string(), [dev_name.to_string()].into());
-                Ok(())
+                    .insert(room_name.to_string(), Room::with_device(dev_name));
+                self.publish(Event::RoomAdded {
+                    room: room_name.to_string(),
+                });
             }
         }
+        self.publish(Event::DeviceAdded {
+            room: room_name.to_string(),
+            device: dev_name.to_string(),
+        });
+        Ok(())
     }
 
     /// Remove device from a room
@@ -101,7 +165,12 @@ impl SmartHome {
     pub fn remove_device(&mut self, room_name: &str, dev_name: &str) -> Result<(), SmartHomeError> {
         match self.rooms.get_mut(room_name) {
             Some(room) => {
-                if room.remove(dev_name) {
+                if room.devices.remove(dev_name).is_some() {
+                    self.forget_scene_targets(|room, dev| room == room_name && dev == dev_name);
+                    self.publish(Event::DeviceRemoved {
+                        room: room_name.to_string(),
+                        device: dev_name.to_string(),
+                    });
                     Ok(())
                 } else {
                     Err(SmartHomeError::NoDevice {
@@ -114,6 +183,67 @@ impl SmartHome {
         }
     }
 
+    /// Rename room keeping devices, metadata, floor, zones and scenes
+    /// Device objects are not owned by the home,
+    /// they follow RoomRenamed event, e.g. with DeviceSource::follow
+    pub fn rename_room(&mut self, room_name: &str, new_name: &str) -> Result<(), SmartHomeError> {
+        if room_name == new_name {
+            return self.room_mut(room_name).map(|_| ());
+        }
+        if self.rooms.contains_key(new_name) {
+            return Err(SmartHomeError::DupRoom(new_name.to_string()));
+        }
+        let room = self
+            .rooms
+            .remove(room_name)
+            .ok_or(SmartHomeError::NoRoom(room_name.to_string()))?;
+        self.rooms.insert(new_name.to_string(), room);
+        for rooms in self.zones.values_mut() {
+            if rooms.remove(room_name) {
+                rooms.insert(new_name.to_string());
+            }
+        }
+        for scene in self.scenes.values_mut() {
+            for target in scene.targets_mut().iter_mut() {
+                if target.room == room_name {
+                    target.room = new_name.to_string();
+                }
+            }
+        }
+        self.publish(Event::RoomRenamed {
+            from: room_name.to_string(),
+            to: new_name.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Rename device in the room keeping scenes
+    /// Device objects and schedules follow DeviceMoved event,
+    /// e.g. with DeviceSource::follow and network Scheduler::follow
+    pub fn rename_device(
+        &mut self,
+        room_name: &str,
+        dev_name: &str,
+        new_name: &str,
+    ) -> Result<(), SmartHomeError> {
+        self.relocate_device(room_name, dev_name, room_name, new_name)
+    }
+
+    /// Move device to another existing room keeping scenes, see rename_device
+    pub fn move_device(
+        &mut self,
+        room_name: &str,
+        dev_name: &str,
+        new_room: &str,
+    ) -> Result<(), SmartHomeError> {
+        self.relocate_device(room_name, dev_name, new_room, dev_name)
+    }
+
+    /// Home name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Get rooms list
     pub fn get_rooms(&self) -> Vec<&str> {
         let mut rooms = self
@@ -128,8 +258,8 @@ impl SmartHome {
     /// Get devices in the room
     pub fn devices(&self, room: &str) -> Result<Vec<&str>, SmartHomeError> {
         match self.rooms.get(room) {
-            Some(devices) => {
-                let mut devices = devices.iter().map(|d| d.as_str()).collect::<Vec<_>>();
+            Some(room) => {
+                let mut devices = room.devices.keys().map(|d| d.as_str()).collect::<Vec<_>>();
                 devices.sort_unstable();
                 Ok(devices)
             }
@@ -137,31 +267,635 @@ impl SmartHome {
         }
     }
 
+    /// Add tag to the device
+    pub fn tag_device(
+        &mut self,
+        room: &str,
+        device: &str,
+        tag: &str,
+    ) -> Result<(), SmartHomeError> {
+        self.tags_mut(room, device)?.insert(tag.to_string());
+        Ok(())
+    }
+
+    /// Remove tag from the device, missing tag is not an error
+    pub fn untag_device(
+        &mut self,
+        room: &str,
+        device: &str,
+        tag: &str,
+    ) -> Result<(), SmartHomeError> {
+        self.tags_mut(room, device)?.remove(tag);
+        Ok(())
+    }
+
+    /// Get sorted device tags
+    pub fn device_tags(&self, room: &str, device: &str) -> Result<Vec<&str>, SmartHomeError> {
+        let tags = self
+            .rooms
+            .get(room)
+            .ok_or(SmartHomeError::NoRoom(room.to_string()))?
+            .devices
+            .get(device)
+            .ok_or(SmartHomeError::NoDevice {
+                room: room.to_string(),
+                device: device.to_string(),
+            })?;
+        Ok(tags.iter().map(|t| t.as_str()).collect())
+    }
+
+    /// Find devices by room, floor, zone and tags
+    /// Devices can't match filters which need status (see query_with)
+    pub fn query(&self, query: &Query) -> Vec<DeviceHandle> {
+        self.query_with(query, &mut NoStatus)
+    }
+
+    /// Find devices, live status is taken from the provider
+    /// Handles are sorted by room and device
+    pub fn query_with<T: DeviceStatusProvider>(
+        &self,
+        query: &Query,
+        status_provider: &mut T,
+    ) -> Vec<DeviceHandle> {
+        let mut handles = vec![];
+        for (room_name, room) in self.rooms.iter() {
+            let zoned = |z: &String| {
+                self.zones
+                    .get(z)
+                    .is_some_and(|rooms| rooms.contains(room_name))
+            };
+            if !(query.rooms.is_empty() || query.rooms.contains(room_name))
+                || !(query.floors.is_empty()
+                    || query.floors.iter().any(|f| room.floor.as_ref() == Some(f)))
+                || !(query.zones.is_empty() || query.zones.iter().any(zoned))
+            {
+                continue;
+            }
+            for (device, tags) in room.devices.iter() {
+                if query.tags.iter().all(|t| tags.contains(t)) {
+                    handles.push(DeviceHandle::new(room_name, device));
+                }
+            }
+        }
+        if query.needs_status() {
+            handles.retain(|h| {
+                status_provider
+                    .get_status(&h.room, &h.device)
+                    .is_some_and(|s| query.matches_status(&s))
+            });
+        }
+        handles.sort_unstable();
+        handles
+    }
+
+    /// Switch devices to the state, e.g. devices from query
+    /// All devices are tried, results are returned in order
+    pub fn switch_devices<T: DeviceSwitch>(
+        &self,
+        devices: &[DeviceHandle],
+        switch: &mut T,
+        state: SocketState,
+    ) -> Vec<SceneResult> {
+        devices
+            .iter()
+            .map(|d| SceneResult {
+                room: d.room.clone(),
+                device: d.device.clone(),
+                result: switch.set_state(&d.room, &d.device, state),
+            })
+            .collect()
+    }
+
+    /// Set room metadata
+    pub fn set_room_info(&mut self, room: &str, info: RoomInfo) -> Result<(), SmartHomeError> {
+        self.room_mut(room)?.info = info;
+        Ok(())
+    }
+
+    /// Get room metadata
+    pub fn room_info(&self, room: &str) -> Result<&RoomInfo, SmartHomeError> {
+        match self.rooms.get(room) {
+            Some(room) => Ok(&room.info),
+            None => Err(SmartHomeError::NoRoom(room.to_string())),
+        }
+    }
+
+    /// Limit total load of the room sockets, W
+    pub fn set_room_budget(&mut self, room: &str, watts: f32) -> Result<(), SmartHomeError> {
+        self.room_mut(room)?.budget = Some(watts);
+        Ok(())
+    }
+
+    pub fn room_budget(&self, room: &str) -> Result<Option<f32>, SmartHomeError> {
+        match self.rooms.get(room) {
+            Some(room) => Ok(room.budget),
+            None => Err(SmartHomeError::NoRoom(room.to_string())),
+        }
+    }
+
+    /// Limit total load of all sockets, W
+    pub fn set_home_budget(&mut self, watts: f32) {
+        self.budget = Some(watts);
+    }
+
+    pub fn home_budget(&self) -> Option<f32> {
+        self.budget
+    }
+
+    /// Sockets switched off by budgets, oldest first
+    pub fn trips(&self) -> &[Trip] {
+        &self.trips
+    }
+
+    pub fn clear_trips(&mut self) {
+        self.trips.clear();
+    }
+
+    /// Check room budgets, then home budget, with live socket loads
+    /// Sockets with the highest load in the exceeded budget are tripped
+    /// until total load fits, sockets which fail to trip are skipped
+    pub fn enforce_budgets<T: DeviceStatusProvider + DeviceSwitch>(
+        &mut self,
+        devices: &mut T,
+    ) -> Vec<Trip> {
+        let mut loads = vec![];
+        for (room, device) in self.devices_in_rooms(&self.get_rooms()) {
+            if let Some(DeviceStatus {
+                state: DeviceState::Socket(SocketState::On),
+                reading: Some(power),
+            }) = devices.get_status(room, device)
+            {
+                loads.push(Load {
+                    room: room.to_string(),
+                    device: device.to_string(),
+                    power,
+                });
+            }
+        }
+        let mut rooms = self
+            .rooms
+            .iter()
+            .filter_map(|(name, room)| Some((name.clone(), room.budget?)))
+            .collect::<Vec<_>>();
+        rooms.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let budgets = rooms
+            .into_iter()
+            .map(|(room, limit)| (Budget::Room(room), limit))
+            .chain(self.budget.map(|limit| (Budget::Home, limit)));
+
+        let mut trips = vec![];
+        for (budget, limit) in budgets {
+            let candidates = over_budget(&loads, limit, |l| match &budget {
+                Budget::Room(room) => &l.room == room,
+                Budget::Home => true,
+            });
+            if candidates.is_empty() {
+                continue;
+            }
+            let mut load: f32 = candidates.iter().map(|l| l.power).sum();
+            let total = load;
+            let mut tripped = vec![];
+            for candidate in candidates {
+                if load <= limit {
+                    break;
+                }
+                if devices
+                    .trip(&candidate.room, &candidate.device, candidate.power, limit)
+                    .is_ok()
+                {
+                    load -= candidate.power;
+                    tripped.push(candidate.clone());
+                }
+            }
+            loads.retain(|l| !tripped.contains(l));
+            self.publish(Event::PowerBudgetExceeded {
+                budget: budget.clone(),
+                load: total,
+                limit,
+                tripped: tripped
+                    .iter()
+                    .map(|l| DeviceHandle::new(&l.room, &l.device))
+                    .collect(),
+            });
+            trips.extend(tripped.into_iter().map(|l| Trip {
+                room: l.room,
+                device: l.device,
+                load: l.power,
+                budget: budget.clone(),
+                limit,
+            }));
+        }
+        self.trips.extend(trips.iter().cloned());
+        trips
+    }
+
+    /// Register floor, floors are kept in order of registration
+    pub fn add_floor(&mut self, floor: &str) -> Result<(), SmartHomeError> {
+        if self.floors.iter().any(|f| f == floor) {
+            return Err(SmartHomeError::DupFloor(floor.to_string()));
+        }
+        self.floors.push(floor.to_string());
+        Ok(())
+    }
+
+    /// Remove floor, rooms on the floor are kept without floor
+    pub fn remove_floor(&mut self, floor: &str) -> Result<(), SmartHomeError> {
+        self.check_floor(floor)?;
+        self.floors.retain(|f| f != floor);
+        for room in self.rooms.values_mut() {
+            if room.floor.as_deref() == Some(floor) {
+                room.floor = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get floors list
+    pub fn get_floors(&self) -> Vec<&str> {
+        self.floors.iter().map(|f| f.as_str()).collect()
+    }
+
+    /// Place room on the floor, room could be on one floor only
+    pub fn set_room_floor(&mut self, room: &str, floor: &str) -> Result<(), SmartHomeError> {
+        self.check_floor(floor)?;
+        self.room_mut(room)?.floor = Some(floor.to_string());
+        Ok(())
+    }
+
+    /// Get floor of the room
+    pub fn room_floor(&self, room: &str) -> Result<Option<&str>, SmartHomeError> {
+        match self.rooms.get(room) {
+            Some(room) => Ok(room.floor.as_deref()),
+            None => Err(SmartHomeError::NoRoom(room.to_string())),
+        }
+    }
+
+    /// Get rooms on the floor
+    pub fn rooms_on_floor(&self, floor: &str) -> Result<Vec<&str>, SmartHomeError> {
+        self.check_floor(floor)?;
+        let mut rooms = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| room.floor.as_deref() == Some(floor))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        rooms.sort_unstable();
+        Ok(rooms)
+    }
+
+    /// Register zone
+    pub fn add_zone(&mut self, zone: &str) -> Result<(), SmartHomeError> {
+        if self.zones.contains_key(zone) {
+            return Err(SmartHomeError::DupZone(zone.to_string()));
+        }
+        self.zones.insert(zone.to_string(), HashSet::default());
+        Ok(())
+    }
+
+    pub fn remove_zone(&mut self, zone: &str) -> Result<(), SmartHomeError> {
+        match self.zones.remove(zone) {
+            Some(_) => Ok(()),
+            None => Err(SmartHomeError::NoZone(zone.to_string())),
+        }
+    }
+
+    /// Get zones list
+    pub fn get_zones(&self) -> Vec<&str> {
+        let mut zones = self.zones.keys().map(|z| z.as_str()).collect::<Vec<_>>();
+        zones.sort_unstable();
+        zones
+    }
+
+    /// Add room to the zone, room could be in several zones
+    pub fn add_room_to_zone(&mut self, zone: &str, room: &str) -> Result<(), SmartHomeError> {
+        self.room_mut(room)?;
+        match self.zones.get_mut(zone) {
+            Some(rooms) => {
+                rooms.insert(room.to_string());
+                Ok(())
+            }
+            None => Err(SmartHomeError::NoZone(zone.to_string())),
+        }
+    }
+
+    pub fn remove_room_from_zone(&mut self, zone: &str, room: &str) -> Result<(), SmartHomeError> {
+        self.room_mut(room)?;
+        match self.zones.get_mut(zone).map(|rooms| rooms.remove(room)) {
+            Some(true) => Ok(()),
+            Some(false) => Err(SmartHomeError::NotInZone {
+                zone: zone.to_string(),
+                room: room.to_string(),
+            }),
+            None => Err(SmartHomeError::NoZone(zone.to_string())),
+        }
+    }
+
+    /// Get rooms in the zone
+    pub fn rooms_in_zone(&self, zone: &str) -> Result<Vec<&str>, SmartHomeError> {
+        match self.zones.get(zone) {
+            Some(rooms) => {
+                let mut rooms = rooms.iter().map(|r| r.as_str()).collect::<Vec<_>>();
+                rooms.sort_unstable();
+                Ok(rooms)
+            }
+            None => Err(SmartHomeError::NoZone(zone.to_string())),
+        }
+    }
+
+    /// Get (room, device) pairs on the floor
+    pub fn devices_on_floor(&self, floor: &str) -> Result<Vec<(&str, &str)>, SmartHomeError> {
+        let rooms = self.rooms_on_floor(floor)?;
+        Ok(self.devices_in_rooms(&rooms))
+    }
+
+    /// Get (room, device) pairs in the zone
+    pub fn devices_in_zone(&self, zone: &str) -> Result<Vec<(&str, &str)>, SmartHomeError> {
+        let rooms = self.rooms_in_zone(zone)?;
+        Ok(self.devices_in_rooms(&rooms))
+    }
+
     /// Create report based on type implementing DeviceInfoProvider trait
     pub fn create_report<T: DeviceInfoProvider>(
         &self,
         info_provider: &T,
     ) -> Result<String, SmartHomeError> {
-        let mut report = format!("Report for {} smart home\n\n", self.name);
-        match self.get_rooms() {
+        let title = format!("Report for {} smart home", self.name);
+        let mut report = self.report_rooms(title, &self.get_rooms(), info_provider)?;
+        if !self.trips.is_empty() {
+            report.push_str("\nTrips\n\n");
+            for trip in &self.trips {
+                report.push_str(&format!("{trip}\n"));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Create report for devices, e.g. devices from query
+    pub fn create_devices_report<T: DeviceInfoProvider>(
+        &self,
+        devices: &[DeviceHandle],
+        info_provider: &T,
+    ) -> String {
+        let mut report = format!("Report for {} smart home, selected devices\n\n", self.name);
+        for d in devices {
+            let line = format!("{}\n", info_provider.get_info(&d.room, &d.device));
+            report.push_str(&line);
+        }
+        report
+    }
+
+    /// Create report for rooms on the floor
+    pub fn create_floor_report<T: DeviceInfoProvider>(
+        &self,
+        floor: &str,
+        info_provider: &T,
+    ) -> Result<String, SmartHomeError> {
+        let title = format!("Report for {} smart home, floor {}", self.name, floor);
+        self.report_rooms(title, &self.rooms_on_floor(floor)?, info_provider)
+    }
+
+    /// Create report for rooms in the zone
+    pub fn create_zone_report<T: DeviceInfoProvider>(
+        &self,
+        zone: &str,
+        info_provider: &T,
+    ) -> Result<String, SmartHomeError> {
+        let title = format!("Report for {} smart home, zone {}", self.name, zone);
+        self.report_rooms(title, &self.rooms_in_zone(zone)?, info_provider)
+    }
+
+    fn report_rooms<T: DeviceInfoProvider>(
+        &self,
+        title: String,
+        rooms: &[&str],
+        info_provider: &T,
+    ) -> Result<String, SmartHomeError> {
+        let mut report = format!("{title}\n\n");
+        match rooms {
             rooms if !rooms.is_empty() => {
-                for room in self.get_rooms() {
-                    for device in self.devices(room).unwrap() {
-                        let line = format!("{}\n", info_provider.get_info(room, device));
-                        report.push_str(&line);
-                    }
+                for (room, device) in self.devices_in_rooms(rooms) {
+                    let line = format!("{}\n", info_provider.get_info(room, device));
+                    report.push_str(&line);
                 }
                 Ok(report)
             }
             _ => Err(SmartHomeError::NoRooms),
         }
     }
+
+    fn devices_in_rooms<'a>(&'a self, rooms: &[&'a str]) -> Vec<(&'a str, &'a str)> {
+        rooms
+            .iter()
+            .flat_map(|room| {
+                self.devices(room)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |device| (*room, device))
+            })
+            .collect()
+    }
+
+    /// Check everything first, so failed operation changes nothing
+    fn relocate_device(
+        &mut self,
+        room_name: &str,
+        dev_name: &str,
+        new_room: &str,
+        new_name: &str,
+    ) -> Result<(), SmartHomeError> {
+        if !self.room_mut(room_name)?.devices.contains_key(dev_name) {
+            return Err(SmartHomeError::NoDevice {
+                room: room_name.to_string(),
+                device: dev_name.to_string(),
+            });
+        }
+        if (room_name, dev_name) == (new_room, new_name) {
+            return Ok(());
+        }
+        if self.room_mut(new_room)?.devices.contains_key(new_name) {
+            return Err(SmartHomeError::DupDevice {
+                room: new_room.to_string(),
+                device: new_name.to_string(),
+            });
+        }
+        let tags = self.room_mut(room_name)?.devices.remove(dev_name);
+        self.room_mut(new_room)?
+            .devices
+            .insert(new_name.to_string(), tags.unwrap_or_default());
+        for scene in self.scenes.values_mut() {
+            for target in scene.targets_mut().iter_mut() {
+                if target.room == room_name && target.device == dev_name {
+                    target.room = new_room.to_string();
+                    target.device = new_name.to_string();
+                }
+            }
+        }
+        self.publish(Event::DeviceMoved {
+            from_room: room_name.to_string(),
+            from_device: dev_name.to_string(),
+            to_room: new_room.to_string(),
+            to_device: new_name.to_string(),
+        });
+        Ok(())
+    }
+
+    fn tags_mut(&mut self, room: &str, device: &str) -> Result<&mut Tags, SmartHomeError> {
+        self.room_mut(room)?
+            .devices
+            .get_mut(device)
+            .ok_or(SmartHomeError::NoDevice {
+                room: room.to_string(),
+                device: device.to_string(),
+            })
+    }
+
+    fn room_mut(&mut self, room: &str) -> Result<&mut Room, SmartHomeError> {
+        self.rooms
+            .get_mut(room)
+            .ok_or(SmartHomeError::NoRoom(room.to_string()))
+    }
+
+    fn check_floor(&self, floor: &str) -> Result<(), SmartHomeError> {
+        match self.floors.iter().any(|f| f == floor) {
+            true => Ok(()),
+            false => Err(SmartHomeError::NoFloor(floor.to_string())),
+        }
+    }
+
+    /// Register scene in the home
+    /// All scene devices should exist in the home
+    pub fn add_scene(&mut self, scene: Scene) -> Result<(), SmartHomeError> {
+        if self.scenes.contains_key(scene.name()) {
+            return Err(SmartHomeError::DupScene(scene.name().to_string()));
+        }
+        for target in scene.targets() {
+            if !self
+                .devices(&target.room)?
+                .contains(&target.device.as_str())
+            {
+                return Err(SmartHomeError::NoDevice {
+                    room: target.room.clone(),
+                    device: target.device.clone(),
+                });
+            }
+        }
+        self.scenes.insert(scene.name().to_string(), scene);
+        Ok(())
+    }
+
+    pub fn remove_scene(&mut self, name: &str) -> Result<Scene, SmartHomeError> {
+        self.scenes
+            .remove(name)
+            .ok_or(SmartHomeError::NoScene(name.to_string()))
+    }
+
+    pub fn scene(&self, name: &str) -> Option<&Scene> {
+        self.scenes.get(name)
+    }
+
+    /// Get scenes list
+    pub fn get_scenes(&self) -> Vec<&str> {
+        let mut scenes = self.scenes.keys().map(|s| s.as_str()).collect::<Vec<_>>();
+        scenes.sort_unstable();
+        scenes
+    }
+
+    /// Switch scene devices to desired states
+    /// Without rollback all devices are tried, with rollback first failure
+    /// stops the scene and already changed devices are restored
+    pub fn apply_scene<T: DeviceSwitch>(
+        &self,
+        name: &str,
+        switch: &mut T,
+        rollback: bool,
+    ) -> Result<SceneReport, SmartHomeError> {
+        let scene = self
+            .scenes
+            .get(name)
+            .ok_or(SmartHomeError::NoScene(name.to_string()))?;
+        let mut report = SceneReport {
+            scene: name.to_string(),
+            ..Default::default()
+        };
+        // Devices changed by the scene with their previous state
+        let mut changed = vec![];
+        let mut targets = scene.targets().iter();
+        for target in targets.by_ref() {
+            let (room, device) = (target.room.as_str(), target.device.as_str());
+            let result = match switch.get_state(room, device) {
+                Ok(state) if state == target.state => Ok(()),
+                Ok(state) => switch
+                    .set_state(room, device, target.state)
+                    .map(|_| changed.push((target, state))),
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            report.results.push(SceneResult {
+                room: room.to_string(),
+                device: device.to_string(),
+                result,
+            });
+            if failed && rollback {
+                for (target, state) in changed.into_iter().rev() {
+                    report.rolled_back.push(SceneResult {
+                        room: target.room.clone(),
+                        device: target.device.clone(),
+                        result: switch.set_state(&target.room, &target.device, state),
+                    });
+                }
+                break;
+            }
+        }
+        // Targets after the failure are reported as not attempted
+        report.results.extend(targets.map(|target| SceneResult {
+            room: target.room.clone(),
+            device: target.device.clone(),
+            result: Err("Not executed".into()),
+        }));
+        Ok(report)
+    }
+
+    fn publish(&self, event: Event) {
+        debug!(home = self.name, ?event, "home changed");
+        if let Some(events) = &self.events {
+            events.publish(event);
+        }
+    }
+
+    /// Remove devices from scenes
+    fn forget_scene_targets<F: Fn(&str, &str) -> bool>(&mut self, f: F) {
+        for scene in self.scenes.values_mut() {
+            scene
+                .targets_mut()
+                .retain(|t| !f(t.room.as_str(), t.device.as_str()));
+        }
+    }
+}
+
+/// Status provider without devices
+struct NoStatus;
+
+impl DeviceStatusProvider for NoStatus {
+    fn get_status(&mut self, _room: &str, _device: &str) -> Option<DeviceStatus> {
+        None
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::{SmartHome, SmartHomeError};
+    use crate::{
+        devices::{SocketState, ThermometerState},
+        events::{Event, EventBus},
+        power::Budget,
+        query::{DeviceHandle, DeviceKind, DeviceState, DeviceStatus, Query},
+        room::RoomInfo,
+        scene::Scene,
+        sources::DeviceSource,
+        DeviceStatusProvider, DeviceSwitch,
+    };
+    use std::collections::HashMap;
 
     #[test]
     fn test_rooms() {
@@ -239,4 +973,444 @@ mod tests {
             })
         )
     }
+
+    #[test]
+    fn test_room_info() {
+        let mut h = SmartHome::new("My home");
+        h.add_room("Bedroom").unwrap();
+        let info = RoomInfo {
+            display_name: Some("Master bedroom".to_string()),
+            area: Some(16.5),
+            target_temperature: Some(21.),
+        };
+        h.set_room_info("Bedroom", info.clone()).unwrap();
+        assert_eq!(h.room_info("Bedroom"), Ok(&info));
+        assert_eq!(
+            h.set_room_info("Kitchen", info),
+            Err(SmartHomeError::NoRoom("Kitchen".to_string()))
+        );
+    }
+
+    fn two_floor_home() -> SmartHome {
+        let mut h = SmartHome::new("My home");
+        h.add_device("Kitchen", "Socket1").unwrap();
+        h.add_device("Bedroom", "Socket2").unwrap();
+        h.add_device("Bedroom", "Thermometer").unwrap();
+        h.add_device("Attic", "Socket3").unwrap();
+        h.add_floor("Ground").unwrap();
+        h.add_floor("First").unwrap();
+        h.set_room_floor("Kitchen", "Ground").unwrap();
+        h.set_room_floor("Bedroom", "First").unwrap();
+        h.add_zone("heated").unwrap();
+        h.add_room_to_zone("heated", "Kitchen").unwrap();
+        h.add_room_to_zone("heated", "Bedroom").unwrap();
+        h
+    }
+
+    #[test]
+    fn test_floors() {
+        let mut h = two_floor_home();
+        assert_eq!(h.get_floors(), vec!["Ground", "First"]);
+        assert_eq!(
+            h.add_floor("Ground"),
+            Err(SmartHomeError::DupFloor("Ground".to_string()))
+        );
+        assert_eq!(
+            h.set_room_floor("Attic", "Second"),
+            Err(SmartHomeError::NoFloor("Second".to_string()))
+        );
+        assert_eq!(
+            h.devices_on_floor("First"),
+            Ok(vec![("Bedroom", "Socket2"), ("Bedroom", "Thermometer")])
+        );
+        h.remove_floor("First").unwrap();
+        assert_eq!(h.room_floor("Bedroom"), Ok(None));
+        assert_eq!(h.room_floor("Kitchen"), Ok(Some("Ground")));
+    }
+
+    #[test]
+    fn test_zones() {
+        let mut h = two_floor_home();
+        h.add_zone("upstairs").unwrap();
+        h.add_room_to_zone("upstairs", "Bedroom").unwrap();
+        h.add_room_to_zone("upstairs", "Attic").unwrap();
+        assert_eq!(h.get_zones(), vec!["heated", "upstairs"]);
+        assert_eq!(
+            h.add_room_to_zone("upstairs", "Garage"),
+            Err(SmartHomeError::NoRoom("Garage".to_string()))
+        );
+        assert_eq!(
+            h.devices_in_zone("heated"),
+            Ok(vec![
+                ("Bedroom", "Socket2"),
+                ("Bedroom", "Thermometer"),
+                ("Kitchen", "Socket1")
+            ])
+        );
+        h.remove_room("Bedroom").unwrap();
+        assert_eq!(h.rooms_in_zone("upstairs"), Ok(vec!["Attic"]));
+        h.remove_room_from_zone("upstairs", "Attic").unwrap();
+        assert_eq!(h.devices_in_zone("upstairs"), Ok(vec![]));
+        assert_eq!(
+            h.remove_room_from_zone("upstairs", "Attic"),
+            Err(SmartHomeError::NotInZone {
+                zone: "upstairs".to_string(),
+                room: "Attic".to_string()
+            })
+        );
+        assert_eq!(
+            h.remove_room_from_zone("upstairs", "Garage"),
+            Err(SmartHomeError::NoRoom("Garage".to_string()))
+        );
+        assert_eq!(
+            h.remove_zone("downstairs"),
+            Err(SmartHomeError::NoZone("downstairs".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rename_room() {
+        let mut h = two_floor_home();
+        h.add_scene(Scene::new("Night").with("Bedroom", "Socket2", SocketState::Off))
+            .unwrap();
+        assert_eq!(
+            h.rename_room("Bedroom", "Kitchen"),
+            Err(SmartHomeError::DupRoom("Kitchen".to_string()))
+        );
+        assert_eq!(
+            h.rename_room("Garage", "Workshop"),
+            Err(SmartHomeError::NoRoom("Garage".to_string()))
+        );
+        h.rename_room("Bedroom", "Guestroom").unwrap();
+        assert_eq!(h.get_rooms(), vec!["Attic", "Guestroom", "Kitchen"]);
+        assert_eq!(h.devices("Guestroom"), Ok(vec!["Socket2", "Thermometer"]));
+        assert_eq!(h.room_floor("Guestroom"), Ok(Some("First")));
+        assert_eq!(h.rooms_in_zone("heated"), Ok(vec!["Guestroom", "Kitchen"]));
+        assert_eq!(h.scene("Night").unwrap().targets()[0].room, "Guestroom");
+    }
+
+    #[test]
+    fn test_move_device() {
+        let mut h = two_floor_home();
+        h.add_scene(Scene::new("Night").with("Bedroom", "Socket2", SocketState::Off))
+            .unwrap();
+        assert_eq!(
+            h.move_device("Bedroom", "Socket2", "Garage"),
+            Err(SmartHomeError::NoRoom("Garage".to_string()))
+        );
+        assert_eq!(
+            h.rename_device("Bedroom", "Socket2", "Thermometer"),
+            Err(SmartHomeError::DupDevice {
+                room: "Bedroom".to_string(),
+                device: "Thermometer".to_string()
+            })
+        );
+        assert_eq!(h.devices("Bedroom"), Ok(vec!["Socket2", "Thermometer"]));
+
+        h.move_device("Bedroom", "Socket2", "Kitchen").unwrap();
+        h.rename_device("Kitchen", "Socket2", "Kettle").unwrap();
+        assert_eq!(h.devices("Kitchen"), Ok(vec!["Kettle", "Socket1"]));
+        assert_eq!(h.devices("Bedroom"), Ok(vec!["Thermometer"]));
+        let target = &h.scene("Night").unwrap().targets()[0];
+        assert_eq!(
+            (target.room.as_str(), target.device.as_str()),
+            ("Kitchen", "Kettle")
+        );
+        assert_eq!(
+            h.move_device("Kitchen", "Socket2", "Bedroom"),
+            Err(SmartHomeError::NoDevice {
+                room: "Kitchen".to_string(),
+                device: "Socket2".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut h = two_floor_home();
+        h.tag_device("Kitchen", "Socket1", "kitchen-appliance")
+            .unwrap();
+        h.tag_device("Kitchen", "Socket1", "critical").unwrap();
+        h.untag_device("Kitchen", "Socket1", "outdoor").unwrap();
+        assert_eq!(
+            h.device_tags("Kitchen", "Socket1"),
+            Ok(vec!["critical", "kitchen-appliance"])
+        );
+        assert_eq!(
+            h.tag_device("Kitchen", "Socket9", "critical"),
+            Err(SmartHomeError::NoDevice {
+                room: "Kitchen".to_string(),
+                device: "Socket9".to_string()
+            })
+        );
+        // tags follow the device
+        h.move_device("Kitchen", "Socket1", "Attic").unwrap();
+        assert_eq!(
+            h.device_tags("Attic", "Socket1"),
+            Ok(vec!["critical", "kitchen-appliance"])
+        );
+    }
+
+    /// Sockets are on, thermometers read 22 degrees
+    struct TestStatus;
+
+    impl DeviceStatusProvider for TestStatus {
+        fn get_status(&mut self, _room: &str, device: &str) -> Option<DeviceStatus> {
+            let (state, reading) = match device.starts_with("Socket") {
+                true => (DeviceState::Socket(SocketState::On), 100.),
+                false => (DeviceState::Thermometer(ThermometerState::On), 22.),
+            };
+            Some(DeviceStatus {
+                state,
+                reading: Some(reading),
+            })
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let mut h = two_floor_home();
+        h.tag_device("Bedroom", "Socket2", "critical").unwrap();
+        h.tag_device("Attic", "Socket3", "critical").unwrap();
+        let handles = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(r, d)| DeviceHandle::new(r, d))
+                .collect::<Vec<_>>()
+        };
+
+        let q = Query::new().tag("critical");
+        assert_eq!(
+            h.query(&q),
+            handles(&[("Attic", "Socket3"), ("Bedroom", "Socket2")])
+        );
+        assert_eq!(
+            h.query(&q.clone().zone("heated")),
+            handles(&[("Bedroom", "Socket2")])
+        );
+        assert_eq!(
+            h.query(&Query::new().floor("Ground")),
+            handles(&[("Kitchen", "Socket1")])
+        );
+        // status filters need provider
+        let q = Query::new().room("Bedroom").kind(DeviceKind::Thermometer);
+        assert_eq!(h.query(&q), vec![]);
+        assert_eq!(
+            h.query_with(&q, &mut TestStatus),
+            handles(&[("Bedroom", "Thermometer")])
+        );
+        let q = Query::new().reading_above(50.).state(SocketState::On);
+        assert_eq!(h.query_with(&q, &mut TestStatus).len(), 3);
+        let q = Query::new().reading_below(50.).state(SocketState::Off);
+        assert_eq!(h.query_with(&q, &mut TestStatus), vec![]);
+    }
+
+    #[test]
+    fn test_query_switch() {
+        let mut h = two_floor_home();
+        h.tag_device("Kitchen", "Socket1", "critical").unwrap();
+        let devices = h.query(&Query::new().floor("Ground"));
+        let mut switch = TestSwitch::default();
+        let results = h.switch_devices(&devices, &mut switch, SocketState::Off);
+        assert_eq!(results[0].result, Ok(()));
+        assert_eq!(switch.0.get("Socket1"), Some(&SocketState::Off));
+        h.add_scene(Scene::new("Off").with_devices(&devices, SocketState::Off))
+            .unwrap();
+        assert_eq!(h.scene("Off").unwrap().targets()[0].device, "Socket1");
+    }
+
+    #[test]
+    fn test_events() {
+        let bus = EventBus::new();
+        let (_, rx) = bus.subscribe_channel();
+        let mut h = SmartHome::new("My home");
+        h.set_event_bus(bus);
+        h.add_device("Oneroom", "mydevice").unwrap();
+        h.add_device("Oneroom", "mydevice").unwrap_err();
+        h.remove_device("Oneroom", "mydevice").unwrap();
+        h.remove_room("Oneroom").unwrap();
+        let room = || "Oneroom".to_string();
+        let device = || "mydevice".to_string();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![
+                Event::RoomAdded { room: room() },
+                Event::DeviceAdded {
+                    room: room(),
+                    device: device()
+                },
+                Event::DeviceRemoved {
+                    room: room(),
+                    device: device()
+                },
+                Event::RoomRemoved { room: room() },
+            ]
+        );
+    }
+
+    /// Switch which fails on devices named "broken"
+    #[derive(Default)]
+    struct TestSwitch(HashMap<String, SocketState>);
+
+    impl DeviceSwitch for TestSwitch {
+        fn get_state(&mut self, _room: &str, device: &str) -> Result<SocketState, String> {
+            Ok(*self.0.get(device).unwrap_or(&SocketState::On))
+        }
+        fn set_state(
+            &mut self,
+            _room: &str,
+            device: &str,
+            state: SocketState,
+        ) -> Result<(), String> {
+            if device == "broken" {
+                return Err("Device is broken".into());
+            }
+            self.0.insert(device.to_string(), state);
+            Ok(())
+        }
+    }
+
+    /// Sockets which are on with their load, switched off sockets are removed
+    struct TestLoads(HashMap<String, f32>);
+
+    impl DeviceStatusProvider for TestLoads {
+        fn get_status(&mut self, _room: &str, device: &str) -> Option<DeviceStatus> {
+            Some(DeviceStatus {
+                state: DeviceState::Socket(SocketState::On),
+                reading: Some(*self.0.get(device)?),
+            })
+        }
+    }
+
+    impl DeviceSwitch for TestLoads {
+        fn get_state(&mut self, _room: &str, _device: &str) -> Result<SocketState, String> {
+            Ok(SocketState::On)
+        }
+        fn set_state(
+            &mut self,
+            _room: &str,
+            device: &str,
+            _state: SocketState,
+        ) -> Result<(), String> {
+            self.0.remove(device);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_budgets() {
+        let bus = EventBus::new();
+        let (_, rx) = bus.subscribe_channel();
+        let mut h = SmartHome::new("My home");
+        h.add_device("Kitchen", "kettle").unwrap();
+        h.add_device("Kitchen", "fridge").unwrap();
+        h.add_device("Bedroom", "heater").unwrap();
+        h.set_event_bus(bus);
+        assert_eq!(
+            h.set_room_budget("Attic", 100.),
+            Err(SmartHomeError::NoRoom("Attic".into()))
+        );
+        h.set_room_budget("Kitchen", 2500.).unwrap();
+        h.set_home_budget(2000.);
+        let mut loads = TestLoads(
+            [("kettle", 2000.), ("fridge", 150.), ("heater", 1500.)]
+                .map(|(d, p)| (d.to_string(), p))
+                .into(),
+        );
+        assert_eq!(h.enforce_budgets(&mut loads).len(), 1);
+        assert_eq!(
+            h.trips()
+                .iter()
+                .map(|t| (t.device.as_str(), &t.budget))
+                .collect::<Vec<_>>(),
+            vec![("kettle", &Budget::Home)]
+        );
+        assert_eq!(loads.0.len(), 2);
+        assert!(matches!(
+            rx.try_iter().collect::<Vec<_>>().as_slice(),
+            [Event::PowerBudgetExceeded { budget: Budget::Home, load: 3650., tripped, .. }]
+                if tripped == &vec![DeviceHandle::new("Kitchen", "kettle")]
+        ));
+        // Room budget is checked first
+        h.set_room_budget("Bedroom", 1000.).unwrap();
+        let trips = h.enforce_budgets(&mut loads);
+        assert_eq!(trips[0].budget, Budget::Room("Bedroom".into()));
+        assert_eq!(trips.len(), 1);
+        assert_eq!(h.trips().len(), 2);
+        assert!(h.enforce_budgets(&mut loads).is_empty());
+        let source = DeviceSource::<String>::new();
+        let report = h.create_report(&source).unwrap();
+        assert!(report.ends_with(
+            "Trips
+
+Kitchen             kettle              Tripped at 2000.0W, home budget 2000.0W
+Bedroom             heater              Tripped at 1500.0W, room Bedroom budget 1000.0W
+"
+        ));
+        h.clear_trips();
+        assert!(!h.create_report(&source).unwrap().contains("Trips"));
+    }
+
+    fn night_home() -> SmartHome {
+        let mut h = SmartHome::new("My home");
+        h.add_device("bedroom", "s1").unwrap();
+        h.add_device("kitchen", "broken").unwrap();
+        h.add_device("kitchen", "s2").unwrap();
+        h.add_scene(
+            Scene::new("Night mode")
+                .with("bedroom", "s1", SocketState::Off)
+                .with("kitchen", "broken", SocketState::Off)
+                .with("kitchen", "s2", SocketState::Off),
+        )
+        .unwrap();
+        h
+    }
+
+    #[test]
+    fn test_add_scene() {
+        let mut h = night_home();
+        assert_eq!(h.get_scenes(), vec!["Night mode"]);
+        assert_eq!(
+            h.add_scene(Scene::new("Night mode")),
+            Err(SmartHomeError::DupScene("Night mode".to_string()))
+        );
+        assert_eq!(
+            h.add_scene(Scene::new("Day").with("bedroom", "s3", SocketState::On)),
+            Err(SmartHomeError::NoDevice {
+                room: "bedroom".to_string(),
+                device: "s3".to_string()
+            })
+        );
+        // removed devices are removed from scenes
+        h.remove_room("kitchen").unwrap();
+        assert_eq!(h.scene("Night mode").unwrap().targets().len(), 1);
+    }
+
+    #[test]
+    fn test_apply_scene() {
+        let h = night_home();
+        let mut switch = TestSwitch::default();
+        let report = h.apply_scene("Night mode", &mut switch, false).unwrap();
+        assert!(!report.is_success());
+        let results = report.results.iter().map(|r| r.result.is_ok());
+        assert_eq!(results.collect::<Vec<_>>(), vec![true, false, true]);
+        assert_eq!(switch.0.get("s2"), Some(&SocketState::Off));
+        assert_eq!(
+            h.apply_scene("Day", &mut switch, false),
+            Err(SmartHomeError::NoScene("Day".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_apply_scene_rollback() {
+        let h = night_home();
+        let mut switch = TestSwitch::default();
+        let report = h.apply_scene("Night mode", &mut switch, true).unwrap();
+        assert_eq!(report.results.len(), 3);
+        assert_eq!(report.results[2].device, "s2");
+        assert_eq!(report.results[2].result, Err("Not executed".into()));
+        assert_eq!(report.rolled_back.len(), 1);
+        assert_eq!(report.rolled_back[0].device, "s1");
+        assert_eq!(switch.0.get("s1"), Some(&SocketState::On));
+        assert_eq!(switch.0.get("s2"), None);
+    }
 }
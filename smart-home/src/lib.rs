@@ -1,11 +1,40 @@
 pub mod devices;
+pub mod events;
 mod home;
+pub mod power;
+pub mod query;
+mod room;
+pub mod scene;
 pub mod sources;
 
+use devices::SocketState;
 pub use home::{SmartHome, SmartHomeError};
+use query::DeviceStatus;
+pub use room::RoomInfo;
 
 /// Interface for container with live devices
 pub trait DeviceInfoProvider {
     /// Method returns device info from room name and device name
     fn get_info(&self, room: &str, device: &str) -> String;
 }
+
+/// Interface for container which switches devices on and off
+/// Used to apply scenes to local or remote devices
+pub trait DeviceSwitch {
+    /// Method returns current device state from room name and device name
+    fn get_state(&mut self, room: &str, device: &str) -> Result<SocketState, String>;
+    /// Method sets device state
+    fn set_state(&mut self, room: &str, device: &str, state: SocketState) -> Result<(), String>;
+    /// Method trips socket over the power limit, it stays off until reset
+    /// Container without trip support just switches socket off
+    fn trip(&mut self, room: &str, device: &str, _load: f32, _limit: f32) -> Result<(), String> {
+        self.set_state(room, device, SocketState::Off)
+    }
+}
+
+/// Interface for container which reports live device status
+/// Used by queries filtering by type, state and readings
+pub trait DeviceStatusProvider {
+    /// Method returns device status or None if device is not available
+    fn get_status(&mut self, room: &str, device: &str) -> Option<DeviceStatus>;
+}
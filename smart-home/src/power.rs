@@ -0,0 +1,92 @@
+/// Power budgets of the smart home
+/// Room or home budget limits total load of sockets which are on
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Budget {
+    Room(String),
+    Home,
+}
+
+impl Display for Budget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Room(r) => write!(f, "room {r} budget"),
+            Self::Home => write!(f, "home budget"),
+        }
+    }
+}
+
+/// Socket switched off to fit the budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trip {
+    pub room: String,
+    pub device: String,
+    pub load: f32, // W
+    pub budget: Budget,
+    pub limit: f32, // W
+}
+
+// Text representation used in report
+impl Display for Trip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<20}{:<20}Tripped at {:.1}W, {} {:.1}W",
+            self.room, self.device, self.load, self.budget, self.limit
+        )
+    }
+}
+
+/// Load of the socket which is on
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Load {
+    pub(crate) room: String,
+    pub(crate) device: String,
+    pub(crate) power: f32,
+}
+
+/// Counted loads, highest first, if their total exceeds the limit
+/// Caller switches them off in order until the total fits
+/// Empty if the budget is not exceeded
+pub(crate) fn over_budget<F: Fn(&Load) -> bool>(
+    loads: &[Load],
+    limit: f32,
+    counted: F,
+) -> Vec<&Load> {
+    let mut loads = loads.iter().filter(|l| counted(l)).collect::<Vec<_>>();
+    let total: f32 = loads.iter().map(|l| l.power).sum();
+    if total <= limit {
+        return vec![];
+    }
+    loads.sort_by(|a, b| b.power.total_cmp(&a.power));
+    loads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_over_budget() {
+        let load = |device: &str, power| Load {
+            room: "Kitchen".into(),
+            device: device.into(),
+            power,
+        };
+        let loads = [
+            load("kettle", 2000.),
+            load("fridge", 150.),
+            load("oven", 2500.),
+        ];
+        assert!(over_budget(&loads, 5000., |_| true).is_empty());
+        let devices = over_budget(&loads, 3000., |_| true)
+            .into_iter()
+            .map(|l| l.device.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(devices, vec!["oven", "kettle", "fridge"]);
+        assert!(over_budget(&loads, 3000., |l| l.device == "fridge").is_empty());
+    }
+}
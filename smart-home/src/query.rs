@@ -0,0 +1,126 @@
+/// Query of devices in the smart home
+/// Filters by room, floor, zone and tag use home structure only,
+/// filters by type, state and reading need DeviceStatusProvider
+use serde::{Deserialize, Serialize};
+
+use crate::devices::{SocketState, ThermometerState};
+
+/// Device in the room, result of the query
+/// Usable for reports, scenes and bulk commands
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DeviceHandle {
+    pub room: String,
+    pub device: String,
+}
+
+impl DeviceHandle {
+    pub fn new(room: &str, device: &str) -> Self {
+        Self {
+            room: room.to_string(),
+            device: device.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceKind {
+    Socket,
+    Thermometer,
+}
+
+/// State of the device of its kind
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceState {
+    Socket(SocketState),
+    Thermometer(ThermometerState),
+}
+
+/// Live device status
+/// Reading is power consumption for socket and temperature for thermometer
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceStatus {
+    pub state: DeviceState,
+    pub reading: Option<f32>,
+}
+
+impl DeviceStatus {
+    pub fn kind(&self) -> DeviceKind {
+        match self.state {
+            DeviceState::Socket(_) => DeviceKind::Socket,
+            DeviceState::Thermometer(_) => DeviceKind::Thermometer,
+        }
+    }
+}
+
+/// Devices filter, all specified conditions should match
+/// Several rooms (floors, zones, kinds) match any of them,
+/// several tags should all be on the device
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Query {
+    pub(crate) rooms: Vec<String>,
+    pub(crate) floors: Vec<String>,
+    pub(crate) zones: Vec<String>,
+    pub(crate) tags: Vec<String>,
+    kinds: Vec<DeviceKind>,
+    state: Option<SocketState>,
+    reading_above: Option<f32>,
+    reading_below: Option<f32>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn room(mut self, room: &str) -> Self {
+        self.rooms.push(room.to_string());
+        self
+    }
+    pub fn floor(mut self, floor: &str) -> Self {
+        self.floors.push(floor.to_string());
+        self
+    }
+    pub fn zone(mut self, zone: &str) -> Self {
+        self.zones.push(zone.to_string());
+        self
+    }
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+    pub fn kind(mut self, kind: DeviceKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+    /// Socket state, thermometers don't match
+    pub fn state(mut self, state: SocketState) -> Self {
+        self.state = Some(state);
+        self
+    }
+    pub fn reading_above(mut self, value: f32) -> Self {
+        self.reading_above = Some(value);
+        self
+    }
+    pub fn reading_below(mut self, value: f32) -> Self {
+        self.reading_below = Some(value);
+        self
+    }
+
+    /// True if query has filters which need device status
+    pub fn needs_status(&self) -> bool {
+        !self.kinds.is_empty()
+            || self.state.is_some()
+            || self.reading_above.is_some()
+            || self.reading_below.is_some()
+    }
+
+    /// Check status filters
+    pub(crate) fn matches_status(&self, status: &DeviceStatus) -> bool {
+        let reading = |check: &dyn Fn(f32) -> bool| status.reading.is_some_and(check);
+        (self.kinds.is_empty() || self.kinds.contains(&status.kind()))
+            && self
+                .state
+                .is_none_or(|s| status.state == DeviceState::Socket(s))
+            && self.reading_above.is_none_or(|v| reading(&|r| r > v))
+            && self.reading_below.is_none_or(|v| reading(&|r| r < v))
+    }
+}
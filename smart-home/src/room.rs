@@ -0,0 +1,30 @@
+/// Room of the smart home: devices, metadata and floor
+use std::collections::{BTreeSet, HashMap};
+
+/// Room attributes, all are optional
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RoomInfo {
+    pub display_name: Option<String>,
+    pub area: Option<f32>,               // square meters
+    pub target_temperature: Option<f32>, // celsius
+}
+
+// Device tags, e.g. "critical", "outdoor"
+pub(crate) type Tags = BTreeSet<String>;
+
+#[derive(Default)]
+pub(crate) struct Room {
+    pub(crate) devices: HashMap<String, Tags>,
+    pub(crate) info: RoomInfo,
+    pub(crate) floor: Option<String>,
+    pub(crate) budget: Option<f32>, // W
+}
+
+impl Room {
+    pub(crate) fn with_device(device: &str) -> Self {
+        Self {
+            devices: [(device.to_string(), Tags::default())].into(),
+            ..Default::default()
+        }
+    }
+}
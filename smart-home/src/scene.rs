@@ -0,0 +1,77 @@
+/// Scenes: named groups of desired device states
+/// applied together with SmartHome::apply_scene
+use crate::{devices::SocketState, query::DeviceHandle};
+
+/// Desired state of the device in the room
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneTarget {
+    pub room: String,
+    pub device: String,
+    pub state: SocketState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    name: String,
+    targets: Vec<SceneTarget>,
+}
+
+impl Scene {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            targets: Vec::default(),
+        }
+    }
+    /// Add device with desired state, targets are applied in order
+    pub fn with(mut self, room: &str, device: &str, state: SocketState) -> Self {
+        self.targets.push(SceneTarget {
+            room: room.to_string(),
+            device: device.to_string(),
+            state,
+        });
+        self
+    }
+    /// Add devices with the same desired state, e.g. from query
+    pub fn with_devices(mut self, devices: &[DeviceHandle], state: SocketState) -> Self {
+        for d in devices {
+            self = self.with(&d.room, &d.device, state);
+        }
+        self
+    }
+    /// name getter
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn targets(&self) -> &[SceneTarget] {
+        &self.targets
+    }
+    pub(crate) fn targets_mut(&mut self) -> &mut Vec<SceneTarget> {
+        &mut self.targets
+    }
+}
+
+/// Result of applying scene to one device
+#[derive(Debug, PartialEq)]
+pub struct SceneResult {
+    pub room: String,
+    pub device: String,
+    pub result: Result<(), String>,
+}
+
+/// Per-device results of applying scene
+/// Devices restored to previous state after failure are listed in rolled_back,
+/// devices after the failure are in results with "Not executed" error
+#[derive(Debug, PartialEq, Default)]
+pub struct SceneReport {
+    pub scene: String,
+    pub results: Vec<SceneResult>,
+    pub rolled_back: Vec<SceneResult>,
+}
+
+impl SceneReport {
+    /// True if all devices were switched
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|r| r.result.is_ok())
+    }
+}
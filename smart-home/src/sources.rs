@@ -1,7 +1,12 @@
 /// Device sources
 use std::{collections::HashMap, error::Error, fmt::Display};
 
-use crate::DeviceInfoProvider;
+use crate::{
+    devices::{Socket, SocketState, Thermometer},
+    events::Event,
+    query::{DeviceState, DeviceStatus},
+    DeviceInfoProvider, DeviceStatusProvider, DeviceSwitch,
+};
 
 //   Id = (Name,   Room  )
 type Id = (String, String);
@@ -16,6 +21,7 @@ pub struct DeviceSource<T: Display> {
 #[derive(Debug, PartialEq)]
 pub enum DeviceSourceError {
     DupDevice { device: String, room: String },
+    NoDevice { device: String, room: String },
 }
 
 impl Display for DeviceSourceError {
@@ -24,6 +30,9 @@ impl Display for DeviceSourceError {
             Self::DupDevice { device, room } => {
                 write!(f, "Device {} already exists in the room {}", device, room)
             }
+            Self::NoDevice { device, room } => {
+                write!(f, "No device {} in the room {}", device, room)
+            }
         }
     }
 }
@@ -59,6 +68,69 @@ impl<T: Display> DeviceSource<T> {
     }
 }
 
+impl<T: Display> DeviceSource<T> {
+    /// Follow rename and move events of SmartHome
+    /// Devices which are not in the source are ignored
+    pub fn follow(&mut self, event: &Event) {
+        match event {
+            Event::DeviceMoved {
+                from_room,
+                from_device,
+                to_room,
+                to_device,
+            } => {
+                let _ = self.move_device(from_device, from_room, to_device, to_room);
+            }
+            Event::RoomRenamed { from, to } => {
+                let moved = self
+                    .devices
+                    .keys()
+                    .filter(|(_, room)| room == from)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for key in moved {
+                    if let Some(device) = self.devices.remove(&key) {
+                        self.devices.insert((key.0, to.clone()), device);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Move device to new name and room
+    /// Used to follow SmartHome rename and move operations
+    pub fn move_device(
+        &mut self,
+        device_name: &str,
+        room: &str,
+        new_name: &str,
+        new_room: &str,
+    ) -> Result<(), DeviceSourceError> {
+        let key = (device_name.to_string(), room.to_string());
+        let new_key = (new_name.to_string(), new_room.to_string());
+        if key == new_key {
+            return Ok(());
+        }
+        if self.devices.contains_key(&new_key) {
+            return Err(DeviceSourceError::DupDevice {
+                device: new_key.0,
+                room: new_key.1,
+            });
+        }
+        match self.devices.remove(&key) {
+            Some(device) => {
+                self.devices.insert(new_key, device);
+                Ok(())
+            }
+            None => Err(DeviceSourceError::NoDevice {
+                device: key.0,
+                room: key.1,
+            }),
+        }
+    }
+}
+
 impl<T: Display> DeviceInfoProvider for DeviceSource<T> {
     fn get_info(&self, room: &str, device: &str) -> String {
         match self.devices.get(&(device.to_string(), room.to_string())) {
@@ -68,6 +140,57 @@ impl<T: Display> DeviceInfoProvider for DeviceSource<T> {
     }
 }
 
+impl DeviceSwitch for DeviceSource<Socket> {
+    fn get_state(&mut self, room: &str, device: &str) -> Result<SocketState, String> {
+        match self.devices.get(&(device.to_string(), room.to_string())) {
+            Some(socket) => Ok(socket.state()),
+            None => Err("Error connecting device".into()),
+        }
+    }
+
+    fn set_state(&mut self, room: &str, device: &str, state: SocketState) -> Result<(), String> {
+        let socket = self
+            .devices
+            .get_mut(&(device.to_string(), room.to_string()))
+            .ok_or("Error connecting device")?;
+        match state {
+            SocketState::On => socket.turn_on(),
+            SocketState::Off => socket.turn_off(),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    fn trip(&mut self, room: &str, device: &str, load: f32, limit: f32) -> Result<(), String> {
+        self.devices
+            .get_mut(&(device.to_string(), room.to_string()))
+            .ok_or("Error connecting device")?
+            .trip(load, limit)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl DeviceStatusProvider for DeviceSource<Socket> {
+    fn get_status(&mut self, room: &str, device: &str) -> Option<DeviceStatus> {
+        let socket = self.devices.get(&(device.to_string(), room.to_string()))?;
+        Some(DeviceStatus {
+            state: DeviceState::Socket(socket.state()),
+            reading: Some(socket.power_consuption()),
+        })
+    }
+}
+
+impl DeviceStatusProvider for DeviceSource<Thermometer> {
+    fn get_status(&mut self, room: &str, device: &str) -> Option<DeviceStatus> {
+        let therm = self
+            .devices
+            .get_mut(&(device.to_string(), room.to_string()))?;
+        Some(DeviceStatus {
+            state: DeviceState::Thermometer(therm.state()),
+            reading: therm.get_temperature().ok(),
+        })
+    }
+}
+
 impl<T: Display> Default for DeviceSource<T> {
     fn default() -> Self {
         Self::new()
@@ -77,7 +200,13 @@ impl<T: Display> Default for DeviceSource<T> {
 #[cfg(test)]
 mod tests {
     use super::DeviceSource;
-    use crate::{sources::DeviceSourceError, DeviceInfoProvider};
+    use crate::{
+        devices::{Socket, SocketState},
+        events::EventBus,
+        query::DeviceKind,
+        sources::DeviceSourceError,
+        DeviceInfoProvider, DeviceStatusProvider, DeviceSwitch, SmartHome,
+    };
 
     #[test]
     /// If device exists return error
@@ -110,4 +239,72 @@ mod tests {
             "Kitchen             Socket-2            Error connecting device"
         );
     }
+
+    #[test]
+    fn test_move_device() {
+        let mut s = DeviceSource::new();
+        s.add_device("Socket-1", "Guestroom", "Socket-1 state description")
+            .unwrap();
+        s.move_device("Socket-1", "Guestroom", "Socket-2", "Kitchen")
+            .unwrap();
+        assert_eq!(
+            s.get_info("Kitchen", "Socket-2"),
+            "Kitchen             Socket-2            Socket-1 state description"
+        );
+        assert_eq!(
+            s.move_device("Socket-1", "Guestroom", "Socket-2", "Kitchen"),
+            Err(DeviceSourceError::DupDevice {
+                device: "Socket-2".to_string(),
+                room: "Kitchen".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_follow() {
+        let bus = EventBus::new();
+        let (_, rx) = bus.subscribe_channel();
+        let mut home = SmartHome::new("My home");
+        home.set_event_bus(bus);
+        home.add_device("Guestroom", "Socket-1").unwrap();
+        home.add_device("Guestroom", "Socket-2").unwrap();
+        home.add_room("Bedroom").unwrap();
+        let mut s = DeviceSource::new();
+        s.add_device("Socket-1", "Guestroom", "Socket-1 state description")
+            .unwrap();
+        s.add_device("Socket-2", "Guestroom", "Socket-2 state description")
+            .unwrap();
+
+        home.move_device("Guestroom", "Socket-1", "Bedroom")
+            .unwrap();
+        home.rename_room("Guestroom", "Kitchen").unwrap();
+        home.rename_device("Kitchen", "Socket-2", "Socket-3")
+            .unwrap();
+        for event in rx.try_iter() {
+            s.follow(&event);
+        }
+        assert_eq!(
+            s.get_info("Bedroom", "Socket-1"),
+            "Bedroom             Socket-1            Socket-1 state description"
+        );
+        assert_eq!(
+            s.get_info("Kitchen", "Socket-3"),
+            "Kitchen             Socket-3            Socket-2 state description"
+        );
+        assert_eq!(s.devices.len(), 2);
+    }
+
+    #[test]
+    fn test_switch() {
+        let mut s = DeviceSource::new();
+        s.add_device("Socket-1", "Guestroom", Socket::new("s1"))
+            .unwrap();
+        s.set_state("Guestroom", "Socket-1", SocketState::On)
+            .unwrap();
+        assert_eq!(s.get_state("Guestroom", "Socket-1"), Ok(SocketState::On));
+        assert!(s.set_state("Kitchen", "Socket-1", SocketState::On).is_err());
+        let status = s.get_status("Guestroom", "Socket-1").unwrap();
+        assert_eq!(status.kind(), DeviceKind::Socket);
+        assert!(status.reading.unwrap() >= 20.);
+    }
 }
@@ -24,3 +24,60 @@ guestroom           Thermometer1        Error connecting device
 "
     )
 }
+
+#[test]
+fn test_zone_report() {
+    let socket1 = Socket::new("Smart Socket v1.0");
+    let mut house = SmartHome::new("City home");
+    house.add_device("guestroom", "Thermometer1").unwrap();
+    house.add_device("bedroom", "Socket1").unwrap();
+    house.add_floor("first").unwrap();
+    house.set_room_floor("bedroom", "first").unwrap();
+    house.add_zone("upstairs").unwrap();
+    house.add_room_to_zone("upstairs", "guestroom").unwrap();
+
+    let mut info_provider1 = DeviceSource::new();
+    info_provider1
+        .add_device("Socket1", "bedroom", &socket1 as &dyn Display)
+        .unwrap();
+
+    assert_eq!(
+        house.create_floor_report("first", &info_provider1).unwrap(),
+        "Report for City home smart home, floor first
+
+bedroom             Socket1             State: off, power consumption 0.0W
+"
+    );
+    assert_eq!(
+        house
+            .create_zone_report("upstairs", &info_provider1)
+            .unwrap(),
+        "Report for City home smart home, zone upstairs
+
+guestroom           Thermometer1        Error connecting device
+"
+    );
+}
+
+#[test]
+fn test_trips_report() {
+    let mut socket1 = Socket::new("Smart Socket v1.0");
+    socket1.set_max_load(10.);
+    socket1.turn_on().unwrap_err();
+    let mut house = SmartHome::new("City home");
+    house.add_device("bedroom", "Socket1").unwrap();
+
+    let mut info_provider = DeviceSource::new();
+    info_provider
+        .add_device("Socket1", "bedroom", &socket1 as &dyn Display)
+        .unwrap();
+    let report = house.create_report(&info_provider).unwrap();
+
+    assert_eq!(
+        report,
+        "Report for City home smart home
+
+bedroom             Socket1             State: off, tripped, power consumption 0.0W
+"
+    )
+}